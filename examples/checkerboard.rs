@@ -0,0 +1,18 @@
+//! Generates a checkerboard floor and saves it as a Sponge schematic.
+
+use mc_schems::{Blocks, Schematic, SchematicFormat};
+
+fn main() {
+    let blocks = Blocks::from_fn(16, 1, 16, |x, _, z| {
+        if (x + z) % 2 == 0 {
+            "minecraft:white_concrete"
+        } else {
+            "minecraft:black_concrete"
+        }
+    });
+    let mut schem = Schematic::new(blocks).with_data_version(3700);
+    schem.paste_offset = Some((0, 0, 0));
+
+    let data = schem.serialize(SchematicFormat::Sponge(3)).unwrap();
+    std::fs::write("checkerboard.schem", data).unwrap();
+}
@@ -0,0 +1,55 @@
+//! Compares setting blocks one by one with `Blocks::set_block_at` to `Blocks::set_blocks`, both for
+//! runs of the same block and for alternating blocks. Run it with
+//! `cargo run --release --example set_blocks_bench`.
+
+use mc_schems::Blocks;
+use std::time::Instant;
+
+const SIZE: u32 = 128;
+const LAYERS: [&str; 4] = [
+    "minecraft:stone",
+    "minecraft:dirt",
+    "minecraft:grass_block[snowy=false]",
+    "minecraft:oak_planks",
+];
+
+/// Layers of 8 blocks each, so most placements repeat the previous block
+fn layers() -> impl Iterator<Item = ((u32, u32, u32), &'static str)> {
+    (0..SIZE).flat_map(|x| {
+        (0..SIZE)
+            .flat_map(move |y| (0..SIZE).map(move |z| ((x, y, z), LAYERS[(y / 8) as usize % 4])))
+    })
+}
+
+/// A checkerboard of two blocks, so every placement differs from the previous one
+fn alternating() -> impl Iterator<Item = ((u32, u32, u32), &'static str)> {
+    (0..SIZE).flat_map(|x| {
+        (0..SIZE).flat_map(move |y| {
+            (0..SIZE).map(move |z| ((x, y, z), LAYERS[((x + y + z) % 2) as usize]))
+        })
+    })
+}
+
+fn bench<I>(name: &str, placements: impl Fn() -> I)
+where
+    I: Iterator<Item = ((u32, u32, u32), &'static str)>,
+{
+    let start = Instant::now();
+    let mut naive = Blocks::new(SIZE, SIZE, SIZE, "minecraft:air");
+    for ((x, y, z), block) in placements() {
+        naive.set_block_at(x, y, z, block);
+    }
+    println!("{name}, set_block_at loop: {:?}", start.elapsed());
+
+    let start = Instant::now();
+    let mut bulk = Blocks::new(SIZE, SIZE, SIZE, "minecraft:air");
+    bulk.set_blocks(placements()).unwrap();
+    println!("{name}, set_blocks:        {:?}", start.elapsed());
+
+    assert_eq!(naive.get_block_at(5, 20, 7), bulk.get_block_at(5, 20, 7));
+}
+
+fn main() {
+    bench("layers", layers);
+    bench("alternating", alternating);
+}
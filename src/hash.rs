@@ -0,0 +1,128 @@
+use super::Schematic;
+use nbt::Value;
+use std::collections::HashMap;
+
+/// A 64-bit FNV-1a hasher. Unlike [`std::hash::DefaultHasher`], its output is guaranteed to stay
+/// the same across Rust versions and platforms.
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Fnv {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    fn write_u32(&mut self, val: u32) {
+        self.write(&val.to_le_bytes());
+    }
+
+    fn write_str(&mut self, val: &str) {
+        self.write_u32(val.len() as u32);
+        self.write(val.as_bytes());
+    }
+
+    fn write_compound(&mut self, compound: &HashMap<String, Value>) {
+        self.write_u32(compound.len() as u32);
+        let mut entries: Vec<_> = compound.iter().collect();
+        entries.sort_by_key(|(name, _)| *name);
+        for (name, val) in entries {
+            self.write_str(name);
+            self.write_value(val);
+        }
+    }
+
+    fn write_value(&mut self, value: &Value) {
+        match value {
+            Value::Byte(val) => self.write(&[1, *val as u8]),
+            Value::Short(val) => {
+                self.write(&[2]);
+                self.write(&val.to_le_bytes());
+            }
+            Value::Int(val) => {
+                self.write(&[3]);
+                self.write(&val.to_le_bytes());
+            }
+            Value::Long(val) => {
+                self.write(&[4]);
+                self.write(&val.to_le_bytes());
+            }
+            Value::Float(val) => {
+                self.write(&[5]);
+                self.write(&val.to_bits().to_le_bytes());
+            }
+            Value::Double(val) => {
+                self.write(&[6]);
+                self.write(&val.to_bits().to_le_bytes());
+            }
+            Value::ByteArray(vals) => {
+                self.write(&[7]);
+                self.write_u32(vals.len() as u32);
+                vals.iter().for_each(|val| self.write(&[*val as u8]));
+            }
+            Value::String(val) => {
+                self.write(&[8]);
+                self.write_str(val);
+            }
+            Value::List(vals) => {
+                self.write(&[9]);
+                self.write_u32(vals.len() as u32);
+                vals.iter().for_each(|val| self.write_value(val));
+            }
+            Value::Compound(compound) => {
+                self.write(&[10]);
+                self.write_compound(compound);
+            }
+            Value::IntArray(vals) => {
+                self.write(&[11]);
+                self.write_u32(vals.len() as u32);
+                vals.iter().for_each(|val| self.write(&val.to_le_bytes()));
+            }
+            Value::LongArray(vals) => {
+                self.write(&[12]);
+                self.write_u32(vals.len() as u32);
+                vals.iter().for_each(|val| self.write(&val.to_le_bytes()));
+            }
+        }
+    }
+}
+
+pub fn content_hash(schem: &Schematic) -> u64 {
+    let mut hasher = Fnv::new();
+    let (size_x, size_y, size_z) = schem.size();
+    hasher.write_u32(size_x);
+    hasher.write_u32(size_y);
+    hasher.write_u32(size_z);
+
+    // Hash the name of each palette entry up front so the palette order doesn't matter
+    let palette_hashes: Vec<u64> = schem
+        .blocks
+        .blocks_in_palette()
+        .map(|block| {
+            let mut hasher = Fnv::new();
+            hasher.write_str(block);
+            hasher.0
+        })
+        .collect();
+    for &id in &schem.blocks.indices {
+        hasher.write(&palette_hashes[id as usize].to_le_bytes());
+    }
+
+    let mut block_entities: Vec<_> = schem.block_entities.iter().collect();
+    block_entities.sort_by_key(|(pos, _)| **pos);
+    hasher.write_u32(block_entities.len() as u32);
+    for ((x, y, z), block_entity) in block_entities {
+        hasher.write_u32(*x);
+        hasher.write_u32(*y);
+        hasher.write_u32(*z);
+        hasher.write_str(&block_entity.id);
+        hasher.write_compound(&block_entity.data);
+    }
+
+    hasher.0
+}
@@ -0,0 +1,63 @@
+//! Mapping block states to the items needed to place them.
+
+/// Blocks that have no item form
+const NO_ITEM: [&str; 12] = [
+    "minecraft:air",
+    "minecraft:cave_air",
+    "minecraft:void_air",
+    "minecraft:water",
+    "minecraft:lava",
+    "minecraft:fire",
+    "minecraft:soul_fire",
+    "minecraft:bubble_column",
+    "minecraft:nether_portal",
+    "minecraft:end_portal",
+    "minecraft:end_gateway",
+    "minecraft:piston_head",
+];
+
+fn property<'a>(properties: &'a str, name: &str) -> Option<&'a str> {
+    properties
+        .split(',')
+        .filter_map(|property| property.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+/// Get the item needed to place a block state and how many of it are needed, or `None` if the
+/// block has no item form or is the second part of a multi-block structure.
+pub(crate) fn block_item(block: &str) -> Option<(String, u64)> {
+    let (name, properties) = match block.split_once('[') {
+        Some((name, properties)) => (name, properties.trim_end_matches(']')),
+        None => (block, ""),
+    };
+    if NO_ITEM.contains(&name) {
+        return None;
+    }
+    // Only count the lower half of doors and tall plants and the foot of beds
+    if property(properties, "half") == Some("upper") || property(properties, "part") == Some("head")
+    {
+        return None;
+    }
+
+    let count = if name.ends_with("_slab") && property(properties, "type") == Some("double") {
+        2
+    } else {
+        // Blocks that stack several items in one block space
+        ["candles", "pickles", "layers", "eggs", "flower_amount"]
+            .into_iter()
+            .find_map(|name| property(properties, name)?.parse().ok())
+            .unwrap_or(1)
+    };
+
+    let item = match name {
+        "minecraft:redstone_wire" => "minecraft:redstone".to_owned(),
+        "minecraft:tripwire" => "minecraft:string".to_owned(),
+        "minecraft:wall_torch" => "minecraft:torch".to_owned(),
+        "minecraft:soul_wall_torch" => "minecraft:soul_torch".to_owned(),
+        "minecraft:redstone_wall_torch" => "minecraft:redstone_torch".to_owned(),
+        // Wall signs, banners, heads, and fans are placed with the same item as the standing block
+        _ => name.replacen("_wall_", "_", 1),
+    };
+    Some((item, count))
+}
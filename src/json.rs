@@ -0,0 +1,42 @@
+use super::Schematic;
+use std::fmt::Write;
+
+/// Write a quoted string, escaping quotes, backslashes, and control characters. The escapes are
+/// the same in SNBT, so this is used for both.
+pub(crate) fn write_string(out: &mut String, val: &str) {
+    out.push('"');
+    for c in val.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+pub fn to_json(schem: &Schematic) -> String {
+    let blocks = &schem.blocks;
+    let (size_x, size_y, size_z) = blocks.size();
+    let mut out = String::new();
+    write!(out, "{{\"size\":[{size_x},{size_y},{size_z}],\"palette\":[").unwrap();
+    for (i, block) in blocks.palette.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write_string(&mut out, block);
+    }
+    out.push_str("],\"blocks\":[");
+    for (i, id) in blocks.indices.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write!(out, "{id}").unwrap();
+    }
+    out.push_str("]}");
+    out
+}
@@ -0,0 +1,792 @@
+//! Mapping numeric block ids from before Minecraft 1.13 to modern block states.
+
+/// Legacy ids and data values along with the block states they were turned into by the 1.13
+/// flattening. Only common building blocks are covered; blocks whose state depends on their
+/// neighbours or on block entities, such as doors, beds, and redstone components, are missing.
+const LEGACY_BLOCKS: [(u16, u8, &str); 574] = [
+    (0, 0, "minecraft:air"),
+    (1, 0, "minecraft:stone"),
+    (1, 1, "minecraft:granite"),
+    (1, 2, "minecraft:polished_granite"),
+    (1, 3, "minecraft:diorite"),
+    (1, 4, "minecraft:polished_diorite"),
+    (1, 5, "minecraft:andesite"),
+    (1, 6, "minecraft:polished_andesite"),
+    (2, 0, "minecraft:grass_block[snowy=false]"),
+    (3, 0, "minecraft:dirt"),
+    (3, 1, "minecraft:coarse_dirt"),
+    (3, 2, "minecraft:podzol[snowy=false]"),
+    (4, 0, "minecraft:cobblestone"),
+    (5, 0, "minecraft:oak_planks"),
+    (5, 1, "minecraft:spruce_planks"),
+    (5, 2, "minecraft:birch_planks"),
+    (5, 3, "minecraft:jungle_planks"),
+    (5, 4, "minecraft:acacia_planks"),
+    (5, 5, "minecraft:dark_oak_planks"),
+    (6, 0, "minecraft:oak_sapling[stage=0]"),
+    (6, 1, "minecraft:spruce_sapling[stage=0]"),
+    (6, 2, "minecraft:birch_sapling[stage=0]"),
+    (6, 3, "minecraft:jungle_sapling[stage=0]"),
+    (6, 4, "minecraft:acacia_sapling[stage=0]"),
+    (6, 5, "minecraft:dark_oak_sapling[stage=0]"),
+    (6, 8, "minecraft:oak_sapling[stage=1]"),
+    (6, 9, "minecraft:spruce_sapling[stage=1]"),
+    (6, 10, "minecraft:birch_sapling[stage=1]"),
+    (6, 11, "minecraft:jungle_sapling[stage=1]"),
+    (6, 12, "minecraft:acacia_sapling[stage=1]"),
+    (6, 13, "minecraft:dark_oak_sapling[stage=1]"),
+    (7, 0, "minecraft:bedrock"),
+    // Flowing liquids upgrade to the same state as still ones
+    (9, 0, "minecraft:water[level=0]"),
+    (8, 0, "minecraft:water[level=0]"),
+    (11, 0, "minecraft:lava[level=0]"),
+    (10, 0, "minecraft:lava[level=0]"),
+    (12, 0, "minecraft:sand"),
+    (12, 1, "minecraft:red_sand"),
+    (13, 0, "minecraft:gravel"),
+    (14, 0, "minecraft:gold_ore"),
+    (15, 0, "minecraft:iron_ore"),
+    (16, 0, "minecraft:coal_ore"),
+    (17, 0, "minecraft:oak_log[axis=y]"),
+    (17, 1, "minecraft:spruce_log[axis=y]"),
+    (17, 2, "minecraft:birch_log[axis=y]"),
+    (17, 3, "minecraft:jungle_log[axis=y]"),
+    (17, 4, "minecraft:oak_log[axis=x]"),
+    (17, 5, "minecraft:spruce_log[axis=x]"),
+    (17, 6, "minecraft:birch_log[axis=x]"),
+    (17, 7, "minecraft:jungle_log[axis=x]"),
+    (17, 8, "minecraft:oak_log[axis=z]"),
+    (17, 9, "minecraft:spruce_log[axis=z]"),
+    (17, 10, "minecraft:birch_log[axis=z]"),
+    (17, 11, "minecraft:jungle_log[axis=z]"),
+    (17, 12, "minecraft:oak_wood[axis=y]"),
+    (17, 13, "minecraft:spruce_wood[axis=y]"),
+    (17, 14, "minecraft:birch_wood[axis=y]"),
+    (17, 15, "minecraft:jungle_wood[axis=y]"),
+    (18, 0, "minecraft:oak_leaves[persistent=false]"),
+    (18, 1, "minecraft:spruce_leaves[persistent=false]"),
+    (18, 2, "minecraft:birch_leaves[persistent=false]"),
+    (18, 3, "minecraft:jungle_leaves[persistent=false]"),
+    (18, 4, "minecraft:oak_leaves[persistent=true]"),
+    (18, 5, "minecraft:spruce_leaves[persistent=true]"),
+    (18, 6, "minecraft:birch_leaves[persistent=true]"),
+    (18, 7, "minecraft:jungle_leaves[persistent=true]"),
+    (19, 0, "minecraft:sponge"),
+    (19, 1, "minecraft:wet_sponge"),
+    (20, 0, "minecraft:glass"),
+    (21, 0, "minecraft:lapis_ore"),
+    (22, 0, "minecraft:lapis_block"),
+    (23, 0, "minecraft:dispenser[facing=down,triggered=false]"),
+    (23, 1, "minecraft:dispenser[facing=up,triggered=false]"),
+    (23, 2, "minecraft:dispenser[facing=north,triggered=false]"),
+    (23, 3, "minecraft:dispenser[facing=south,triggered=false]"),
+    (23, 4, "minecraft:dispenser[facing=west,triggered=false]"),
+    (23, 5, "minecraft:dispenser[facing=east,triggered=false]"),
+    (24, 0, "minecraft:sandstone"),
+    (24, 1, "minecraft:chiseled_sandstone"),
+    (24, 2, "minecraft:cut_sandstone"),
+    (25, 0, "minecraft:note_block"),
+    (30, 0, "minecraft:cobweb"),
+    (31, 0, "minecraft:dead_bush"),
+    (31, 1, "minecraft:grass"),
+    (31, 2, "minecraft:fern"),
+    (32, 0, "minecraft:dead_bush"),
+    (35, 0, "minecraft:white_wool"),
+    (35, 1, "minecraft:orange_wool"),
+    (35, 2, "minecraft:magenta_wool"),
+    (35, 3, "minecraft:light_blue_wool"),
+    (35, 4, "minecraft:yellow_wool"),
+    (35, 5, "minecraft:lime_wool"),
+    (35, 6, "minecraft:pink_wool"),
+    (35, 7, "minecraft:gray_wool"),
+    (35, 8, "minecraft:light_gray_wool"),
+    (35, 9, "minecraft:cyan_wool"),
+    (35, 10, "minecraft:purple_wool"),
+    (35, 11, "minecraft:blue_wool"),
+    (35, 12, "minecraft:brown_wool"),
+    (35, 13, "minecraft:green_wool"),
+    (35, 14, "minecraft:red_wool"),
+    (35, 15, "minecraft:black_wool"),
+    (37, 0, "minecraft:dandelion"),
+    (38, 0, "minecraft:poppy"),
+    (38, 1, "minecraft:blue_orchid"),
+    (38, 2, "minecraft:allium"),
+    (38, 3, "minecraft:azure_bluet"),
+    (38, 4, "minecraft:red_tulip"),
+    (38, 5, "minecraft:orange_tulip"),
+    (38, 6, "minecraft:white_tulip"),
+    (38, 7, "minecraft:pink_tulip"),
+    (38, 8, "minecraft:oxeye_daisy"),
+    (39, 0, "minecraft:brown_mushroom"),
+    (40, 0, "minecraft:red_mushroom"),
+    (41, 0, "minecraft:gold_block"),
+    (42, 0, "minecraft:iron_block"),
+    // Stone slabs (data value 0) were renamed in 1.14, so they are left out
+    (43, 1, "minecraft:sandstone_slab[type=double]"),
+    (43, 3, "minecraft:cobblestone_slab[type=double]"),
+    (43, 4, "minecraft:brick_slab[type=double]"),
+    (43, 5, "minecraft:stone_brick_slab[type=double]"),
+    (43, 6, "minecraft:nether_brick_slab[type=double]"),
+    (43, 7, "minecraft:quartz_slab[type=double]"),
+    (44, 1, "minecraft:sandstone_slab[type=bottom]"),
+    (44, 3, "minecraft:cobblestone_slab[type=bottom]"),
+    (44, 4, "minecraft:brick_slab[type=bottom]"),
+    (44, 5, "minecraft:stone_brick_slab[type=bottom]"),
+    (44, 6, "minecraft:nether_brick_slab[type=bottom]"),
+    (44, 7, "minecraft:quartz_slab[type=bottom]"),
+    (44, 9, "minecraft:sandstone_slab[type=top]"),
+    (44, 11, "minecraft:cobblestone_slab[type=top]"),
+    (44, 12, "minecraft:brick_slab[type=top]"),
+    (44, 13, "minecraft:stone_brick_slab[type=top]"),
+    (44, 14, "minecraft:nether_brick_slab[type=top]"),
+    (44, 15, "minecraft:quartz_slab[type=top]"),
+    (45, 0, "minecraft:bricks"),
+    (46, 0, "minecraft:tnt[unstable=false]"),
+    (47, 0, "minecraft:bookshelf"),
+    (48, 0, "minecraft:mossy_cobblestone"),
+    (49, 0, "minecraft:obsidian"),
+    (50, 5, "minecraft:torch"),
+    (50, 1, "minecraft:wall_torch[facing=east]"),
+    (50, 2, "minecraft:wall_torch[facing=west]"),
+    (50, 3, "minecraft:wall_torch[facing=south]"),
+    (50, 4, "minecraft:wall_torch[facing=north]"),
+    (52, 0, "minecraft:spawner"),
+    (53, 0, "minecraft:oak_stairs[facing=east,half=bottom]"),
+    (53, 1, "minecraft:oak_stairs[facing=west,half=bottom]"),
+    (53, 2, "minecraft:oak_stairs[facing=south,half=bottom]"),
+    (53, 3, "minecraft:oak_stairs[facing=north,half=bottom]"),
+    (53, 4, "minecraft:oak_stairs[facing=east,half=top]"),
+    (53, 5, "minecraft:oak_stairs[facing=west,half=top]"),
+    (53, 6, "minecraft:oak_stairs[facing=south,half=top]"),
+    (53, 7, "minecraft:oak_stairs[facing=north,half=top]"),
+    (54, 2, "minecraft:chest[facing=north,type=single]"),
+    (54, 3, "minecraft:chest[facing=south,type=single]"),
+    (54, 4, "minecraft:chest[facing=west,type=single]"),
+    (54, 5, "minecraft:chest[facing=east,type=single]"),
+    (56, 0, "minecraft:diamond_ore"),
+    (57, 0, "minecraft:diamond_block"),
+    (58, 0, "minecraft:crafting_table"),
+    (60, 0, "minecraft:farmland[moisture=0]"),
+    (60, 1, "minecraft:farmland[moisture=1]"),
+    (60, 2, "minecraft:farmland[moisture=2]"),
+    (60, 3, "minecraft:farmland[moisture=3]"),
+    (60, 4, "minecraft:farmland[moisture=4]"),
+    (60, 5, "minecraft:farmland[moisture=5]"),
+    (60, 6, "minecraft:farmland[moisture=6]"),
+    (60, 7, "minecraft:farmland[moisture=7]"),
+    (61, 2, "minecraft:furnace[facing=north,lit=false]"),
+    (61, 3, "minecraft:furnace[facing=south,lit=false]"),
+    (61, 4, "minecraft:furnace[facing=west,lit=false]"),
+    (61, 5, "minecraft:furnace[facing=east,lit=false]"),
+    (62, 2, "minecraft:furnace[facing=north,lit=true]"),
+    (62, 3, "minecraft:furnace[facing=south,lit=true]"),
+    (62, 4, "minecraft:furnace[facing=west,lit=true]"),
+    (62, 5, "minecraft:furnace[facing=east,lit=true]"),
+    (65, 2, "minecraft:ladder[facing=north]"),
+    (65, 3, "minecraft:ladder[facing=south]"),
+    (65, 4, "minecraft:ladder[facing=west]"),
+    (65, 5, "minecraft:ladder[facing=east]"),
+    (66, 0, "minecraft:rail[shape=north_south]"),
+    (66, 1, "minecraft:rail[shape=east_west]"),
+    (
+        67,
+        0,
+        "minecraft:cobblestone_stairs[facing=east,half=bottom]",
+    ),
+    (
+        67,
+        1,
+        "minecraft:cobblestone_stairs[facing=west,half=bottom]",
+    ),
+    (
+        67,
+        2,
+        "minecraft:cobblestone_stairs[facing=south,half=bottom]",
+    ),
+    (
+        67,
+        3,
+        "minecraft:cobblestone_stairs[facing=north,half=bottom]",
+    ),
+    (67, 4, "minecraft:cobblestone_stairs[facing=east,half=top]"),
+    (67, 5, "minecraft:cobblestone_stairs[facing=west,half=top]"),
+    (67, 6, "minecraft:cobblestone_stairs[facing=south,half=top]"),
+    (67, 7, "minecraft:cobblestone_stairs[facing=north,half=top]"),
+    (73, 0, "minecraft:redstone_ore[lit=false]"),
+    (74, 0, "minecraft:redstone_ore[lit=true]"),
+    (78, 0, "minecraft:snow[layers=1]"),
+    (78, 1, "minecraft:snow[layers=2]"),
+    (78, 2, "minecraft:snow[layers=3]"),
+    (78, 3, "minecraft:snow[layers=4]"),
+    (78, 4, "minecraft:snow[layers=5]"),
+    (78, 5, "minecraft:snow[layers=6]"),
+    (78, 6, "minecraft:snow[layers=7]"),
+    (78, 7, "minecraft:snow[layers=8]"),
+    (79, 0, "minecraft:ice"),
+    (80, 0, "minecraft:snow_block"),
+    (81, 0, "minecraft:cactus[age=0]"),
+    (82, 0, "minecraft:clay"),
+    (84, 0, "minecraft:jukebox[has_record=false]"),
+    (85, 0, "minecraft:oak_fence"),
+    (86, 0, "minecraft:carved_pumpkin[facing=south]"),
+    (86, 1, "minecraft:carved_pumpkin[facing=west]"),
+    (86, 2, "minecraft:carved_pumpkin[facing=north]"),
+    (86, 3, "minecraft:carved_pumpkin[facing=east]"),
+    (87, 0, "minecraft:netherrack"),
+    (88, 0, "minecraft:soul_sand"),
+    (89, 0, "minecraft:glowstone"),
+    (91, 0, "minecraft:jack_o_lantern[facing=south]"),
+    (91, 1, "minecraft:jack_o_lantern[facing=west]"),
+    (91, 2, "minecraft:jack_o_lantern[facing=north]"),
+    (91, 3, "minecraft:jack_o_lantern[facing=east]"),
+    (95, 0, "minecraft:white_stained_glass"),
+    (95, 1, "minecraft:orange_stained_glass"),
+    (95, 2, "minecraft:magenta_stained_glass"),
+    (95, 3, "minecraft:light_blue_stained_glass"),
+    (95, 4, "minecraft:yellow_stained_glass"),
+    (95, 5, "minecraft:lime_stained_glass"),
+    (95, 6, "minecraft:pink_stained_glass"),
+    (95, 7, "minecraft:gray_stained_glass"),
+    (95, 8, "minecraft:light_gray_stained_glass"),
+    (95, 9, "minecraft:cyan_stained_glass"),
+    (95, 10, "minecraft:purple_stained_glass"),
+    (95, 11, "minecraft:blue_stained_glass"),
+    (95, 12, "minecraft:brown_stained_glass"),
+    (95, 13, "minecraft:green_stained_glass"),
+    (95, 14, "minecraft:red_stained_glass"),
+    (95, 15, "minecraft:black_stained_glass"),
+    (97, 0, "minecraft:infested_stone"),
+    (97, 1, "minecraft:infested_cobblestone"),
+    (97, 2, "minecraft:infested_stone_bricks"),
+    (97, 3, "minecraft:infested_mossy_stone_bricks"),
+    (97, 4, "minecraft:infested_cracked_stone_bricks"),
+    (97, 5, "minecraft:infested_chiseled_stone_bricks"),
+    (98, 0, "minecraft:stone_bricks"),
+    (98, 1, "minecraft:mossy_stone_bricks"),
+    (98, 2, "minecraft:cracked_stone_bricks"),
+    (98, 3, "minecraft:chiseled_stone_bricks"),
+    (101, 0, "minecraft:iron_bars"),
+    (102, 0, "minecraft:glass_pane"),
+    (103, 0, "minecraft:melon"),
+    (108, 0, "minecraft:brick_stairs[facing=east,half=bottom]"),
+    (108, 1, "minecraft:brick_stairs[facing=west,half=bottom]"),
+    (108, 2, "minecraft:brick_stairs[facing=south,half=bottom]"),
+    (108, 3, "minecraft:brick_stairs[facing=north,half=bottom]"),
+    (108, 4, "minecraft:brick_stairs[facing=east,half=top]"),
+    (108, 5, "minecraft:brick_stairs[facing=west,half=top]"),
+    (108, 6, "minecraft:brick_stairs[facing=south,half=top]"),
+    (108, 7, "minecraft:brick_stairs[facing=north,half=top]"),
+    (
+        109,
+        0,
+        "minecraft:stone_brick_stairs[facing=east,half=bottom]",
+    ),
+    (
+        109,
+        1,
+        "minecraft:stone_brick_stairs[facing=west,half=bottom]",
+    ),
+    (
+        109,
+        2,
+        "minecraft:stone_brick_stairs[facing=south,half=bottom]",
+    ),
+    (
+        109,
+        3,
+        "minecraft:stone_brick_stairs[facing=north,half=bottom]",
+    ),
+    (109, 4, "minecraft:stone_brick_stairs[facing=east,half=top]"),
+    (109, 5, "minecraft:stone_brick_stairs[facing=west,half=top]"),
+    (
+        109,
+        6,
+        "minecraft:stone_brick_stairs[facing=south,half=top]",
+    ),
+    (
+        109,
+        7,
+        "minecraft:stone_brick_stairs[facing=north,half=top]",
+    ),
+    (110, 0, "minecraft:mycelium[snowy=false]"),
+    (111, 0, "minecraft:lily_pad"),
+    (112, 0, "minecraft:nether_bricks"),
+    (113, 0, "minecraft:nether_brick_fence"),
+    (
+        114,
+        0,
+        "minecraft:nether_brick_stairs[facing=east,half=bottom]",
+    ),
+    (
+        114,
+        1,
+        "minecraft:nether_brick_stairs[facing=west,half=bottom]",
+    ),
+    (
+        114,
+        2,
+        "minecraft:nether_brick_stairs[facing=south,half=bottom]",
+    ),
+    (
+        114,
+        3,
+        "minecraft:nether_brick_stairs[facing=north,half=bottom]",
+    ),
+    (
+        114,
+        4,
+        "minecraft:nether_brick_stairs[facing=east,half=top]",
+    ),
+    (
+        114,
+        5,
+        "minecraft:nether_brick_stairs[facing=west,half=top]",
+    ),
+    (
+        114,
+        6,
+        "minecraft:nether_brick_stairs[facing=south,half=top]",
+    ),
+    (
+        114,
+        7,
+        "minecraft:nether_brick_stairs[facing=north,half=top]",
+    ),
+    (116, 0, "minecraft:enchanting_table"),
+    (121, 0, "minecraft:end_stone"),
+    (122, 0, "minecraft:dragon_egg"),
+    (123, 0, "minecraft:redstone_lamp[lit=false]"),
+    (124, 0, "minecraft:redstone_lamp[lit=true]"),
+    (125, 0, "minecraft:oak_slab[type=double]"),
+    (125, 1, "minecraft:spruce_slab[type=double]"),
+    (125, 2, "minecraft:birch_slab[type=double]"),
+    (125, 3, "minecraft:jungle_slab[type=double]"),
+    (125, 4, "minecraft:acacia_slab[type=double]"),
+    (125, 5, "minecraft:dark_oak_slab[type=double]"),
+    (126, 0, "minecraft:oak_slab[type=bottom]"),
+    (126, 1, "minecraft:spruce_slab[type=bottom]"),
+    (126, 2, "minecraft:birch_slab[type=bottom]"),
+    (126, 3, "minecraft:jungle_slab[type=bottom]"),
+    (126, 4, "minecraft:acacia_slab[type=bottom]"),
+    (126, 5, "minecraft:dark_oak_slab[type=bottom]"),
+    (126, 8, "minecraft:oak_slab[type=top]"),
+    (126, 9, "minecraft:spruce_slab[type=top]"),
+    (126, 10, "minecraft:birch_slab[type=top]"),
+    (126, 11, "minecraft:jungle_slab[type=top]"),
+    (126, 12, "minecraft:acacia_slab[type=top]"),
+    (126, 13, "minecraft:dark_oak_slab[type=top]"),
+    (
+        128,
+        0,
+        "minecraft:sandstone_stairs[facing=east,half=bottom]",
+    ),
+    (
+        128,
+        1,
+        "minecraft:sandstone_stairs[facing=west,half=bottom]",
+    ),
+    (
+        128,
+        2,
+        "minecraft:sandstone_stairs[facing=south,half=bottom]",
+    ),
+    (
+        128,
+        3,
+        "minecraft:sandstone_stairs[facing=north,half=bottom]",
+    ),
+    (128, 4, "minecraft:sandstone_stairs[facing=east,half=top]"),
+    (128, 5, "minecraft:sandstone_stairs[facing=west,half=top]"),
+    (128, 6, "minecraft:sandstone_stairs[facing=south,half=top]"),
+    (128, 7, "minecraft:sandstone_stairs[facing=north,half=top]"),
+    (129, 0, "minecraft:emerald_ore"),
+    (133, 0, "minecraft:emerald_block"),
+    (134, 0, "minecraft:spruce_stairs[facing=east,half=bottom]"),
+    (134, 1, "minecraft:spruce_stairs[facing=west,half=bottom]"),
+    (134, 2, "minecraft:spruce_stairs[facing=south,half=bottom]"),
+    (134, 3, "minecraft:spruce_stairs[facing=north,half=bottom]"),
+    (134, 4, "minecraft:spruce_stairs[facing=east,half=top]"),
+    (134, 5, "minecraft:spruce_stairs[facing=west,half=top]"),
+    (134, 6, "minecraft:spruce_stairs[facing=south,half=top]"),
+    (134, 7, "minecraft:spruce_stairs[facing=north,half=top]"),
+    (135, 0, "minecraft:birch_stairs[facing=east,half=bottom]"),
+    (135, 1, "minecraft:birch_stairs[facing=west,half=bottom]"),
+    (135, 2, "minecraft:birch_stairs[facing=south,half=bottom]"),
+    (135, 3, "minecraft:birch_stairs[facing=north,half=bottom]"),
+    (135, 4, "minecraft:birch_stairs[facing=east,half=top]"),
+    (135, 5, "minecraft:birch_stairs[facing=west,half=top]"),
+    (135, 6, "minecraft:birch_stairs[facing=south,half=top]"),
+    (135, 7, "minecraft:birch_stairs[facing=north,half=top]"),
+    (136, 0, "minecraft:jungle_stairs[facing=east,half=bottom]"),
+    (136, 1, "minecraft:jungle_stairs[facing=west,half=bottom]"),
+    (136, 2, "minecraft:jungle_stairs[facing=south,half=bottom]"),
+    (136, 3, "minecraft:jungle_stairs[facing=north,half=bottom]"),
+    (136, 4, "minecraft:jungle_stairs[facing=east,half=top]"),
+    (136, 5, "minecraft:jungle_stairs[facing=west,half=top]"),
+    (136, 6, "minecraft:jungle_stairs[facing=south,half=top]"),
+    (136, 7, "minecraft:jungle_stairs[facing=north,half=top]"),
+    (138, 0, "minecraft:beacon"),
+    (139, 0, "minecraft:cobblestone_wall"),
+    (139, 1, "minecraft:mossy_cobblestone_wall"),
+    (152, 0, "minecraft:redstone_block"),
+    (153, 0, "minecraft:nether_quartz_ore"),
+    (155, 0, "minecraft:quartz_block"),
+    (155, 1, "minecraft:chiseled_quartz_block"),
+    (155, 2, "minecraft:quartz_pillar[axis=y]"),
+    (155, 3, "minecraft:quartz_pillar[axis=x]"),
+    (155, 4, "minecraft:quartz_pillar[axis=z]"),
+    (156, 0, "minecraft:quartz_stairs[facing=east,half=bottom]"),
+    (156, 1, "minecraft:quartz_stairs[facing=west,half=bottom]"),
+    (156, 2, "minecraft:quartz_stairs[facing=south,half=bottom]"),
+    (156, 3, "minecraft:quartz_stairs[facing=north,half=bottom]"),
+    (156, 4, "minecraft:quartz_stairs[facing=east,half=top]"),
+    (156, 5, "minecraft:quartz_stairs[facing=west,half=top]"),
+    (156, 6, "minecraft:quartz_stairs[facing=south,half=top]"),
+    (156, 7, "minecraft:quartz_stairs[facing=north,half=top]"),
+    (159, 0, "minecraft:white_terracotta"),
+    (159, 1, "minecraft:orange_terracotta"),
+    (159, 2, "minecraft:magenta_terracotta"),
+    (159, 3, "minecraft:light_blue_terracotta"),
+    (159, 4, "minecraft:yellow_terracotta"),
+    (159, 5, "minecraft:lime_terracotta"),
+    (159, 6, "minecraft:pink_terracotta"),
+    (159, 7, "minecraft:gray_terracotta"),
+    (159, 8, "minecraft:light_gray_terracotta"),
+    (159, 9, "minecraft:cyan_terracotta"),
+    (159, 10, "minecraft:purple_terracotta"),
+    (159, 11, "minecraft:blue_terracotta"),
+    (159, 12, "minecraft:brown_terracotta"),
+    (159, 13, "minecraft:green_terracotta"),
+    (159, 14, "minecraft:red_terracotta"),
+    (159, 15, "minecraft:black_terracotta"),
+    (160, 0, "minecraft:white_stained_glass_pane"),
+    (160, 1, "minecraft:orange_stained_glass_pane"),
+    (160, 2, "minecraft:magenta_stained_glass_pane"),
+    (160, 3, "minecraft:light_blue_stained_glass_pane"),
+    (160, 4, "minecraft:yellow_stained_glass_pane"),
+    (160, 5, "minecraft:lime_stained_glass_pane"),
+    (160, 6, "minecraft:pink_stained_glass_pane"),
+    (160, 7, "minecraft:gray_stained_glass_pane"),
+    (160, 8, "minecraft:light_gray_stained_glass_pane"),
+    (160, 9, "minecraft:cyan_stained_glass_pane"),
+    (160, 10, "minecraft:purple_stained_glass_pane"),
+    (160, 11, "minecraft:blue_stained_glass_pane"),
+    (160, 12, "minecraft:brown_stained_glass_pane"),
+    (160, 13, "minecraft:green_stained_glass_pane"),
+    (160, 14, "minecraft:red_stained_glass_pane"),
+    (160, 15, "minecraft:black_stained_glass_pane"),
+    (161, 0, "minecraft:acacia_leaves[persistent=false]"),
+    (161, 1, "minecraft:dark_oak_leaves[persistent=false]"),
+    (161, 4, "minecraft:acacia_leaves[persistent=true]"),
+    (161, 5, "minecraft:dark_oak_leaves[persistent=true]"),
+    (162, 0, "minecraft:acacia_log[axis=y]"),
+    (162, 1, "minecraft:dark_oak_log[axis=y]"),
+    (162, 4, "minecraft:acacia_log[axis=x]"),
+    (162, 5, "minecraft:dark_oak_log[axis=x]"),
+    (162, 8, "minecraft:acacia_log[axis=z]"),
+    (162, 9, "minecraft:dark_oak_log[axis=z]"),
+    (162, 12, "minecraft:acacia_wood[axis=y]"),
+    (162, 13, "minecraft:dark_oak_wood[axis=y]"),
+    (163, 0, "minecraft:acacia_stairs[facing=east,half=bottom]"),
+    (163, 1, "minecraft:acacia_stairs[facing=west,half=bottom]"),
+    (163, 2, "minecraft:acacia_stairs[facing=south,half=bottom]"),
+    (163, 3, "minecraft:acacia_stairs[facing=north,half=bottom]"),
+    (163, 4, "minecraft:acacia_stairs[facing=east,half=top]"),
+    (163, 5, "minecraft:acacia_stairs[facing=west,half=top]"),
+    (163, 6, "minecraft:acacia_stairs[facing=south,half=top]"),
+    (163, 7, "minecraft:acacia_stairs[facing=north,half=top]"),
+    (164, 0, "minecraft:dark_oak_stairs[facing=east,half=bottom]"),
+    (164, 1, "minecraft:dark_oak_stairs[facing=west,half=bottom]"),
+    (
+        164,
+        2,
+        "minecraft:dark_oak_stairs[facing=south,half=bottom]",
+    ),
+    (
+        164,
+        3,
+        "minecraft:dark_oak_stairs[facing=north,half=bottom]",
+    ),
+    (164, 4, "minecraft:dark_oak_stairs[facing=east,half=top]"),
+    (164, 5, "minecraft:dark_oak_stairs[facing=west,half=top]"),
+    (164, 6, "minecraft:dark_oak_stairs[facing=south,half=top]"),
+    (164, 7, "minecraft:dark_oak_stairs[facing=north,half=top]"),
+    (165, 0, "minecraft:slime_block"),
+    (166, 0, "minecraft:barrier"),
+    (168, 0, "minecraft:prismarine"),
+    (168, 1, "minecraft:prismarine_bricks"),
+    (168, 2, "minecraft:dark_prismarine"),
+    (169, 0, "minecraft:sea_lantern"),
+    (170, 0, "minecraft:hay_block[axis=y]"),
+    (170, 4, "minecraft:hay_block[axis=x]"),
+    (170, 8, "minecraft:hay_block[axis=z]"),
+    (171, 0, "minecraft:white_carpet"),
+    (171, 1, "minecraft:orange_carpet"),
+    (171, 2, "minecraft:magenta_carpet"),
+    (171, 3, "minecraft:light_blue_carpet"),
+    (171, 4, "minecraft:yellow_carpet"),
+    (171, 5, "minecraft:lime_carpet"),
+    (171, 6, "minecraft:pink_carpet"),
+    (171, 7, "minecraft:gray_carpet"),
+    (171, 8, "minecraft:light_gray_carpet"),
+    (171, 9, "minecraft:cyan_carpet"),
+    (171, 10, "minecraft:purple_carpet"),
+    (171, 11, "minecraft:blue_carpet"),
+    (171, 12, "minecraft:brown_carpet"),
+    (171, 13, "minecraft:green_carpet"),
+    (171, 14, "minecraft:red_carpet"),
+    (171, 15, "minecraft:black_carpet"),
+    (172, 0, "minecraft:terracotta"),
+    (173, 0, "minecraft:coal_block"),
+    (174, 0, "minecraft:packed_ice"),
+    (179, 0, "minecraft:red_sandstone"),
+    (179, 1, "minecraft:chiseled_red_sandstone"),
+    (179, 2, "minecraft:cut_red_sandstone"),
+    (
+        180,
+        0,
+        "minecraft:red_sandstone_stairs[facing=east,half=bottom]",
+    ),
+    (
+        180,
+        1,
+        "minecraft:red_sandstone_stairs[facing=west,half=bottom]",
+    ),
+    (
+        180,
+        2,
+        "minecraft:red_sandstone_stairs[facing=south,half=bottom]",
+    ),
+    (
+        180,
+        3,
+        "minecraft:red_sandstone_stairs[facing=north,half=bottom]",
+    ),
+    (
+        180,
+        4,
+        "minecraft:red_sandstone_stairs[facing=east,half=top]",
+    ),
+    (
+        180,
+        5,
+        "minecraft:red_sandstone_stairs[facing=west,half=top]",
+    ),
+    (
+        180,
+        6,
+        "minecraft:red_sandstone_stairs[facing=south,half=top]",
+    ),
+    (
+        180,
+        7,
+        "minecraft:red_sandstone_stairs[facing=north,half=top]",
+    ),
+    (181, 0, "minecraft:red_sandstone_slab[type=double]"),
+    (182, 0, "minecraft:red_sandstone_slab[type=bottom]"),
+    (182, 8, "minecraft:red_sandstone_slab[type=top]"),
+    (188, 0, "minecraft:spruce_fence"),
+    (189, 0, "minecraft:birch_fence"),
+    (190, 0, "minecraft:jungle_fence"),
+    (191, 0, "minecraft:dark_oak_fence"),
+    (192, 0, "minecraft:acacia_fence"),
+    (201, 0, "minecraft:purpur_block"),
+    (202, 0, "minecraft:purpur_pillar[axis=y]"),
+    (202, 4, "minecraft:purpur_pillar[axis=x]"),
+    (202, 8, "minecraft:purpur_pillar[axis=z]"),
+    (203, 0, "minecraft:purpur_stairs[facing=east,half=bottom]"),
+    (203, 1, "minecraft:purpur_stairs[facing=west,half=bottom]"),
+    (203, 2, "minecraft:purpur_stairs[facing=south,half=bottom]"),
+    (203, 3, "minecraft:purpur_stairs[facing=north,half=bottom]"),
+    (203, 4, "minecraft:purpur_stairs[facing=east,half=top]"),
+    (203, 5, "minecraft:purpur_stairs[facing=west,half=top]"),
+    (203, 6, "minecraft:purpur_stairs[facing=south,half=top]"),
+    (203, 7, "minecraft:purpur_stairs[facing=north,half=top]"),
+    (204, 0, "minecraft:purpur_slab[type=double]"),
+    (205, 0, "minecraft:purpur_slab[type=bottom]"),
+    (205, 8, "minecraft:purpur_slab[type=top]"),
+    (206, 0, "minecraft:end_stone_bricks"),
+    (213, 0, "minecraft:magma_block"),
+    (214, 0, "minecraft:nether_wart_block"),
+    (215, 0, "minecraft:red_nether_bricks"),
+    (216, 0, "minecraft:bone_block[axis=y]"),
+    (216, 4, "minecraft:bone_block[axis=x]"),
+    (216, 8, "minecraft:bone_block[axis=z]"),
+    (235, 0, "minecraft:white_glazed_terracotta[facing=south]"),
+    (235, 1, "minecraft:white_glazed_terracotta[facing=west]"),
+    (235, 2, "minecraft:white_glazed_terracotta[facing=north]"),
+    (235, 3, "minecraft:white_glazed_terracotta[facing=east]"),
+    (236, 0, "minecraft:orange_glazed_terracotta[facing=south]"),
+    (236, 1, "minecraft:orange_glazed_terracotta[facing=west]"),
+    (236, 2, "minecraft:orange_glazed_terracotta[facing=north]"),
+    (236, 3, "minecraft:orange_glazed_terracotta[facing=east]"),
+    (237, 0, "minecraft:magenta_glazed_terracotta[facing=south]"),
+    (237, 1, "minecraft:magenta_glazed_terracotta[facing=west]"),
+    (237, 2, "minecraft:magenta_glazed_terracotta[facing=north]"),
+    (237, 3, "minecraft:magenta_glazed_terracotta[facing=east]"),
+    (
+        238,
+        0,
+        "minecraft:light_blue_glazed_terracotta[facing=south]",
+    ),
+    (
+        238,
+        1,
+        "minecraft:light_blue_glazed_terracotta[facing=west]",
+    ),
+    (
+        238,
+        2,
+        "minecraft:light_blue_glazed_terracotta[facing=north]",
+    ),
+    (
+        238,
+        3,
+        "minecraft:light_blue_glazed_terracotta[facing=east]",
+    ),
+    (239, 0, "minecraft:yellow_glazed_terracotta[facing=south]"),
+    (239, 1, "minecraft:yellow_glazed_terracotta[facing=west]"),
+    (239, 2, "minecraft:yellow_glazed_terracotta[facing=north]"),
+    (239, 3, "minecraft:yellow_glazed_terracotta[facing=east]"),
+    (240, 0, "minecraft:lime_glazed_terracotta[facing=south]"),
+    (240, 1, "minecraft:lime_glazed_terracotta[facing=west]"),
+    (240, 2, "minecraft:lime_glazed_terracotta[facing=north]"),
+    (240, 3, "minecraft:lime_glazed_terracotta[facing=east]"),
+    (241, 0, "minecraft:pink_glazed_terracotta[facing=south]"),
+    (241, 1, "minecraft:pink_glazed_terracotta[facing=west]"),
+    (241, 2, "minecraft:pink_glazed_terracotta[facing=north]"),
+    (241, 3, "minecraft:pink_glazed_terracotta[facing=east]"),
+    (242, 0, "minecraft:gray_glazed_terracotta[facing=south]"),
+    (242, 1, "minecraft:gray_glazed_terracotta[facing=west]"),
+    (242, 2, "minecraft:gray_glazed_terracotta[facing=north]"),
+    (242, 3, "minecraft:gray_glazed_terracotta[facing=east]"),
+    (
+        243,
+        0,
+        "minecraft:light_gray_glazed_terracotta[facing=south]",
+    ),
+    (
+        243,
+        1,
+        "minecraft:light_gray_glazed_terracotta[facing=west]",
+    ),
+    (
+        243,
+        2,
+        "minecraft:light_gray_glazed_terracotta[facing=north]",
+    ),
+    (
+        243,
+        3,
+        "minecraft:light_gray_glazed_terracotta[facing=east]",
+    ),
+    (244, 0, "minecraft:cyan_glazed_terracotta[facing=south]"),
+    (244, 1, "minecraft:cyan_glazed_terracotta[facing=west]"),
+    (244, 2, "minecraft:cyan_glazed_terracotta[facing=north]"),
+    (244, 3, "minecraft:cyan_glazed_terracotta[facing=east]"),
+    (245, 0, "minecraft:purple_glazed_terracotta[facing=south]"),
+    (245, 1, "minecraft:purple_glazed_terracotta[facing=west]"),
+    (245, 2, "minecraft:purple_glazed_terracotta[facing=north]"),
+    (245, 3, "minecraft:purple_glazed_terracotta[facing=east]"),
+    (246, 0, "minecraft:blue_glazed_terracotta[facing=south]"),
+    (246, 1, "minecraft:blue_glazed_terracotta[facing=west]"),
+    (246, 2, "minecraft:blue_glazed_terracotta[facing=north]"),
+    (246, 3, "minecraft:blue_glazed_terracotta[facing=east]"),
+    (247, 0, "minecraft:brown_glazed_terracotta[facing=south]"),
+    (247, 1, "minecraft:brown_glazed_terracotta[facing=west]"),
+    (247, 2, "minecraft:brown_glazed_terracotta[facing=north]"),
+    (247, 3, "minecraft:brown_glazed_terracotta[facing=east]"),
+    (248, 0, "minecraft:green_glazed_terracotta[facing=south]"),
+    (248, 1, "minecraft:green_glazed_terracotta[facing=west]"),
+    (248, 2, "minecraft:green_glazed_terracotta[facing=north]"),
+    (248, 3, "minecraft:green_glazed_terracotta[facing=east]"),
+    (249, 0, "minecraft:red_glazed_terracotta[facing=south]"),
+    (249, 1, "minecraft:red_glazed_terracotta[facing=west]"),
+    (249, 2, "minecraft:red_glazed_terracotta[facing=north]"),
+    (249, 3, "minecraft:red_glazed_terracotta[facing=east]"),
+    (250, 0, "minecraft:black_glazed_terracotta[facing=south]"),
+    (250, 1, "minecraft:black_glazed_terracotta[facing=west]"),
+    (250, 2, "minecraft:black_glazed_terracotta[facing=north]"),
+    (250, 3, "minecraft:black_glazed_terracotta[facing=east]"),
+    (251, 0, "minecraft:white_concrete"),
+    (251, 1, "minecraft:orange_concrete"),
+    (251, 2, "minecraft:magenta_concrete"),
+    (251, 3, "minecraft:light_blue_concrete"),
+    (251, 4, "minecraft:yellow_concrete"),
+    (251, 5, "minecraft:lime_concrete"),
+    (251, 6, "minecraft:pink_concrete"),
+    (251, 7, "minecraft:gray_concrete"),
+    (251, 8, "minecraft:light_gray_concrete"),
+    (251, 9, "minecraft:cyan_concrete"),
+    (251, 10, "minecraft:purple_concrete"),
+    (251, 11, "minecraft:blue_concrete"),
+    (251, 12, "minecraft:brown_concrete"),
+    (251, 13, "minecraft:green_concrete"),
+    (251, 14, "minecraft:red_concrete"),
+    (251, 15, "minecraft:black_concrete"),
+    (252, 0, "minecraft:white_concrete_powder"),
+    (252, 1, "minecraft:orange_concrete_powder"),
+    (252, 2, "minecraft:magenta_concrete_powder"),
+    (252, 3, "minecraft:light_blue_concrete_powder"),
+    (252, 4, "minecraft:yellow_concrete_powder"),
+    (252, 5, "minecraft:lime_concrete_powder"),
+    (252, 6, "minecraft:pink_concrete_powder"),
+    (252, 7, "minecraft:gray_concrete_powder"),
+    (252, 8, "minecraft:light_gray_concrete_powder"),
+    (252, 9, "minecraft:cyan_concrete_powder"),
+    (252, 10, "minecraft:purple_concrete_powder"),
+    (252, 11, "minecraft:blue_concrete_powder"),
+    (252, 12, "minecraft:brown_concrete_powder"),
+    (252, 13, "minecraft:green_concrete_powder"),
+    (252, 14, "minecraft:red_concrete_powder"),
+    (252, 15, "minecraft:black_concrete_powder"),
+];
+
+/// Parse a legacy block name such as `35:14` into its id and data value
+fn parse_legacy(block: &str) -> Option<(u16, u8)> {
+    let (id, data) = block.split_once(':').unwrap_or((block, "0"));
+    Some((id.parse().ok()?, data.parse().ok()?))
+}
+
+/// Upgrade a numeric block id from before Minecraft 1.13 (see
+/// [`is_legacy_block_name`](super::is_legacy_block_name)) to the modern block state it was
+/// flattened into, or `None` if the block isn't covered by the built-in table.
+///
+/// This can be passed to [`upgrade_legacy_blocks`](super::Schematic::upgrade_legacy_blocks)
+/// directly, or combined with a fallback for the blocks the table is missing.
+pub fn upgrade_legacy_block(block: &str) -> Option<String> {
+    let (id, data) = parse_legacy(block)?;
+    LEGACY_BLOCKS
+        .iter()
+        .find(|entry| entry.0 == id && entry.1 == data)
+        .map(|entry| entry.2.to_owned())
+}
+
+/// Split a block state into its name and properties
+fn split_state(block: &str) -> (&str, impl Iterator<Item = &str> + Clone) {
+    let (name, properties) = match block.split_once('[') {
+        Some((name, properties)) => (name, properties.trim_end_matches(']')),
+        None => (block, ""),
+    };
+    (name, properties.split(',').filter(|p| !p.is_empty()))
+}
+
+/// Downgrade a modern block state to the numeric id from before Minecraft 1.13 it was flattened
+/// from, or `None` if the block isn't covered by the built-in table. This is the reverse of
+/// [`upgrade_legacy_block`].
+///
+/// Properties that legacy blocks don't store, such as the `shape` of stairs or `waterlogged`, are
+/// ignored. The result can be passed to
+/// [`downgrade_blocks`](super::Schematic::downgrade_blocks) directly.
+pub fn downgrade_block(block: &str) -> Option<String> {
+    let (name, properties) = split_state(block);
+    let (id, data, _) = LEGACY_BLOCKS.iter().find(|entry| {
+        let (entry_name, mut entry_properties) = split_state(entry.2);
+        entry_name == name && entry_properties.all(|p| properties.clone().any(|q| q == p))
+    })?;
+    Some(if *data == 0 {
+        id.to_string()
+    } else {
+        format!("{id}:{data}")
+    })
+}
@@ -1,11 +1,27 @@
 //! This library provides a convenient way to read, write, and convert Minecraft schematic files of
 //! various formats.
 
+#[macro_use]
+mod macros;
+
+mod hash;
+mod items;
+mod json;
+mod legacy;
+mod litematica;
+mod schematica;
+mod snbt;
 mod sponge;
+mod transform;
+mod varint;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 use thiserror::Error;
 
+pub use legacy::{downgrade_block, upgrade_legacy_block};
+pub use transform::{mirror_block_state, other_part_offset, rotate_block_state, Mirror, Rotation};
+pub use varint::{read_varint, write_varint};
+
 #[derive(Error, Debug)]
 pub enum SchematicError {
     /// The format of the schematic data could not be recongnized as one of the supported types.
@@ -16,22 +32,121 @@ pub enum SchematicError {
     UnsupportedFormat(SchematicFormat),
     #[error("failed to deserialize NBT")]
     NbtError(#[from] nbt::Error),
+    #[error("failed to access file")]
+    Io(#[from] std::io::Error),
+    #[cfg(feature = "zip")]
+    #[error("failed to read zip archive")]
+    ZipError(#[from] zip::result::ZipError),
     #[error("missing required NBT tag: {0}")]
     MissingRequiredField(String),
     #[error("mistyped NBT tag: {0}")]
     MistypedField(String),
     #[error("invalid value for NBT tag: {0}")]
     InvalidValue(String),
+    /// The packed block data doesn't contain exactly one entry for every block of the schematic.
+    #[error("block data has {actual} entries but the schematic has {expected} blocks")]
+    CorruptData { expected: u64, actual: u64 },
+    /// The schematic contains more blocks than the allowed limit.
+    #[error("schematic has {cells} blocks which exceeds the limit of {limit}")]
+    TooLarge { cells: u64, limit: u64 },
+    /// The palette contains more entries than the allowed limit.
+    #[error("palette has {len} entries which exceeds the limit of {limit}")]
+    PaletteTooLarge { len: usize, limit: usize },
+    /// A coordinate was outside of the bounds of the schematic.
+    #[error("out of bounds: {0}")]
+    OutOfBounds(String),
+    /// A pasted block entity would land on a position that already has one, see
+    /// [`BlockEntityCollision::Error`].
+    #[error("block entity collision at {0:?}")]
+    BlockEntityCollision((u32, u32, u32)),
+    /// Reading a serialized schematic back didn't produce the same contents, see
+    /// [`Schematic::verify_roundtrip`]. This describes the first difference found.
+    #[error("round trip changed the schematic: {0}")]
+    RoundTripMismatch(String),
+    /// A block uses a numeric id from before Minecraft 1.13 which could not be upgraded to a
+    /// modern block state.
+    #[error("legacy block could not be upgraded: {0}")]
+    LegacyBlock(String),
+    /// A block has no numeric id from before Minecraft 1.13, so it can't be written to a legacy
+    /// format.
+    #[error("block has no legacy id: {0}")]
+    NoLegacyId(String),
+}
+
+/// Join a block's name and properties compound, as stored in vanilla style palettes, into a block
+/// state such as `minecraft:repeater[delay=2,facing=north]`. Properties are sorted by name, and
+/// every property value has to be a string.
+pub fn block_state_from_nbt(
+    name: &str,
+    properties: &HashMap<String, nbt::Value>,
+) -> Result<String, SchematicError> {
+    let mut properties = properties
+        .iter()
+        .map(|(key, value)| match value {
+            nbt::Value::String(value) => Ok(format!("{key}={value}")),
+            _ => Err(SchematicError::MistypedField(key.clone())),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if properties.is_empty() {
+        return Ok(name.to_owned());
+    }
+    properties.sort();
+    Ok(format!("{name}[{}]", properties.join(",")))
+}
+
+/// Split a block state such as `minecraft:repeater[delay=2,facing=north]` into its name and its
+/// properties, in the order they are written. This is the inverse of [`block_state_from_nbt`].
+/// Properties without a value are skipped.
+pub(crate) fn parse_block_state(block: &str) -> (&str, Vec<(&str, &str)>) {
+    let Some((name, properties)) = block.split_once('[') else {
+        return (block, Vec::new());
+    };
+    let properties = properties
+        .trim_end_matches(']')
+        .split(',')
+        .filter_map(|property| property.split_once('='))
+        .collect();
+    (name, properties)
+}
+
+/// Read a palette entry of the form `{Name: "...", Properties: {...}}`, where the properties are
+/// optional. `list_name` is the name of the list containing the entry.
+pub(crate) fn read_palette_entry(
+    list_name: &str,
+    entry: &nbt::Value,
+) -> Result<String, SchematicError> {
+    let nbt::Value::Compound(entry) = entry else {
+        return Err(SchematicError::MistypedField(list_name.to_owned()));
+    };
+    let name = match entry.get("Name") {
+        Some(nbt::Value::String(name)) => name,
+        Some(_) => return Err(SchematicError::MistypedField("Name".to_owned())),
+        None => return Err(SchematicError::MissingRequiredField("Name".to_owned())),
+    };
+    match entry.get("Properties") {
+        Some(nbt::Value::Compound(properties)) => block_state_from_nbt(name, properties),
+        Some(_) => Err(SchematicError::MistypedField("Properties".to_owned())),
+        None => Ok(name.clone()),
+    }
+}
+
+/// Check if a block name is a numeric id from before Minecraft 1.13, such as `35` or `35:14`
+pub fn is_legacy_block_name(block: &str) -> bool {
+    let (id, data) = block.split_once(':').unwrap_or((block, "0"));
+    !id.is_empty()
+        && !data.is_empty()
+        && id.bytes().all(|b| b.is_ascii_digit())
+        && data.bytes().all(|b| b.is_ascii_digit())
 }
 
 /// Types of schematic formats used by Schematica
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SchematicaFormat {
     Structure,
     Alpha,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 /// The known schematic formats. Not that not all of these schematic formats are supported by this
 /// library.
 pub enum SchematicFormat {
@@ -71,7 +186,80 @@ impl std::fmt::Display for SchematicFormat {
     }
 }
 
+/// The order in which the cells of a [`Blocks`] are visited by [`Blocks::iter_blocks_order`].
+///
+/// The axes are named from the outermost to the innermost loop, so the first axis changes slowest
+/// and the last axis changes fastest. For example, [`AxisOrder::Yzx`] visits every cell of the
+/// lowest layer (y = 0) before moving up, and within a layer every cell of the row z = 0 before
+/// moving on to z = 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AxisOrder {
+    /// Ordered by x, then y, then z. This is the order blocks are stored in by [`Blocks`].
+    Xyz,
+    /// Ordered by x, then z, then y
+    Xzy,
+    /// Ordered by y, then x, then z
+    Yxz,
+    /// Ordered by y, then z, then x. This is the order used by Sponge and Litematica schematics.
+    Yzx,
+    /// Ordered by z, then x, then y
+    Zxy,
+    /// Ordered by z, then y, then x
+    Zyx,
+}
+
+impl AxisOrder {
+    /// The axes (0 for x, 1 for y, and 2 for z) from the outermost to the innermost loop
+    fn axes(self) -> [usize; 3] {
+        match self {
+            AxisOrder::Xyz => [0, 1, 2],
+            AxisOrder::Xzy => [0, 2, 1],
+            AxisOrder::Yxz => [1, 0, 2],
+            AxisOrder::Yzx => [1, 2, 0],
+            AxisOrder::Zxy => [2, 0, 1],
+            AxisOrder::Zyx => [2, 1, 0],
+        }
+    }
+}
+
+/// Which blocks are copied by [`Schematic::paste`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasteMode {
+    /// Copy every block, overwriting the whole box the pasted schematic covers
+    Replace,
+    /// Skip blocks that are the background block of the pasted schematic, usually air, so only
+    /// its content overwrites existing blocks
+    SkipBackground,
+}
+
+/// What [`Schematic::paste`] does when a pasted block entity lands on a position that already has
+/// a block entity, such as a chest pasted onto a chest
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockEntityCollision {
+    /// Replace the existing block and block entity with the pasted ones
+    Overwrite,
+    /// Keep the existing block and block entity, skipping the pasted ones
+    KeepExisting,
+    /// Fail with [`SchematicError::BlockEntityCollision`] without changing anything
+    Error,
+}
+
+/// A run of identical blocks along the innermost axis of an [`AxisOrder`]. See [`Blocks::runs`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Run<'a> {
+    /// The position of the first block of the run
+    pub start: (u32, u32, u32),
+    /// The number of blocks in the run, which is at least 1
+    pub length: u32,
+    pub block: &'a str,
+}
+
 /// A simple fixed-size block storage for dealing with schematic files.
+///
+/// The first entry of the palette (index 0) is always the background block of the container, which
+/// is the block the container was initially filled with. Use [`Blocks::background_id`] instead of
+/// assuming a specific block is at index 0.
+#[derive(Clone)]
 pub struct Blocks {
     palette: Vec<String>,
     palette_map: HashMap<String, u32>,
@@ -79,6 +267,8 @@ pub struct Blocks {
     size_x: u32,
     size_y: u32,
     size_z: u32,
+    /// The number of blocks that aren't `minecraft:air`, if tracking is enabled
+    non_air: Option<u64>,
 }
 
 impl Blocks {
@@ -94,9 +284,47 @@ impl Blocks {
             size_x,
             size_y,
             size_z,
+            non_air: None,
         }
     }
 
+    /// Create a container by calling a function for every position to get the block there. The
+    /// function is called in order of x, then y, then z.
+    pub fn from_fn<S, F>(size_x: u32, size_y: u32, size_z: u32, mut f: F) -> Self
+    where
+        S: AsRef<str>,
+        F: FnMut(u32, u32, u32) -> S,
+    {
+        let mut blocks = Blocks::new(size_x, size_y, size_z, "minecraft:air");
+        // Neighboring blocks are often the same, so avoid the palette lookup in that case
+        let mut last: Option<(S, u32)> = None;
+        for x in 0..size_x {
+            for y in 0..size_y {
+                for z in 0..size_z {
+                    let block = f(x, y, z);
+                    let id = match &last {
+                        Some((last_block, id)) if last_block.as_ref() == block.as_ref() => *id,
+                        _ => blocks.get_block_id_for(block.as_ref()),
+                    };
+                    let idx = blocks.block_index_at(x, y, z);
+                    blocks.indices[idx] = id;
+                    last = Some((block, id));
+                }
+            }
+        }
+        blocks
+    }
+
+    /// Get the palette index of the background block. This is always 0.
+    pub fn background_id(&self) -> u32 {
+        0
+    }
+
+    /// Get the name of the background block, which the container was initially filled with
+    pub fn background_block(&self) -> &str {
+        &self.palette[self.background_id() as usize]
+    }
+
     /// Get the size of this container (x, y, z)
     pub fn size(&self) -> (u32, u32, u32) {
         (self.size_x, self.size_y, self.size_z)
@@ -116,6 +344,34 @@ impl Blocks {
         ((pos_x * self.size_y * self.size_z) + (pos_y * self.size_z) + pos_z) as usize
     }
 
+    /// Get the linear index of a position. Blocks are stored ordered by x, then y, then z, so
+    /// the z coordinate changes fastest.
+    pub fn pos_to_linear_index(&self, pos_x: u32, pos_y: u32, pos_z: u32) -> usize {
+        self.bounds_check(pos_x, pos_y, pos_z);
+        self.block_index_at(pos_x, pos_y, pos_z)
+    }
+
+    /// Get the position of a linear index. This is the inverse of
+    /// [`Blocks::pos_to_linear_index`].
+    pub fn linear_index_to_pos(&self, index: usize) -> (u32, u32, u32) {
+        assert!(
+            index < self.indices.len(),
+            "linear index {index} out of bounds for block container with size {:?}",
+            self.size()
+        );
+        let index = index as u32;
+        (
+            index / (self.size_y * self.size_z),
+            (index / self.size_z) % self.size_y,
+            index % self.size_z,
+        )
+    }
+
+    /// Get the palette index for a block at a linear index
+    pub fn get_by_linear_index(&self, index: usize) -> u32 {
+        self.indices[index]
+    }
+
     /// Get the palette index for a block at a position
     pub fn get_block_id_at(&self, pos_x: u32, pos_y: u32, pos_z: u32) -> u32 {
         self.bounds_check(pos_x, pos_y, pos_z);
@@ -146,6 +402,15 @@ impl Blocks {
     pub fn set_block_id_at(&mut self, pos_x: u32, pos_y: u32, pos_z: u32, id: u32) {
         self.bounds_check(pos_x, pos_y, pos_z);
         let idx = self.block_index_at(pos_x, pos_y, pos_z);
+        if let Some(non_air) = &mut self.non_air {
+            let was_air = self.palette[self.indices[idx] as usize] == "minecraft:air";
+            let is_air = self.palette[id as usize] == "minecraft:air";
+            match (was_air, is_air) {
+                (true, false) => *non_air += 1,
+                (false, true) => *non_air -= 1,
+                _ => {}
+            }
+        }
         self.indices[idx] = id;
     }
 
@@ -155,90 +420,2506 @@ impl Blocks {
         self.set_block_id_at(pos_x, pos_y, pos_z, id);
     }
 
+    /// Set many blocks at once, such as from generator code. This is faster than calling
+    /// [`Blocks::set_block_at`] in a loop, since the palette indices of the first few distinct
+    /// blocks are kept in a small cache, and runs of the same block skip the lookup entirely.
+    /// Blocks that don't fit into the cache are looked up in the palette every time.
+    ///
+    /// Placements are applied in order and fail fast: at the first position outside of the
+    /// container, [`SchematicError::OutOfBounds`] is returned and the remaining placements are
+    /// skipped, while the ones before it stay applied.
+    pub fn set_blocks<'a>(
+        &mut self,
+        blocks: impl IntoIterator<Item = ((u32, u32, u32), &'a str)>,
+    ) -> Result<(), SchematicError> {
+        // Generators usually place only a few distinct blocks, which are faster to find by
+        // comparing them directly than by hashing them
+        let mut recent: Vec<(&str, u32)> = Vec::with_capacity(SET_BLOCKS_CACHE_LEN);
+        for ((x, y, z), block) in blocks {
+            if x >= self.size_x || y >= self.size_y || z >= self.size_z {
+                return Err(SchematicError::OutOfBounds(format!(
+                    "block at {:?} in container with size {:?}",
+                    (x, y, z),
+                    self.size()
+                )));
+            }
+            let id = match recent.iter().find(|(recent, _)| *recent == block) {
+                Some(&(_, id)) => id,
+                None => {
+                    let id = self.get_block_id_for(block);
+                    if recent.len() < SET_BLOCKS_CACHE_LEN {
+                        recent.push((block, id));
+                    }
+                    id
+                }
+            };
+            self.set_block_id_at(x, y, z, id);
+        }
+        Ok(())
+    }
+
+    /// Get the number of entries currently in the palette. This is cheap, so it can be used to
+    /// keep track of the number of distinct blocks while building a container.
+    pub fn palette_size_live(&self) -> usize {
+        self.palette.len()
+    }
+
+    /// Rename the palette entries that are in `aliases` to `minecraft:air`, merging them into a
+    /// single entry. This makes files that spell air differently, such as `air`, compare equal to
+    /// `minecraft:air` everywhere else. See [`AIR_ALIASES`] for the aliases handled when reading.
+    pub fn normalize_air(&mut self, aliases: &[&str]) {
+        if !self
+            .palette
+            .iter()
+            .any(|block| block != "minecraft:air" && aliases.contains(&block.as_str()))
+        {
+            return;
+        }
+        // The renaming function never fails
+        self.rename_palette(|block| {
+            Ok(if aliases.contains(&block) {
+                "minecraft:air".to_owned()
+            } else {
+                block.to_owned()
+            })
+        })
+        .unwrap();
+    }
+
+    /// Start keeping count of the blocks that aren't `minecraft:air`, see
+    /// [`Blocks::non_air_count`]. This scans the container once.
+    ///
+    /// While enabled, every call to [`Blocks::set_block_id_at`] and [`Blocks::set_block_at`] has to
+    /// compare the old and new block with `minecraft:air`, which adds two string comparisons to
+    /// every block that is set.
+    pub fn track_non_air(&mut self) {
+        self.non_air = Some(self.count_non_air());
+    }
+
+    /// Get the number of blocks that aren't `minecraft:air`, or `None` if
+    /// [`Blocks::track_non_air`] wasn't called.
+    pub fn non_air_count(&self) -> Option<u64> {
+        self.non_air
+    }
+
+    /// Check if every block in the container is the background block, see
+    /// [`Blocks::background_block`].
+    ///
+    /// This is cheap if nothing but the background block was ever added to the palette, or if
+    /// [`Blocks::track_non_air`] was called and the background block is `minecraft:air`. Otherwise
+    /// the container is scanned until a different block is found.
+    pub fn is_empty(&self) -> bool {
+        if self.palette.len() == 1 {
+            return true;
+        }
+        if let Some(non_air) = self.non_air {
+            if self.background_block() == "minecraft:air" {
+                return non_air == 0;
+            }
+        }
+        let background = self.background_id();
+        self.indices.iter().all(|&id| id == background)
+    }
+
+    fn count_non_air(&self) -> u64 {
+        self.indices
+            .iter()
+            .filter(|&&id| self.palette[id as usize] != "minecraft:air")
+            .count() as u64
+    }
+
+    /// Count the items needed to build this container, such as a shopping list for survival
+    /// builders.
+    ///
+    /// Block states are collapsed to the item that places them, so `minecraft:oak_stairs[...]`
+    /// counts as `minecraft:oak_stairs`. This assumes the item has the same name as the block,
+    /// except for wall variants (such as wall signs, torches, and heads), redstone wire, and
+    /// tripwire. Double slabs count as two slabs and stacked blocks such as candles, sea pickles,
+    /// and snow layers count once per layer. Only one part of beds, doors, and tall plants is
+    /// counted. Air, fluids, portals, and other blocks without an item are skipped. Block entity
+    /// contents and crafting recipes are not taken into account.
+    pub fn item_counts(&self) -> HashMap<String, u64> {
+        let mut per_id = vec![0; self.palette.len()];
+        for &id in &self.indices {
+            per_id[id as usize] += 1;
+        }
+        let mut counts = HashMap::new();
+        for (block, blocks) in self.palette.iter().zip(per_id) {
+            if blocks == 0 {
+                continue;
+            }
+            if let Some((item, count)) = items::block_item(block) {
+                *counts.entry(item).or_default() += blocks * count;
+            }
+        }
+        counts
+    }
+
+    /// Get the y coordinate of the highest solid block of every column, as seen from above.
+    ///
+    /// The result is indexed as `heightmap[z][x]`, so it has `size_z` rows of `size_x` entries each,
+    /// with north (z = 0) in the first row and west (x = 0) at the start of every row. Columns
+    /// without any solid block are 0, just like columns whose only solid block is at y = 0.
+    pub fn heightmap(&self, is_solid: impl Fn(&str) -> bool) -> Vec<Vec<u32>> {
+        let solid: Vec<bool> = self.palette.iter().map(|block| is_solid(block)).collect();
+        (0..self.size_z)
+            .map(|z| {
+                (0..self.size_x)
+                    .map(|x| {
+                        (0..self.size_y)
+                            .rev()
+                            .find(|&y| solid[self.indices[self.block_index_at(x, y, z)] as usize])
+                            .unwrap_or(0)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Get the positions of every block with exactly the given name, ordered by x, then y, then z.
+    pub fn positions_of(&self, block: &str) -> Vec<(u32, u32, u32)> {
+        self.positions_where(|b| b == block)
+    }
+
+    /// Get the positions of every block for which `f` returns true, ordered by x, then y, then z.
+    /// `f` is called once for every palette entry rather than for every block.
+    pub fn positions_where(&self, mut f: impl FnMut(&str) -> bool) -> Vec<(u32, u32, u32)> {
+        let matches: Vec<bool> = self.palette.iter().map(|block| f(block)).collect();
+        if !matches.contains(&true) {
+            return Vec::new();
+        }
+        self.indices
+            .iter()
+            .enumerate()
+            .filter(|(_, &id)| matches[id as usize])
+            .map(|(i, _)| self.linear_index_to_pos(i))
+            .collect()
+    }
+
     /// Get an iterator of all types of blocks within this container
     pub fn blocks_in_palette(&self) -> impl Iterator<Item = &str> {
         self.palette.iter().map(|x| x.as_str())
     }
-}
 
-/// Block entities are blocks with extra NBT data associated with them (containers, comparators,
-/// etc.)
-pub struct BlockEntity {
-    pub id: String,
-    pub data: HashMap<String, nbt::Value>,
-}
+    /// Get an iterator over every position in this container along with the name of the block at
+    /// that position
+    pub fn cells(&self) -> impl Iterator<Item = ((u32, u32, u32), &str)> {
+        self.indices.iter().enumerate().map(|(i, id)| {
+            (
+                self.linear_index_to_pos(i),
+                self.palette[*id as usize].as_str(),
+            )
+        })
+    }
 
-/// A schematic file
-pub struct Schematic {
-    pub blocks: Blocks,
-    /// The original world coordinates of the min point on the schematic when it was created
-    pub origin: Option<(i32, i32, i32)>,
-    /// The offset from the player location to the min point in the schematic when pasing
-    pub paste_offset: Option<(i32, i32, i32)>,
-    pub biomes: Option<Blocks>,
-    pub data_version: Option<u32>,
-    pub block_entities: HashMap<(u32, u32, u32), BlockEntity>,
-    /// Used if the schematic format has a specific metadata tag. When reading from schematics,
-    /// metadata will not be included in this field if it was read into another field of
-    /// [`Schematic`] (e.g. you won't see `WEOffsetX`).
-    pub metadata: Option<HashMap<String, nbt::Value>>,
-}
+    /// Get an iterator over every position in this container along with the name of the block at
+    /// that position, visiting the positions in the given order. [`AxisOrder::Xyz`] is the same
+    /// order as [`Blocks::cells`].
+    pub fn iter_blocks_order(
+        &self,
+        order: AxisOrder,
+    ) -> impl Iterator<Item = ((u32, u32, u32), &str)> {
+        let axes = order.axes();
+        let size = [self.size_x, self.size_y, self.size_z];
+        let (middle, inner) = (size[axes[1]] as usize, size[axes[2]] as usize);
+        (0..self.indices.len()).map(move |i| {
+            let mut pos = [0; 3];
+            pos[axes[0]] = (i / inner / middle) as u32;
+            pos[axes[1]] = (i / inner % middle) as u32;
+            pos[axes[2]] = (i % inner) as u32;
+            let id = self.indices[self.block_index_at(pos[0], pos[1], pos[2])];
+            ((pos[0], pos[1], pos[2]), self.palette[id as usize].as_str())
+        })
+    }
 
-impl Schematic {
-    /// Get the size of this schematic (x, y, z)
-    pub fn size(&self) -> (u32, u32, u32) {
-        self.blocks.size()
+    /// Get an iterator over the runs of identical blocks, visiting the blocks in the given order.
+    ///
+    /// Runs only coalesce along the innermost axis of the order, so a run never continues into the
+    /// next row even if it starts with the same block. For example, with [`AxisOrder::Yzx`], every
+    /// run is a stretch of blocks along the x axis. Every block is part of exactly one run.
+    pub fn runs(&self, order: AxisOrder) -> impl Iterator<Item = Run<'_>> {
+        let inner = order.axes()[2];
+        let mut cells = self.iter_blocks_order(order).peekable();
+        std::iter::from_fn(move || {
+            let (start, block) = cells.next()?;
+            let mut run = Run {
+                start,
+                length: 1,
+                block,
+            };
+            let start = [start.0, start.1, start.2];
+            while let Some(&((x, y, z), next)) = cells.peek() {
+                let mut expected = start;
+                expected[inner] += run.length;
+                if next != block || [x, y, z] != expected {
+                    break;
+                }
+                run.length += 1;
+                cells.next();
+            }
+            Some(run)
+        })
     }
 
-    /// Deserialize a schematic from a raw byte slice.
+    /// Create a lower resolution copy of this container, where every `factor`³ cell of blocks is
+    /// reduced to a single block. The resulting container has a size of `ceil(size / factor)` on
+    /// each axis.
     ///
-    /// This function will attempt to detect which format the schematic is encoded in. If the format
-    /// cannot be recognized, [`SchematicError::UnrecognizedFormat`] is returned. Not all
-    /// schematic formats representable with [`SchematicFormat`] are deserializable. In that case,
-    /// [`SchematicError::UnsupportedFormat`] is returned.
-    pub fn deserialize(data: &[u8]) -> Result<Schematic, SchematicError> {
-        let mut cur = std::io::Cursor::new(data);
-        let nbt = nbt::Blob::from_gzip_reader(&mut cur)?;
-        if let Some(nbt::Value::Int(version)) = nbt.get("Version") {
-            let version = *version as u32;
-            if nbt.get("Regions").is_some() {
-                // This is a Litematica schematic
-                return Err(SchematicError::UnsupportedFormat(
-                    SchematicFormat::Litematica(version),
-                ));
+    /// The representative block of a cell is the most common block in it that isn't
+    /// `minecraft:air`, or `minecraft:air` if the cell is entirely air. If multiple blocks are
+    /// equally common, the one that comes first in the palette is chosen.
+    ///
+    /// Panics if `factor` is 0.
+    pub fn downsample(&self, factor: u32) -> Blocks {
+        assert!(factor != 0, "downsample factor must not be 0");
+        let new_size = (
+            self.size_x.div_ceil(factor),
+            self.size_y.div_ceil(factor),
+            self.size_z.div_ceil(factor),
+        );
+        let mut result = Blocks::new(new_size.0, new_size.1, new_size.2, &self.palette[0]);
+        let remap = result.import_palette(self);
+        let air = self.palette_map.get("minecraft:air").copied();
+
+        let mut counts = vec![0u32; self.palette.len()];
+        for cx in 0..new_size.0 {
+            for cy in 0..new_size.1 {
+                for cz in 0..new_size.2 {
+                    counts.fill(0);
+                    for x in cx * factor..((cx + 1) * factor).min(self.size_x) {
+                        for y in cy * factor..((cy + 1) * factor).min(self.size_y) {
+                            for z in cz * factor..((cz + 1) * factor).min(self.size_z) {
+                                counts[self.indices[self.block_index_at(x, y, z)] as usize] += 1;
+                            }
+                        }
+                    }
+
+                    let mut best = None;
+                    for (id, &count) in counts.iter().enumerate() {
+                        let id = id as u32;
+                        if count == 0 || Some(id) == air {
+                            continue;
+                        }
+                        if !matches!(best, Some((_, best_count)) if best_count >= count) {
+                            best = Some((id, count));
+                        }
+                    }
+                    let id = best.map(|(id, _)| id).or(air).unwrap_or(0);
+                    result.set_block_id_at(cx, cy, cz, remap[id as usize]);
+                }
             }
-            if matches!(version, 1 | 2) {
-                // This is a Sponge schematic (v1 or v2)
-                // Sponge v3 and newer stores the Schematic schema in a nested tag
-                return sponge::deserialize(&nbt, version);
+        }
+        result
+    }
+
+    /// Replace the entire palette and all palette indices of this container at once. The indices
+    /// are ordered by x, then y, then z.
+    ///
+    /// The first entry of the new palette becomes the background block. Returns
+    /// [`SchematicError::InvalidValue`] and leaves the container unchanged if the number of indices
+    /// does not match the size of the container, the palette is empty, an index is out of range for
+    /// the palette, or the palette contains duplicate entries.
+    pub fn replace_data(
+        &mut self,
+        palette: Vec<String>,
+        indices: Vec<u32>,
+    ) -> Result<(), SchematicError> {
+        if indices.len() != self.indices.len() {
+            return Err(SchematicError::InvalidValue("indices".to_owned()));
+        }
+        if palette.is_empty() {
+            return Err(SchematicError::InvalidValue("palette".to_owned()));
+        }
+        if indices.iter().any(|&id| id as usize >= palette.len()) {
+            return Err(SchematicError::InvalidValue("indices".to_owned()));
+        }
+        let mut palette_map = HashMap::with_capacity(palette.len());
+        for (id, block) in palette.iter().enumerate() {
+            if palette_map.insert(block.clone(), id as u32).is_some() {
+                return Err(SchematicError::InvalidValue("palette".to_owned()));
             }
-        } else if let Some(nbt::Value::Compound(schem_compound)) = nbt.get("Schematic") {
-            if let Some(nbt::Value::Int(version)) = schem_compound.get("Version") {
-                let version = *version as u32;
-                if version == 3 {
-                    return sponge::deserialize(&nbt, version);
+        }
+
+        self.palette = palette;
+        self.palette_map = palette_map;
+        self.indices = indices;
+        if self.non_air.is_some() {
+            self.track_non_air();
+        }
+        Ok(())
+    }
+
+    /// Check that the palette has at most `max` entries, returning
+    /// [`SchematicError::PaletteTooLarge`] otherwise. This is useful to reject containers before
+    /// converting them to a format with a limited palette size. Unused entries are counted as well,
+    /// so consider calling [`Blocks::compact_palette`] first.
+    pub fn assert_palette_within(&self, max: usize) -> Result<(), SchematicError> {
+        if self.palette.len() > max {
+            return Err(SchematicError::PaletteTooLarge {
+                len: self.palette.len(),
+                limit: max,
+            });
+        }
+        Ok(())
+    }
+
+    /// Check that both containers have the same size, returning [`SchematicError::InvalidValue`]
+    /// with both sizes otherwise. Use this before operations that compare or combine containers
+    /// cell by cell.
+    pub fn assert_same_size(&self, other: &Blocks) -> Result<(), SchematicError> {
+        if self.size() != other.size() {
+            return Err(SchematicError::InvalidValue(format!(
+                "size {:?} does not match {:?}",
+                self.size(),
+                other.size()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Remove all palette entries that aren't used by any block. The remaining entries keep their
+    /// relative order, and the background block always stays at index 0, even if it is unused, so
+    /// [`Blocks::background_id`] remains valid.
+    pub fn compact_palette(&mut self) {
+        let mut used = self.used_ids();
+        used[self.background_id() as usize] = true;
+        if used.iter().all(|&used| used) {
+            return;
+        }
+
+        let mut remap = vec![0; self.palette.len()];
+        let mut palette = Vec::new();
+        for (id, block) in self.palette.drain(..).enumerate() {
+            if used[id] {
+                remap[id] = palette.len() as u32;
+                palette.push(block);
+            }
+        }
+        for id in &mut self.indices {
+            *id = remap[*id as usize];
+        }
+        self.palette_map = palette
+            .iter()
+            .enumerate()
+            .map(|(id, block)| (block.clone(), id as u32))
+            .collect();
+        self.palette = palette;
+    }
+
+    /// Get whether each palette entry is used by any block
+    pub(crate) fn used_ids(&self) -> Vec<bool> {
+        let mut used = vec![false; self.palette.len()];
+        for &id in &self.indices {
+            used[id as usize] = true;
+        }
+        used
+    }
+
+    /// Get the positions in the box between `min` and `max` (both inclusive), or
+    /// [`SchematicError::OutOfBounds`] if `max` is outside of the container
+    fn region_positions(
+        &self,
+        min: (u32, u32, u32),
+        max: (u32, u32, u32),
+    ) -> Result<impl Iterator<Item = (u32, u32, u32)>, SchematicError> {
+        if max.0 >= self.size_x || max.1 >= self.size_y || max.2 >= self.size_z {
+            return Err(SchematicError::OutOfBounds(format!(
+                "region up to {max:?} in container with size {:?}",
+                self.size()
+            )));
+        }
+        Ok((min.0..=max.0).flat_map(move |x| {
+            (min.1..=max.1).flat_map(move |y| (min.2..=max.2).map(move |z| (x, y, z)))
+        }))
+    }
+
+    /// Call `f` for every block in the box between `min` and `max` (both inclusive), replacing the
+    /// block with the returned block state, if any. Returning `None` leaves the block unchanged.
+    ///
+    /// Returns [`SchematicError::OutOfBounds`] without changing anything if `max` is outside of
+    /// the container. The box is empty if `min` is larger than `max` on any axis.
+    pub fn map_region<F>(
+        &mut self,
+        min: (u32, u32, u32),
+        max: (u32, u32, u32),
+        mut f: F,
+    ) -> Result<(), SchematicError>
+    where
+        F: FnMut((u32, u32, u32), &str) -> Option<String>,
+    {
+        for (x, y, z) in self.region_positions(min, max)? {
+            let idx = self.block_index_at(x, y, z);
+            let block = &self.palette[self.indices[idx] as usize];
+            if let Some(block) = f((x, y, z), block) {
+                let id = self.get_block_id_for(&block);
+                self.set_block_id_at(x, y, z, id);
+            }
+        }
+        Ok(())
+    }
+
+    /// Set every block in the box between `min` and `max` (both inclusive) to the background block
+    /// (see [`Blocks::background_block`]), which erases that part of the build.
+    ///
+    /// Like [`Blocks::map_region`], [`SchematicError::OutOfBounds`] is returned without changing
+    /// anything if `max` is outside of the container.
+    pub fn clear_region(
+        &mut self,
+        min: (u32, u32, u32),
+        max: (u32, u32, u32),
+    ) -> Result<(), SchematicError> {
+        let background = self.background_id();
+        for (x, y, z) in self.region_positions(min, max)? {
+            self.set_block_id_at(x, y, z, background);
+        }
+        Ok(())
+    }
+
+    /// Get all positions connected to `start` through blocks for which `matches` returns true,
+    /// including `start` itself. Blocks are connected if they share a face. If the block at
+    /// `start` doesn't match, the result is empty.
+    pub fn flood_fill(
+        &self,
+        start: (u32, u32, u32),
+        matches: impl Fn(&str) -> bool,
+    ) -> Vec<(u32, u32, u32)> {
+        let matching: Vec<bool> = self.palette.iter().map(|b| matches(b)).collect();
+        let mut visited = vec![false; self.indices.len()];
+        let start = self.pos_to_linear_index(start.0, start.1, start.2);
+        self.fill_from(start, &matching, &mut visited)
+    }
+
+    /// Split all blocks for which `matches` returns true into groups of connected blocks. Blocks
+    /// are connected if they share a face.
+    pub fn connected_components(
+        &self,
+        matches: impl Fn(&str) -> bool,
+    ) -> Vec<Vec<(u32, u32, u32)>> {
+        let matching: Vec<bool> = self.palette.iter().map(|b| matches(b)).collect();
+        let mut visited = vec![false; self.indices.len()];
+        let mut components = Vec::new();
+        for start in 0..self.indices.len() {
+            if !visited[start] && matching[self.indices[start] as usize] {
+                components.push(self.fill_from(start, &matching, &mut visited));
+            }
+        }
+        components
+    }
+
+    /// Breadth-first search from a linear index through all matching palette entries
+    fn fill_from(
+        &self,
+        start: usize,
+        matching: &[bool],
+        visited: &mut [bool],
+    ) -> Vec<(u32, u32, u32)> {
+        let mut found = Vec::new();
+        if !matching[self.indices[start] as usize] {
+            return found;
+        }
+        let mut queue = VecDeque::from([start]);
+        visited[start] = true;
+        while let Some(idx) = queue.pop_front() {
+            let (x, y, z) = self.linear_index_to_pos(idx);
+            found.push((x, y, z));
+            let neighbors = [
+                (x.wrapping_sub(1), y, z),
+                (x + 1, y, z),
+                (x, y.wrapping_sub(1), z),
+                (x, y + 1, z),
+                (x, y, z.wrapping_sub(1)),
+                (x, y, z + 1),
+            ];
+            for (nx, ny, nz) in neighbors {
+                if nx >= self.size_x || ny >= self.size_y || nz >= self.size_z {
+                    continue;
+                }
+                let neighbor = self.block_index_at(nx, ny, nz);
+                if !visited[neighbor] && matching[self.indices[neighbor] as usize] {
+                    visited[neighbor] = true;
+                    queue.push_back(neighbor);
                 }
             }
         }
+        found
+    }
 
-        Err(SchematicError::UnrecognizedFormat)
+    /// Render a horizontal layer as text for debugging. Each line is a row along the x axis, with
+    /// the lowest z coordinate (north) at the top.
+    ///
+    /// Air is shown as a space and every other block as the first letter of its name without the
+    /// namespace (e.g. `s` for `minecraft:stone`). See [`Blocks::render_layer_ascii_with`] to pick
+    /// the characters yourself. This is only meant as a quick debugging aid and not as a
+    /// substitute for a real renderer.
+    pub fn render_layer_ascii(&self, y: u32) -> String {
+        self.render_layer_ascii_with(y, &HashMap::new())
     }
 
-    /// Serialize a schematic into raw bytes.
+    /// Render a horizontal layer as text for debugging, like [`Blocks::render_layer_ascii`], but
+    /// use the given characters for blocks that appear in `glyphs`.
+    pub fn render_layer_ascii_with(&self, y: u32, glyphs: &HashMap<&str, char>) -> String {
+        let palette_glyphs: Vec<char> = self
+            .palette
+            .iter()
+            .map(|block| {
+                if let Some(glyph) = glyphs.get(block.as_str()) {
+                    return *glyph;
+                }
+                let name = block
+                    .split_once(':')
+                    .map_or(block.as_str(), |(_, name)| name);
+                if matches!(name, "air" | "cave_air" | "void_air") {
+                    ' '
+                } else {
+                    name.chars().next().unwrap_or('?')
+                }
+            })
+            .collect();
+
+        let mut out = String::new();
+        for z in 0..self.size_z {
+            for x in 0..self.size_x {
+                out.push(palette_glyphs[self.get_block_id_at(x, y, z) as usize]);
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Clean up palette entries written by hand or by sloppy tools, such as `Minecraft:Stone `.
+    /// Whitespace is trimmed and the block name and property names are lowercased, while property
+    /// values are kept as is. Entries that become the same are merged.
     ///
-    /// Not all schematic formats representable with [`SchematicFormat`] are serializable. In that
-    /// case, [`SchematicError::UnsupportedFormat`] is returned.
-    pub fn serialize(&self, format: SchematicFormat) -> Result<Vec<u8>, SchematicError> {
-        let data = match format {
-            SchematicFormat::Sponge(version) => {
-                if !matches!(version, 2 | 3) {
-                    return Err(SchematicError::UnsupportedFormat(format));
+    /// This is never done automatically, so the palette is kept exactly as read unless this is
+    /// called.
+    pub fn sanitize_palette(&mut self) {
+        let sanitize = |block: &str| {
+            let block = block.trim();
+            let Some((name, properties)) = block.split_once('[') else {
+                return Ok(block.to_lowercase());
+            };
+            let properties: Vec<String> = properties
+                .trim_end_matches(']')
+                .split(',')
+                .map(|property| match property.split_once('=') {
+                    Some((key, value)) => {
+                        format!("{}={}", key.trim().to_lowercase(), value.trim())
+                    }
+                    None => property.trim().to_owned(),
+                })
+                .collect();
+            Ok(format!(
+                "{}[{}]",
+                name.trim().to_lowercase(),
+                properties.join(",")
+            ))
+        };
+        // The renaming function never fails
+        self.rename_palette(sanitize).unwrap();
+    }
+
+    /// Sort the properties of every palette entry by name, so the same block state written with
+    /// properties in a different order (such as `minecraft:oak_stairs[half=top,facing=north]` and
+    /// `minecraft:oak_stairs[facing=north,half=top]`) ends up as a single entry.
+    ///
+    /// Properties without a value are dropped. Like [`Blocks::sanitize_palette`], this is never
+    /// done automatically, but [`Schematic::deserialize_canonical`] does it while reading.
+    pub fn canonicalize_block_states(&mut self) {
+        let canonicalize = |block: &str| {
+            let (name, mut properties) = parse_block_state(block);
+            if properties.is_empty() {
+                return Ok(block.to_owned());
+            }
+            properties.sort_by_key(|(key, _)| *key);
+            let properties: Vec<String> = properties
+                .into_iter()
+                .map(|(key, value)| format!("{key}={value}"))
+                .collect();
+            Ok(format!("{name}[{}]", properties.join(",")))
+        };
+        // The renaming function never fails
+        self.rename_palette(canonicalize).unwrap();
+    }
+
+    /// Rename palette entries using a table of old names to new names, such as a flattening
+    /// mapping. Entries that aren't in the table are kept as is, and entries that end up with the
+    /// same name are merged.
+    pub fn apply_rename_table(&mut self, table: &HashMap<String, String>) {
+        // The renaming function never fails
+        self.rename_palette(|block| Ok(table.get(block).map_or(block, String::as_str).to_owned()))
+            .unwrap();
+    }
+
+    /// Replace every palette entry with the result of `f`. Entries that end up with the same name
+    /// are merged. The container is left unchanged if `f` returns an error.
+    fn rename_palette(
+        &mut self,
+        mut f: impl FnMut(&str) -> Result<String, SchematicError>,
+    ) -> Result<(), SchematicError> {
+        let mut palette = Vec::with_capacity(self.palette.len());
+        let mut palette_map = HashMap::with_capacity(self.palette.len());
+        let mut remap = Vec::with_capacity(self.palette.len());
+        for block in &self.palette {
+            let block = f(block)?;
+            let id = *palette_map.entry(block.clone()).or_insert_with(|| {
+                palette.push(block);
+                palette.len() as u32 - 1
+            });
+            remap.push(id);
+        }
+
+        if palette.len() != self.palette.len() {
+            for id in &mut self.indices {
+                *id = remap[*id as usize];
+            }
+        }
+        self.palette = palette;
+        self.palette_map = palette_map;
+        if self.non_air.is_some() {
+            self.track_non_air();
+        }
+        Ok(())
+    }
+
+    /// Copy a box of the given size starting at `offset` into a new container with the same
+    /// background block. Only palette entries used within the box are kept.
+    fn crop(&self, offset: (u32, u32, u32), size: (u32, u32, u32)) -> Blocks {
+        let mut cropped = Blocks::new(size.0, size.1, size.2, self.background_block());
+        let remap = cropped.import_palette(self);
+        for x in 0..size.0 {
+            for y in 0..size.1 {
+                for z in 0..size.2 {
+                    let id = self.get_block_id_at(offset.0 + x, offset.1 + y, offset.2 + z);
+                    cropped.set_block_id_at(x, y, z, remap[id as usize]);
                 }
+            }
+        }
+        cropped.compact_palette();
+        cropped
+    }
 
-                sponge::serialize(self, version)?
-            },
-            _ => return Err(SchematicError::UnsupportedFormat(format)),
+    /// Swap the x and z axes, so the block at `(x, y, z)` ends up at `(z, y, x)`. Block states are
+    /// kept as is, so unlike a rotation, directional properties are not changed.
+    pub fn transpose_xz(&mut self) {
+        let mut indices = Vec::with_capacity(self.indices.len());
+        for z in 0..self.size_z {
+            for y in 0..self.size_y {
+                for x in 0..self.size_x {
+                    indices.push(self.indices[self.block_index_at(x, y, z)]);
+                }
+            }
+        }
+        self.indices = indices;
+        std::mem::swap(&mut self.size_x, &mut self.size_z);
+    }
+
+    /// Rotate the container clockwise around the y axis (as seen from above) by the given number of
+    /// quarter turns. Directional block state properties are rotated along with the blocks using
+    /// [`rotate_block_state`].
+    pub fn rotate_y(&mut self, quarter_turns: u32) {
+        let Some(rotation) = Rotation::from_quarter_turns(quarter_turns) else {
+            return;
         };
-        Ok(data)
+        let (size_x, size_z) = match rotation {
+            Rotation::Clockwise180 => (self.size_x, self.size_z),
+            _ => (self.size_z, self.size_x),
+        };
+        let mut indices = vec![0; self.indices.len()];
+        for x in 0..self.size_x {
+            for y in 0..self.size_y {
+                for z in 0..self.size_z {
+                    let (new_x, new_z) =
+                        transform::rotate_block_pos((x, z), (self.size_x, self.size_z), rotation);
+                    let index = new_x as usize * self.size_y as usize * size_z as usize
+                        + y as usize * size_z as usize
+                        + new_z as usize;
+                    indices[index] = self.indices[self.block_index_at(x, y, z)];
+                }
+            }
+        }
+        self.indices = indices;
+        self.size_x = size_x;
+        self.size_z = size_z;
+        // The renaming function never fails
+        self.rename_palette(|block| Ok(rotate_block_state(block, rotation)))
+            .unwrap();
+    }
+
+    /// Mirror the container across one of the horizontal axes. Directional block state properties
+    /// are mirrored along with the blocks using [`mirror_block_state`].
+    pub fn mirror(&mut self, mirror: Mirror) {
+        let mut indices = vec![0; self.indices.len()];
+        for x in 0..self.size_x {
+            for y in 0..self.size_y {
+                for z in 0..self.size_z {
+                    let (new_x, new_z) = match mirror {
+                        Mirror::X => (self.size_x - 1 - x, z),
+                        Mirror::Z => (x, self.size_z - 1 - z),
+                    };
+                    indices[self.block_index_at(new_x, y, new_z)] =
+                        self.indices[self.block_index_at(x, y, z)];
+                }
+            }
+        }
+        self.indices = indices;
+        // The renaming function never fails
+        self.rename_palette(|block| Ok(mirror_block_state(block, mirror)))
+            .unwrap();
+    }
+
+    /// Check if both containers have the same palette in the same order. If they do, palette
+    /// indices can be copied between them without remapping.
+    pub fn palette_matches(&self, other: &Blocks) -> bool {
+        self.palette == other.palette
+    }
+
+    /// Add all palette entries of another container into this container's palette. The returned
+    /// vector maps palette indices of `other` to palette indices of `self`.
+    pub fn import_palette(&mut self, other: &Blocks) -> Vec<u32> {
+        other
+            .palette
+            .iter()
+            .map(|block| self.get_block_id_for(block))
+            .collect()
+    }
+}
+
+impl<'a> IntoIterator for &'a Blocks {
+    type Item = &'a str;
+    type IntoIter = std::iter::Map<std::slice::Iter<'a, String>, fn(&String) -> &str>;
+
+    /// Iterate over all types of blocks within the container, same as
+    /// [`Blocks::blocks_in_palette`]
+    fn into_iter(self) -> Self::IntoIter {
+        self.palette.iter().map(String::as_str)
+    }
+}
+
+/// Block entities are blocks with extra NBT data associated with them (containers, comparators,
+/// etc.)
+#[derive(Clone)]
+pub struct BlockEntity {
+    pub id: String,
+    /// The NBT data of the block entity, not including its id or position. This is the same
+    /// regardless of how the schematic format stores the data (e.g. inline in Sponge version 2
+    /// and nested in a `Data` compound in Sponge version 3).
+    pub data: HashMap<String, nbt::Value>,
+}
+
+/// Entities are mobs, armor stands, item frames, minecarts, etc. that are not bound to the block
+/// grid.
+#[derive(Clone)]
+pub struct Entity {
+    pub id: String,
+    /// The position of the entity relative to the min point of the schematic
+    pub pos: (f64, f64, f64),
+    /// The NBT data of the entity, not including its id or position. Like [`BlockEntity::data`],
+    /// this does not depend on how the schematic format stores the data.
+    pub data: HashMap<String, nbt::Value>,
+}
+
+/// Check if a compound contains the nested `Schematic` compound of a Sponge version 3 schematic
+fn is_sponge_v3(nbt: &HashMap<String, nbt::Value>) -> bool {
+    let Some(nbt::Value::Compound(schem_compound)) = nbt.get("Schematic") else {
+        return false;
+    };
+    match schem_compound.get("Version") {
+        Some(nbt::Value::Int(version)) => *version == 3,
+        // Some tools omit the nested version, so assume version 3 if the compound otherwise looks
+        // like a Sponge schematic
+        None => schem_compound.contains_key("DataVersion") || schem_compound.contains_key("Blocks"),
+        Some(_) => false,
+    }
+}
+
+/// The number of distinct blocks [`Blocks::set_blocks`] remembers the palette index of
+const SET_BLOCKS_CACHE_LEN: usize = 16;
+
+/// The number of leading bytes that are searched for a gzip header when a file doesn't start with
+/// one
+const MAX_JUNK_PREFIX: usize = 16;
+
+/// Skip junk bytes in front of a gzip stream. The data is returned unchanged if it starts with a
+/// gzip header already or no header follows shortly after, so that files which are actually
+/// corrupt still fail to read.
+fn skip_junk_prefix(data: &[u8]) -> &[u8] {
+    const GZIP_MAGIC: [u8; 3] = [0x1f, 0x8b, 0x08];
+    if data.starts_with(&GZIP_MAGIC) {
+        return data;
+    }
+    let window = &data[..data.len().min(MAX_JUNK_PREFIX + GZIP_MAGIC.len())];
+    match window
+        .windows(GZIP_MAGIC.len())
+        .position(|w| w == GZIP_MAGIC)
+    {
+        Some(start) => &data[start..],
+        None => data,
+    }
+}
+
+/// Read an entity position stored as a list of three numbers. The specs use doubles, but some tools
+/// write floats or ints instead, so those are accepted too and converted to `f64`.
+pub(crate) fn read_entity_pos(
+    nbt: &HashMap<String, nbt::Value>,
+) -> Result<(f64, f64, f64), SchematicError> {
+    let coord = |value: &nbt::Value| match *value {
+        nbt::Value::Double(val) => Some(val),
+        nbt::Value::Float(val) => Some(val as f64),
+        nbt::Value::Int(val) => Some(val as f64),
+        _ => None,
+    };
+    match nbt.get("Pos") {
+        Some(nbt::Value::List(pos)) => match &pos[..] {
+            [x, y, z] => match (coord(x), coord(y), coord(z)) {
+                (Some(x), Some(y), Some(z)) => Ok((x, y, z)),
+                _ => Err(SchematicError::InvalidValue("Pos".to_owned())),
+            },
+            _ => Err(SchematicError::InvalidValue("Pos".to_owned())),
+        },
+        Some(_) => Err(SchematicError::MistypedField("Pos".to_owned())),
+        None => Err(SchematicError::MissingRequiredField("Pos".to_owned())),
+    }
+}
+
+pub(crate) fn write_entity_pos(pos: (f64, f64, f64)) -> nbt::Value {
+    nbt::Value::List(vec![
+        nbt::Value::Double(pos.0),
+        nbt::Value::Double(pos.1),
+        nbt::Value::Double(pos.2),
+    ])
+}
+
+/// The size of a named tag entry within a compound, excluding the payload
+pub(crate) fn named_tag_size(name: &str) -> usize {
+    // Tag type + name length + name
+    1 + 2 + name.len()
+}
+
+/// The size of the payload of a compound tag
+pub(crate) fn compound_size(compound: &HashMap<String, nbt::Value>) -> usize {
+    compound
+        .iter()
+        .map(|(name, value)| named_tag_size(name) + value.len_bytes())
+        .sum::<usize>()
+        // End tag
+        + 1
+}
+
+/// The first data version (Minecraft 1.20.5) in which item stacks store their extra data in a
+/// `components` compound instead of the legacy `tag` compound.
+pub const ITEM_COMPONENTS_DATA_VERSION: u32 = 3837;
+
+/// The first data version (Minecraft 1.20) in which signs have text on both sides, stored in
+/// `front_text` and `back_text` instead of `Text1` to `Text4`.
+pub const SIGN_SIDES_DATA_VERSION: u32 = 3463;
+
+/// Block entities that can run commands, spawn entities, or generate structures, which servers
+/// accepting schematics from untrusted users usually want to reject. See
+/// [`Schematic::contains_dangerous`].
+pub const DANGEROUS_BLOCK_ENTITIES: [&str; 6] = [
+    "minecraft:command_block",
+    "minecraft:chain_command_block",
+    "minecraft:repeating_command_block",
+    "minecraft:mob_spawner",
+    "minecraft:jigsaw",
+    "minecraft:structure_block",
+];
+
+/// Names that hand-written or very old files use for air. When reading a schematic, palette
+/// entries matching one of these are renamed to `minecraft:air`, unless the palette is preserved
+/// (see [`Schematic::deserialize_preserve_palette`]). Use
+/// [`Schematic::deserialize_with_air_aliases`] to read with a different list instead.
+pub const AIR_ALIASES: [&str; 3] = ["minecraft:air", "air", ""];
+
+/// An item stored inside of a block entity (e.g. in a chest or hopper)
+pub struct ItemStack {
+    pub id: String,
+    pub count: i32,
+    pub slot: Option<i8>,
+    /// The extra data of the item. This is read from `components` starting with
+    /// [`ITEM_COMPONENTS_DATA_VERSION`] and from `tag` before that.
+    pub data: Option<HashMap<String, nbt::Value>>,
+}
+
+impl BlockEntity {
+    /// Create a sign with the given lines of plain text on its front. The layout of the sign's data
+    /// depends on whether `data_version` is before or after [`SIGN_SIDES_DATA_VERSION`].
+    pub fn sign(lines: [&str; 4], data_version: u32) -> BlockEntity {
+        let lines = lines.map(|line| {
+            let mut text = "{\"text\":".to_owned();
+            json::write_string(&mut text, line);
+            text.push('}');
+            nbt::Value::String(text)
+        });
+
+        let mut data = HashMap::new();
+        if data_version >= SIGN_SIDES_DATA_VERSION {
+            let side = |messages: Vec<nbt::Value>| {
+                let mut side = HashMap::new();
+                side.insert("messages".to_owned(), nbt::Value::List(messages));
+                side.insert("color".to_owned(), nbt::Value::String("black".to_owned()));
+                side.insert("has_glowing_text".to_owned(), nbt::Value::Byte(0));
+                nbt::Value::Compound(side)
+            };
+            let empty = nbt::Value::String("{\"text\":\"\"}".to_owned());
+            data.insert("front_text".to_owned(), side(lines.to_vec()));
+            data.insert("back_text".to_owned(), side(vec![empty; 4]));
+            data.insert("is_waxed".to_owned(), nbt::Value::Byte(0));
+        } else {
+            for (i, line) in lines.into_iter().enumerate() {
+                data.insert(format!("Text{}", i + 1), line);
+            }
+            data.insert("Color".to_owned(), nbt::Value::String("black".to_owned()));
+            data.insert("GlowingText".to_owned(), nbt::Value::Byte(0));
+        }
+        BlockEntity {
+            id: "minecraft:sign".to_owned(),
+            data,
+        }
+    }
+
+    /// Create a chest containing the given items. See [`BlockEntity::container`].
+    pub fn chest(items: Vec<ItemStack>, data_version: u32) -> Result<BlockEntity, SchematicError> {
+        Self::container("minecraft:chest", items, data_version)
+    }
+
+    /// Create a container block entity (chest, barrel, hopper, etc.) with the given id, storing the
+    /// items in its `Items` list. This is the inverse of [`BlockEntity::items`].
+    ///
+    /// Items that have no slot are put into the first slot not used by another item. Returns
+    /// [`SchematicError::InvalidValue`] if the count of an item doesn't fit the layout used by
+    /// `data_version`, or if the items don't fit into the slots of a container.
+    pub fn container(
+        id: &str,
+        items: Vec<ItemStack>,
+        data_version: u32,
+    ) -> Result<BlockEntity, SchematicError> {
+        let components = data_version >= ITEM_COMPONENTS_DATA_VERSION;
+        let mut used: Vec<i8> = items.iter().filter_map(|item| item.slot).collect();
+        let mut list = Vec::with_capacity(items.len());
+        for item in items {
+            let slot = match item.slot {
+                Some(slot) => slot,
+                None => {
+                    let slot = (0..=i8::MAX)
+                        .find(|slot| !used.contains(slot))
+                        .ok_or_else(|| SchematicError::InvalidValue("Slot".to_owned()))?;
+                    used.push(slot);
+                    slot
+                }
+            };
+            let mut nbt_item = HashMap::new();
+            nbt_item.insert("id".to_owned(), nbt::Value::String(item.id));
+            nbt_item.insert("Slot".to_owned(), nbt::Value::Byte(slot));
+            if components {
+                nbt_item.insert("count".to_owned(), nbt::Value::Int(item.count));
+            } else {
+                let count = i8::try_from(item.count)
+                    .map_err(|_| SchematicError::InvalidValue("Count".to_owned()))?;
+                nbt_item.insert("Count".to_owned(), nbt::Value::Byte(count));
+            }
+            if let Some(data) = item.data {
+                let name = if components { "components" } else { "tag" };
+                nbt_item.insert(name.to_owned(), nbt::Value::Compound(data));
+            }
+            list.push(nbt::Value::Compound(nbt_item));
+        }
+
+        let mut data = HashMap::new();
+        data.insert("Items".to_owned(), nbt::Value::List(list));
+        Ok(BlockEntity {
+            id: id.to_owned(),
+            data,
+        })
+    }
+
+    /// Read the contents of the `Items` list of a container block entity.
+    ///
+    /// The layout of item stacks changed in Minecraft 1.20.5, so the data version of the schematic
+    /// is used to decide which layout to expect. If the data version is unknown, both layouts are
+    /// accepted.
+    pub fn items(&self, data_version: Option<u32>) -> Result<Vec<ItemStack>, SchematicError> {
+        let items = match self.data.get("Items") {
+            Some(nbt::Value::List(items)) => items.as_slice(),
+            Some(_) => return Err(SchematicError::MistypedField("Items".to_owned())),
+            None => &[],
+        };
+        let components = data_version.map(|v| v >= ITEM_COMPONENTS_DATA_VERSION);
+
+        let mut stacks = Vec::new();
+        for item in items {
+            let nbt::Value::Compound(item) = item else {
+                return Err(SchematicError::MistypedField("Items".to_owned()));
+            };
+            let id = match item.get("id") {
+                Some(nbt::Value::String(id)) => id.clone(),
+                Some(_) => return Err(SchematicError::MistypedField("id".to_owned())),
+                None => return Err(SchematicError::MissingRequiredField("id".to_owned())),
+            };
+            let count = match (components, item.get("count"), item.get("Count")) {
+                (Some(true) | None, Some(nbt::Value::Int(count)), _) => *count,
+                (Some(false) | None, _, Some(nbt::Value::Byte(count))) => *count as i32,
+                // Components-era items with a count of 1 omit the field entirely
+                (Some(true), None, _) => 1,
+                _ => return Err(SchematicError::MistypedField("count".to_owned())),
+            };
+            let slot = match item.get("Slot") {
+                Some(nbt::Value::Byte(slot)) => Some(*slot),
+                Some(_) => return Err(SchematicError::MistypedField("Slot".to_owned())),
+                None => None,
+            };
+            let data_name = match (components, item.contains_key("components")) {
+                (Some(true), _) | (None, true) => "components",
+                _ => "tag",
+            };
+            let data = match item.get(data_name) {
+                Some(nbt::Value::Compound(data)) => Some(data.clone()),
+                Some(_) => return Err(SchematicError::MistypedField(data_name.to_owned())),
+                None => None,
+            };
+            stacks.push(ItemStack {
+                id,
+                count,
+                slot,
+                data,
+            });
+        }
+        Ok(stacks)
+    }
+
+    /// Get an integer tag of the data, such as a command block's `SuccessCount`. Bytes and shorts
+    /// are widened. Returns `None` if the tag is missing or has another type.
+    pub fn get_i32(&self, name: &str) -> Option<i32> {
+        match *self.data.get(name)? {
+            nbt::Value::Byte(val) => Some(val as i32),
+            nbt::Value::Short(val) => Some(val as i32),
+            nbt::Value::Int(val) => Some(val),
+            _ => None,
+        }
+    }
+
+    /// Get a long tag of the data, such as a container's `LootTableSeed`. Bytes, shorts, and ints
+    /// are widened. Returns `None` if the tag is missing or has another type.
+    pub fn get_i64(&self, name: &str) -> Option<i64> {
+        match *self.data.get(name)? {
+            nbt::Value::Long(val) => Some(val),
+            _ => self.get_i32(name).map(i64::from),
+        }
+    }
+
+    /// Get a string tag of the data, such as a command block's `Command`. Returns `None` if the tag
+    /// is missing or has another type.
+    pub fn get_string(&self, name: &str) -> Option<&str> {
+        match self.data.get(name)? {
+            nbt::Value::String(val) => Some(val),
+            _ => None,
+        }
+    }
+
+    /// Get a compound tag of the data, such as a spawner's `SpawnData`. Returns `None` if the tag
+    /// is missing or has another type.
+    pub fn get_compound(&self, name: &str) -> Option<&HashMap<String, nbt::Value>> {
+        match self.data.get(name)? {
+            nbt::Value::Compound(val) => Some(val),
+            _ => None,
+        }
+    }
+
+    /// Get a list tag of the data, such as a container's `Items`. Returns `None` if the tag is
+    /// missing or has another type.
+    pub fn get_list(&self, name: &str) -> Option<&[nbt::Value]> {
+        match self.data.get(name)? {
+            nbt::Value::List(val) => Some(val),
+            _ => None,
+        }
+    }
+}
+
+/// A schematic file
+///
+/// Light levels are not part of a schematic. None of the supported formats (Sponge, Litematica,
+/// and Schematica) store sky or block light, so there is nothing to preserve, and this library
+/// never computes lighting. Minecraft relights blocks when a schematic is pasted.
+#[derive(Clone)]
+pub struct Schematic {
+    pub blocks: Blocks,
+    /// The original world coordinates of the min point on the schematic when it was created.
+    ///
+    /// - Sponge versions 1 and 2: the root `Offset` tag. The specification describes it as the
+    ///   paste offset, but WorldEdit writes the origin there, so this library does the same.
+    /// - Sponge version 3: WorldEdit's `Metadata.WorldEdit.Origin` plus the paste offset. The
+    ///   origin tag is the position the schematic was copied from, which is what the offset is
+    ///   relative to.
+    /// - Litematica: not stored.
+    /// - Schematica Alpha: `WEOriginX`, `WEOriginY`, and `WEOriginZ` when writing.
+    pub origin: Option<(i32, i32, i32)>,
+    /// The offset from the player location to the min point in the schematic when pasing.
+    ///
+    /// - Sponge versions 1 and 2: `WEOffsetX`, `WEOffsetY`, and `WEOffsetZ` in the `Metadata`
+    ///   compound, following WorldEdit. When reading, other casings of these keys and an `Offset`
+    ///   int array in the metadata are accepted too.
+    /// - Sponge version 3: the `Offset` tag of the `Schematic` compound, as specified. This is
+    ///   `None` for files that omit the tag, and `(0, 0, 0)` is written if this is `None`.
+    /// - Litematica: the min point of all regions when reading, and the region `Position` when
+    ///   writing.
+    /// - Schematica Alpha: `WEOffsetX`, `WEOffsetY`, and `WEOffsetZ` when writing.
+    pub paste_offset: Option<(i32, i32, i32)>,
+    /// The biome of each block. Sponge version 2 only stores biomes per column, so they are read
+    /// into a container with a height of 1, while version 3 stores a biome for every block. Biomes
+    /// stored at a quarter of the resolution are scaled up when reading.
+    pub biomes: Option<Blocks>,
+    pub data_version: Option<u32>,
+    pub block_entities: HashMap<(u32, u32, u32), BlockEntity>,
+    pub entities: Vec<Entity>,
+    /// Used if the schematic format has a specific metadata tag. When reading from schematics,
+    /// metadata will not be included in this field if it was read into another field of
+    /// [`Schematic`] (e.g. you won't see `WEOffsetX`). Everything else, including provenance such
+    /// as the `WorldEdit` compound of Sponge version 3, is written back as is.
+    pub metadata: Option<HashMap<String, nbt::Value>>,
+    /// The Litematica `SubVersion`, which is stored next to the format version. It is written back
+    /// when saving as Litematica, where the current sub-version is used if this is `None`. Other
+    /// formats ignore it.
+    pub sub_version: Option<i32>,
+    /// The name of the root NBT compound. Sponge version 2 names it `Schematic`, while Sponge
+    /// version 3 and Litematica leave it empty. When serializing, it is only written when saving
+    /// in the format the schematic was read from, or in any format if the schematic wasn't read
+    /// from a file. Otherwise, or if this is `None`, the default name for the format is used.
+    pub root_title: Option<String>,
+    /// The regions the schematic was made up of, in order. Litematica schematics can contain
+    /// several regions, which are merged into [`Schematic::blocks`] in this order when reading, so
+    /// later regions overwrite earlier ones where they overlap. This is empty for formats without
+    /// regions. See [`Schematic::split_regions`].
+    ///
+    /// When saving as Litematica, every region is written separately in this order, unless some
+    /// blocks, block entities, or entities lie outside of all regions. In that case the schematic
+    /// is written as a single region.
+    pub regions: Vec<Region>,
+    /// The format the schematic was read from, see [`Schematic::source_format`]
+    source_format: Option<SchematicFormat>,
+}
+
+/// A named box within a schematic
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub name: String,
+    /// The position of the min point of the region within [`Schematic::blocks`]
+    pub offset: (u32, u32, u32),
+    pub size: (u32, u32, u32),
+}
+
+/// A square thumbnail of a schematic, see [`Schematic::preview_image`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreviewImage {
+    /// The width and height of the image in pixels
+    pub size: u32,
+    /// The pixels as RGBA bytes, row by row from the top left
+    pub rgba: Vec<u8>,
+}
+
+/// Options shared by the readers of every format
+pub(crate) struct ReadOptions<'a> {
+    /// Refuse to read schematics with more blocks than this
+    pub max_cells: Option<u64>,
+    /// Called with the number of blocks decoded so far and the total number of blocks
+    pub progress: &'a mut dyn FnMut(u64, u64),
+    /// Skip block entities, entities, and biomes
+    pub blocks_only: bool,
+    /// Keep the order of the palette as stored in the file, including unused entries
+    pub preserve_palette: bool,
+    /// Palette entries renamed to `minecraft:air` unless the palette is preserved
+    pub air_aliases: &'a [&'a str],
+    /// Sort the properties of every block state, see [`Blocks::canonicalize_block_states`]
+    pub canonicalize_block_states: bool,
+}
+
+impl<'a> ReadOptions<'a> {
+    fn new(progress: &'a mut dyn FnMut(u64, u64)) -> Self {
+        ReadOptions {
+            max_cells: None,
+            progress,
+            blocks_only: false,
+            preserve_palette: false,
+            air_aliases: &AIR_ALIASES,
+            canonicalize_block_states: false,
+        }
+    }
+}
+
+impl std::fmt::Debug for Schematic {
+    /// Prints a short summary of the schematic rather than the contents of every block.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Schematic")
+            .field("size", &self.size())
+            .field("data_version", &self.data_version)
+            .field("palette_len", &self.blocks.palette.len())
+            .field("block_entities", &self.block_entities.len())
+            .field("entities", &self.entities.len())
+            .field("origin", &self.origin)
+            .field("paste_offset", &self.paste_offset)
+            .field("has_biomes", &self.biomes.is_some())
+            .field("has_metadata", &self.metadata.is_some())
+            .field("regions", &self.regions.len())
+            .finish()
+    }
+}
+
+impl Schematic {
+    /// Create a schematic containing the given blocks and nothing else. The data version is
+    /// required by most formats when serializing, so it should be set with
+    /// [`Schematic::with_data_version`].
+    pub fn new(blocks: Blocks) -> Schematic {
+        Schematic {
+            blocks,
+            origin: None,
+            paste_offset: None,
+            biomes: None,
+            data_version: None,
+            block_entities: HashMap::new(),
+            entities: Vec::new(),
+            metadata: None,
+            sub_version: None,
+            root_title: None,
+            regions: Vec::new(),
+            source_format: None,
+        }
+    }
+
+    /// Set the data version of the Minecraft version the schematic is for, such as `3465` for
+    /// 1.20.1, and return the schematic. See [`Schematic::set_data_version`].
+    pub fn with_data_version(mut self, data_version: u32) -> Schematic {
+        self.set_data_version(data_version);
+        self
+    }
+
+    /// Set the data version of the Minecraft version the schematic is for. Sponge and Litematica
+    /// schematics can't be serialized without one.
+    pub fn set_data_version(&mut self, data_version: u32) {
+        self.data_version = Some(data_version);
+    }
+
+    /// Get the format this schematic was read from, e.g. to save it in the same format again.
+    /// Returns `None` if the schematic wasn't read from a file.
+    pub fn source_format(&self) -> Option<SchematicFormat> {
+        self.source_format
+    }
+
+    /// Get the name to give the root NBT compound when writing `format`, or `None` to use the
+    /// default name of the format. See [`Schematic::root_title`].
+    fn root_title_for(&self, format: SchematicFormat) -> Option<&str> {
+        match self.source_format {
+            Some(source) if source != format => None,
+            _ => self.root_title.as_deref(),
+        }
+    }
+
+    /// Get the size of this schematic (x, y, z). For Sponge schematics, these are the values of the
+    /// `Width`, `Height`, and `Length` tags exactly as they were read.
+    pub fn size(&self) -> (u32, u32, u32) {
+        self.blocks.size()
+    }
+
+    /// Swap the x and z axes of the schematic, moving blocks, biomes, block entities, entities, and
+    /// regions along. This repairs schematics written by tools that swap `Width` and `Length`. See
+    /// [`Blocks::transpose_xz`].
+    pub fn transpose_xz(&mut self) {
+        self.blocks.transpose_xz();
+        if let Some(biomes) = &mut self.biomes {
+            biomes.transpose_xz();
+        }
+        self.block_entities = std::mem::take(&mut self.block_entities)
+            .into_iter()
+            .map(|((x, y, z), block_entity)| ((z, y, x), block_entity))
+            .collect();
+        for entity in &mut self.entities {
+            let (x, y, z) = entity.pos;
+            entity.pos = (z, y, x);
+        }
+        for region in &mut self.regions {
+            let (x, y, z) = region.offset;
+            region.offset = (z, y, x);
+            let (w, h, l) = region.size;
+            region.size = (l, h, w);
+        }
+    }
+
+    /// Rotate the schematic clockwise around the y axis (as seen from above) by the given number of
+    /// quarter turns.
+    ///
+    /// Blocks, biomes, block entities, entities, and regions are moved along, and directional
+    /// block states as well as the orientation stored in the NBT of skulls, item frames,
+    /// paintings, and other entities are rotated. The paste offset is rotated around the player,
+    /// so the schematic is pasted rotated around the same point. The origin is kept as is.
+    pub fn rotate_y(&mut self, quarter_turns: u32) {
+        let Some(rotation) = Rotation::from_quarter_turns(quarter_turns) else {
+            return;
+        };
+        let (size_x, _, size_z) = self.size();
+
+        self.blocks.rotate_y(quarter_turns);
+        if let Some(biomes) = &mut self.biomes {
+            biomes.rotate_y(quarter_turns);
+        }
+        self.block_entities = std::mem::take(&mut self.block_entities)
+            .into_iter()
+            .map(|((x, y, z), mut block_entity)| {
+                let (x, z) = transform::rotate_block_pos((x, z), (size_x, size_z), rotation);
+                transform::rotate_block_entity_data(&mut block_entity.data, rotation);
+                ((x, y, z), block_entity)
+            })
+            .collect();
+        for entity in &mut self.entities {
+            let (x, y, z) = entity.pos;
+            let (x, z) = transform::rotate_point((x, z), (size_x as f64, size_z as f64), rotation);
+            entity.pos = (x, y, z);
+            transform::rotate_entity_data(&entity.id, &mut entity.data, rotation);
+        }
+        for region in &mut self.regions {
+            let (x, y, z) = region.offset;
+            let (w, h, l) = region.size;
+            region.offset = match rotation {
+                Rotation::Clockwise90 => (size_z - z - l, y, x),
+                Rotation::Clockwise180 => (size_x - x - w, y, size_z - z - l),
+                Rotation::CounterClockwise90 => (z, y, size_x - x - w),
+            };
+            if rotation != Rotation::Clockwise180 {
+                region.size = (l, h, w);
+            }
+        }
+
+        if let Some((x, y, z)) = self.paste_offset {
+            // Blocks are rotated around the player's block, which turns the max corner of the
+            // schematic into the new min corner
+            let (max_x, max_z) = (x + size_x as i32 - 1, z + size_z as i32 - 1);
+            self.paste_offset = Some(match rotation {
+                Rotation::Clockwise90 => (-max_z, y, x),
+                Rotation::Clockwise180 => (-max_x, y, -max_z),
+                Rotation::CounterClockwise90 => (z, y, -max_x),
+            });
+        }
+    }
+
+    /// Get a copy of this schematic rotated by the given number of clockwise quarter turns, leaving
+    /// this schematic untouched. See [`Schematic::rotate_y`].
+    pub fn rotated(&self, quarter_turns: u32) -> Schematic {
+        let mut rotated = self.clone();
+        rotated.rotate_y(quarter_turns);
+        rotated
+    }
+
+    /// Mirror the schematic across one of the horizontal axes.
+    ///
+    /// Like [`Schematic::rotate_y`], everything in the schematic is moved along and the
+    /// orientation of blocks, block entities, and entities is mirrored. The paste offset is
+    /// mirrored around the player and the origin is kept as is.
+    pub fn mirror(&mut self, mirror: Mirror) {
+        let (size_x, _, size_z) = self.size();
+        let mirror_pos = |x: u32, z: u32| match mirror {
+            Mirror::X => (size_x - 1 - x, z),
+            Mirror::Z => (x, size_z - 1 - z),
+        };
+
+        self.blocks.mirror(mirror);
+        if let Some(biomes) = &mut self.biomes {
+            biomes.mirror(mirror);
+        }
+        self.block_entities = std::mem::take(&mut self.block_entities)
+            .into_iter()
+            .map(|((x, y, z), mut block_entity)| {
+                let (x, z) = mirror_pos(x, z);
+                transform::mirror_block_entity_data(&mut block_entity.data, mirror);
+                ((x, y, z), block_entity)
+            })
+            .collect();
+        for entity in &mut self.entities {
+            match mirror {
+                Mirror::X => entity.pos.0 = size_x as f64 - entity.pos.0,
+                Mirror::Z => entity.pos.2 = size_z as f64 - entity.pos.2,
+            }
+            transform::mirror_entity_data(&entity.id, &mut entity.data, mirror);
+        }
+        for region in &mut self.regions {
+            match mirror {
+                Mirror::X => region.offset.0 = size_x - region.offset.0 - region.size.0,
+                Mirror::Z => region.offset.2 = size_z - region.offset.2 - region.size.2,
+            }
+        }
+
+        if let Some((x, _, z)) = &mut self.paste_offset {
+            match mirror {
+                Mirror::X => *x = -(*x + size_x as i32 - 1),
+                Mirror::Z => *z = -(*z + size_z as i32 - 1),
+            }
+        }
+    }
+
+    /// Get a copy of this schematic mirrored across one of the horizontal axes, leaving this
+    /// schematic untouched. See [`Schematic::mirror`].
+    pub fn mirrored(&self, mirror: Mirror) -> Schematic {
+        let mut mirrored = self.clone();
+        mirrored.mirror(mirror);
+        mirrored
+    }
+
+    /// Paste another schematic into this one, with the min point of `other` at `offset`.
+    ///
+    /// `mode` decides which blocks are copied. Block entities are copied along with their blocks,
+    /// and overwriting a block removes its old block entity. If a pasted block entity lands on an
+    /// existing one, `collision` decides what happens, see [`BlockEntityCollision`]. Entities are
+    /// copied and moved by `offset`. Biomes, metadata, and regions of `other` are ignored.
+    ///
+    /// Returns [`SchematicError::OutOfBounds`] without changing anything if `other` doesn't fit.
+    pub fn paste(
+        &mut self,
+        other: &Schematic,
+        offset: (u32, u32, u32),
+        mode: PasteMode,
+        collision: BlockEntityCollision,
+    ) -> Result<(), SchematicError> {
+        let (size_x, size_y, size_z) = other.size();
+        let fits = |offset: u32, size: u32, container: u32| {
+            offset as u64 + size as u64 <= container as u64
+        };
+        let (max_x, max_y, max_z) = self.size();
+        if !fits(offset.0, size_x, max_x)
+            || !fits(offset.1, size_y, max_y)
+            || !fits(offset.2, size_z, max_z)
+        {
+            return Err(SchematicError::OutOfBounds(format!(
+                "schematic with size {:?} pasted at {offset:?} in schematic with size {:?}",
+                other.size(),
+                self.size()
+            )));
+        }
+        let shift = |(x, y, z): (u32, u32, u32)| (x + offset.0, y + offset.1, z + offset.2);
+
+        let mut keep = HashSet::new();
+        for &pos in other.block_entities.keys() {
+            let target = shift(pos);
+            if self.block_entities.contains_key(&target) {
+                match collision {
+                    BlockEntityCollision::Overwrite => {}
+                    BlockEntityCollision::KeepExisting => {
+                        keep.insert(target);
+                    }
+                    BlockEntityCollision::Error => {
+                        return Err(SchematicError::BlockEntityCollision(target));
+                    }
+                }
+            }
+        }
+
+        let ids: Vec<u32> = (&other.blocks)
+            .into_iter()
+            .map(|block| self.blocks.get_block_id_for(block))
+            .collect();
+        let background = other.blocks.background_id();
+        for x in 0..size_x {
+            for y in 0..size_y {
+                for z in 0..size_z {
+                    let id = other.blocks.get_block_id_at(x, y, z);
+                    let target = shift((x, y, z));
+                    if (mode == PasteMode::SkipBackground && id == background)
+                        || keep.contains(&target)
+                    {
+                        continue;
+                    }
+                    self.blocks
+                        .set_block_id_at(target.0, target.1, target.2, ids[id as usize]);
+                    self.block_entities.remove(&target);
+                    if let Some(block_entity) = other.block_entities.get(&(x, y, z)) {
+                        self.block_entities.insert(target, block_entity.clone());
+                    }
+                }
+            }
+        }
+
+        self.entities.extend(other.entities.iter().map(|entity| {
+            let mut entity = entity.clone();
+            entity.pos.0 += offset.0 as f64;
+            entity.pos.1 += offset.1 as f64;
+            entity.pos.2 += offset.2 as f64;
+            entity
+        }));
+        Ok(())
+    }
+
+    /// Shift the world placement of this schematic without touching its contents.
+    ///
+    /// Both [`Schematic::origin`] and [`Schematic::paste_offset`] are moved by the given amount,
+    /// so the schematic will be pasted at a position shifted by `(dx, dy, dz)` and records having
+    /// been created at a position shifted by the same amount. Fields that are `None` are treated
+    /// as `(0, 0, 0)` before being shifted.
+    pub fn translate(&mut self, dx: i32, dy: i32, dz: i32) {
+        for pos in [&mut self.origin, &mut self.paste_offset] {
+            let (x, y, z) = pos.unwrap_or_default();
+            *pos = Some((x + dx, y + dy, z + dz));
+        }
+    }
+
+    /// Get the min and max corners (both inclusive) of the blocks of this schematic in world space.
+    ///
+    /// This uses [`Schematic::origin`], the position the schematic was created at, treating a
+    /// missing origin as `(0, 0, 0)`. The paste offset is not taken into account. Returns `None` if
+    /// the schematic is empty.
+    pub fn world_bounds(&self) -> Option<((i32, i32, i32), (i32, i32, i32))> {
+        let (size_x, size_y, size_z) = self.size();
+        if size_x == 0 || size_y == 0 || size_z == 0 {
+            return None;
+        }
+        let (x, y, z) = self.origin.unwrap_or_default();
+        Some((
+            (x, y, z),
+            (
+                x + size_x as i32 - 1,
+                y + size_y as i32 - 1,
+                z + size_z as i32 - 1,
+            ),
+        ))
+    }
+
+    /// Get the center of the schematic in world space. See [`Schematic::world_bounds`].
+    pub fn center(&self) -> Option<(f64, f64, f64)> {
+        let (min, max) = self.world_bounds()?;
+        let center = |min: i32, max: i32| (min as f64 + max as f64 + 1.0) / 2.0;
+        Some((
+            center(min.0, max.0),
+            center(min.1, max.1),
+            center(min.2, max.2),
+        ))
+    }
+
+    /// Add a block entity at a position, returning the block entity that was previously there.
+    /// Returns [`SchematicError::OutOfBounds`] if the position is outside of the schematic.
+    pub fn set_block_entity(
+        &mut self,
+        pos: (u32, u32, u32),
+        block_entity: BlockEntity,
+    ) -> Result<Option<BlockEntity>, SchematicError> {
+        let (size_x, size_y, size_z) = self.size();
+        if pos.0 >= size_x || pos.1 >= size_y || pos.2 >= size_z {
+            return Err(SchematicError::OutOfBounds(format!(
+                "block entity at {pos:?} in schematic with size {:?}",
+                self.size()
+            )));
+        }
+        Ok(self.block_entities.insert(pos, block_entity))
+    }
+
+    /// Remove the block entity at a position, returning it if there was one
+    pub fn remove_block_entity(&mut self, pos: (u32, u32, u32)) -> Option<BlockEntity> {
+        self.block_entities.remove(&pos)
+    }
+
+    /// Get all block entities sorted by their position, ordered by x, then y, then z like the
+    /// blocks of [`Blocks`]. Unlike iterating over [`Schematic::block_entities`], the order is
+    /// deterministic.
+    pub fn block_entities_sorted(&self) -> Vec<(&(u32, u32, u32), &BlockEntity)> {
+        let mut block_entities: Vec<_> = self.block_entities.iter().collect();
+        block_entities.sort_unstable_by_key(|(pos, _)| **pos);
+        block_entities
+    }
+
+    /// Check if this schematic contains nothing but the background block, and no block entities
+    /// or entities. See [`Blocks::is_empty`] for when this has to scan the blocks.
+    pub fn is_empty(&self) -> bool {
+        self.block_entities.is_empty() && self.entities.is_empty() && self.blocks.is_empty()
+    }
+
+    /// Iterate over the block entities in the box between `min` and `max`. Like
+    /// [`Blocks::map_region`], both corners are inclusive, and the box is empty if `min` is larger
+    /// than `max` on any axis. The block entities are not in any particular order.
+    pub fn block_entities_in(
+        &self,
+        min: (u32, u32, u32),
+        max: (u32, u32, u32),
+    ) -> impl Iterator<Item = (&(u32, u32, u32), &BlockEntity)> {
+        self.block_entities.iter().filter(move |((x, y, z), _)| {
+            (min.0..=max.0).contains(x)
+                && (min.1..=max.1).contains(y)
+                && (min.2..=max.2).contains(z)
+        })
+    }
+
+    /// Get the distinct ids of all block entities in this schematic, such as `minecraft:chest`.
+    /// This is useful for quickly auditing which special blocks a schematic contains.
+    pub fn block_entity_ids(&self) -> HashSet<&str> {
+        self.block_entities
+            .values()
+            .map(|block_entity| block_entity.id.as_str())
+            .collect()
+    }
+
+    /// Check if any block entity has one of the given ids. The `minecraft:` namespace is optional
+    /// on both sides, so `command_block` matches `minecraft:command_block` and the other way around.
+    pub fn contains_any_block_entity(&self, ids: &[&str]) -> bool {
+        let strip = |id: &str| id.strip_prefix("minecraft:").unwrap_or(id).to_owned();
+        let ids: HashSet<String> = ids.iter().map(|id| strip(id)).collect();
+        self.block_entity_ids()
+            .into_iter()
+            .any(|id| ids.contains(&strip(id)))
+    }
+
+    /// Check if the schematic contains command blocks, spawners, or other block entities in
+    /// [`DANGEROUS_BLOCK_ENTITIES`].
+    pub fn contains_dangerous(&self) -> bool {
+        self.contains_any_block_entity(&DANGEROUS_BLOCK_ENTITIES)
+    }
+
+    /// Get a string from the metadata. Returns `None` if it is missing or not a string.
+    pub fn metadata_get_str(&self, key: &str) -> Option<&str> {
+        match self.metadata.as_ref()?.get(key)? {
+            nbt::Value::String(val) => Some(val),
+            _ => None,
+        }
+    }
+
+    /// Get an integer from the metadata. Returns `None` if it is missing or not an integer.
+    pub fn metadata_get_i32(&self, key: &str) -> Option<i32> {
+        match self.metadata.as_ref()?.get(key)? {
+            nbt::Value::Int(val) => Some(*val),
+            _ => None,
+        }
+    }
+
+    /// Get the platform that wrote this schematic, such as `enginehub:fabric`. WorldEdit stores
+    /// this in `Metadata.WorldEdit.EditingPlatform` of Sponge version 3 schematics.
+    pub fn editing_platform(&self) -> Option<&str> {
+        self.worldedit_metadata_str("EditingPlatform")
+    }
+
+    /// Get the version of WorldEdit that wrote this schematic, stored in
+    /// `Metadata.WorldEdit.Version` of Sponge version 3 schematics.
+    pub fn worldedit_version(&self) -> Option<&str> {
+        self.worldedit_metadata_str("Version")
+    }
+
+    fn worldedit_metadata_str(&self, key: &str) -> Option<&str> {
+        let Some(nbt::Value::Compound(worldedit)) = self.metadata.as_ref()?.get("WorldEdit") else {
+            return None;
+        };
+        match worldedit.get(key)? {
+            nbt::Value::String(val) => Some(val),
+            _ => None,
+        }
+    }
+
+    /// Set a string in the metadata, creating the metadata if there is none yet
+    pub fn metadata_set_str(&mut self, key: &str, val: &str) {
+        self.metadata
+            .get_or_insert_with(HashMap::new)
+            .insert(key.to_owned(), nbt::Value::String(val.to_owned()));
+    }
+
+    /// Set an integer in the metadata, creating the metadata if there is none yet
+    pub fn metadata_set_i32(&mut self, key: &str, val: i32) {
+        self.metadata
+            .get_or_insert_with(HashMap::new)
+            .insert(key.to_owned(), nbt::Value::Int(val));
+    }
+
+    /// Remove a key from the metadata, returning its value if it was present
+    pub fn metadata_remove(&mut self, key: &str) -> Option<nbt::Value> {
+        self.metadata.as_mut()?.remove(key)
+    }
+
+    /// Get a copy of a single horizontal layer of the schematic's blocks. The returned container
+    /// has a height of 1 and shares the palette of [`Schematic::blocks`].
+    pub fn layer(&self, y: u32) -> Result<Blocks, SchematicError> {
+        let (size_x, size_y, size_z) = self.size();
+        if y >= size_y {
+            return Err(SchematicError::OutOfBounds(format!(
+                "layer {y} of schematic with height {size_y}"
+            )));
+        }
+        let mut layer = Blocks::new(size_x, 1, size_z, &self.blocks.palette[0]);
+        layer.import_palette(&self.blocks);
+        for x in 0..size_x {
+            for z in 0..size_z {
+                layer.set_block_id_at(x, 0, z, self.blocks.get_block_id_at(x, y, z));
+            }
+        }
+        Ok(layer)
+    }
+
+    /// Get an iterator over all horizontal layers of the schematic's blocks, from bottom to top.
+    /// See [`Schematic::layer`].
+    pub fn layers(&self) -> impl Iterator<Item = Blocks> + '_ {
+        (0..self.size().1).map(|y| self.layer(y).unwrap())
+    }
+
+    /// Split this schematic into one schematic for each of its [`Schematic::regions`], in order. If
+    /// the schematic has no regions, it is returned as is.
+    ///
+    /// Each schematic's origin and paste offset are set to the position of its region, relative to
+    /// the paste offset of this schematic. Block entities and entities are moved into the last region
+    /// containing them, matching the order in which overlapping regions are merged when reading.
+    /// Blocks in overlapping parts of regions are the merged blocks and appear in every one of those
+    /// regions.
+    pub fn split_regions(self) -> Vec<Schematic> {
+        if self.regions.is_empty() {
+            return vec![self];
+        }
+
+        let contains = |region: &Region, pos: (u32, u32, u32)| {
+            let (x, y, z) = region.offset;
+            let (size_x, size_y, size_z) = region.size;
+            (x..x + size_x).contains(&pos.0)
+                && (y..y + size_y).contains(&pos.1)
+                && (z..z + size_z).contains(&pos.2)
+        };
+        let mut block_entities: Vec<HashMap<_, _>> =
+            self.regions.iter().map(|_| HashMap::new()).collect();
+        for (pos, block_entity) in self.block_entities {
+            let Some(i) = self.regions.iter().rposition(|r| contains(r, pos)) else {
+                continue;
+            };
+            let (x, y, z) = self.regions[i].offset;
+            block_entities[i].insert((pos.0 - x, pos.1 - y, pos.2 - z), block_entity);
+        }
+        let mut entities: Vec<Vec<Entity>> = self.regions.iter().map(|_| Vec::new()).collect();
+        for mut entity in self.entities {
+            let (x, y, z) = entity.pos;
+            if x < 0.0 || y < 0.0 || z < 0.0 {
+                continue;
+            }
+            let block_pos = (x as u32, y as u32, z as u32);
+            let Some(i) = self.regions.iter().rposition(|r| contains(r, block_pos)) else {
+                continue;
+            };
+            let (x, y, z) = self.regions[i].offset;
+            entity.pos.0 -= x as f64;
+            entity.pos.1 -= y as f64;
+            entity.pos.2 -= z as f64;
+            entities[i].push(entity);
+        }
+
+        let paste_offset = self.paste_offset.unwrap_or_default();
+        self.regions
+            .into_iter()
+            .zip(block_entities.into_iter().zip(entities))
+            .map(|(region, (block_entities, entities))| {
+                let (x, y, z) = region.offset;
+                let pos = (
+                    paste_offset.0 + x as i32,
+                    paste_offset.1 + y as i32,
+                    paste_offset.2 + z as i32,
+                );
+                let biomes = self.biomes.as_ref().map(|biomes| {
+                    // Biomes may be stored per column
+                    let (y, size_y) = if biomes.size().1 == 1 {
+                        (0, 1)
+                    } else {
+                        (y, region.size.1)
+                    };
+                    biomes.crop((x, y, z), (region.size.0, size_y, region.size.2))
+                });
+                Schematic {
+                    blocks: self.blocks.crop(region.offset, region.size),
+                    origin: Some(pos),
+                    paste_offset: Some(pos),
+                    biomes,
+                    data_version: self.data_version,
+                    block_entities,
+                    entities,
+                    metadata: self.metadata.clone(),
+                    sub_version: self.sub_version,
+                    root_title: self.root_title.clone(),
+                    regions: vec![Region {
+                        offset: (0, 0, 0),
+                        ..region
+                    }],
+                    source_format: self.source_format,
+                }
+            })
+            .collect()
+    }
+
+    /// Generate `setblock` commands that place the blocks of this schematic relative to the position
+    /// the commands are run from, taking the paste offset into account.
+    ///
+    /// If `include_air` is true, air is placed as well, which clears any existing blocks when
+    /// pasting over an existing build. Otherwise air is skipped, which results in fewer commands.
+    /// Block entity data is not included.
+    pub fn to_setblock_commands(&self, include_air: bool) -> Vec<String> {
+        let (dx, dy, dz) = self.paste_offset.unwrap_or_default();
+        self.blocks
+            .cells()
+            .filter(|(_, block)| include_air || *block != "minecraft:air")
+            .map(|((x, y, z), block)| {
+                format!(
+                    "setblock ~{} ~{} ~{} {block}",
+                    x as i32 + dx,
+                    y as i32 + dy,
+                    z as i32 + dz
+                )
+            })
+            .collect()
+    }
+
+    /// Generate `fill` and `setblock` commands that place the blocks of this schematic with its min
+    /// point at `origin`, in absolute world coordinates.
+    ///
+    /// Runs of identical blocks along the x axis are placed with a single `fill` command (see
+    /// [`Blocks::runs`]), and single blocks with `setblock`. Layers are placed from the bottom up.
+    /// Air is only placed if `include_air` is true. Block entities are left out of the runs and
+    /// placed afterwards by a `setblock` command including their NBT data, so containers keep their
+    /// contents.
+    pub fn to_fill_commands(&self, origin: (i32, i32, i32), include_air: bool) -> Vec<String> {
+        // The maximum number of blocks a single fill command may change
+        const MAX_FILL: u32 = 32768;
+
+        let (ox, oy, oz) = origin;
+        let mut commands = Vec::new();
+        let mut place = |start: u32, end: u32, y: u32, z: u32, block: &str| {
+            let (y, z) = (oy + y as i32, oz + z as i32);
+            for start in (start..end).step_by(MAX_FILL as usize) {
+                let last = end.min(start + MAX_FILL) - 1;
+                commands.push(if start == last {
+                    format!("setblock {} {y} {z} {block}", ox + start as i32)
+                } else {
+                    format!(
+                        "fill {} {y} {z} {} {y} {z} {block}",
+                        ox + start as i32,
+                        ox + last as i32
+                    )
+                });
+            }
+        };
+        for run in self.blocks.runs(AxisOrder::Yzx) {
+            if !include_air && run.block == "minecraft:air" {
+                continue;
+            }
+            let (start_x, y, z) = run.start;
+            let end_x = start_x + run.length;
+            let mut start = start_x;
+            for x in start_x..end_x {
+                if self.block_entities.contains_key(&(x, y, z)) {
+                    if start < x {
+                        place(start, x, y, z, run.block);
+                    }
+                    start = x + 1;
+                }
+            }
+            if start < end_x {
+                place(start, end_x, y, z, run.block);
+            }
+        }
+
+        for ((x, y, z), block_entity) in self.block_entities_sorted() {
+            let mut command = format!(
+                "setblock {} {} {} {}",
+                ox + *x as i32,
+                oy + *y as i32,
+                oz + *z as i32,
+                self.blocks.get_block_at(*x, *y, *z)
+            );
+            snbt::write_compound(&mut command, &block_entity.data);
+            commands.push(command);
+        }
+        commands
+    }
+
+    /// Export the blocks of this schematic as JSON, for consumers that don't understand NBT (such as
+    /// web based renderers). The output has the following shape:
+    ///
+    /// ```json
+    /// {"size":[2,1,1],"palette":["minecraft:air","minecraft:stone"],"blocks":[0,1]}
+    /// ```
+    ///
+    /// `blocks` contains the palette index of every block, ordered by x, then y, then z, so the
+    /// block at `(x, y, z)` is at index `x * size_y * size_z + y * size_z + z` (see
+    /// [`Blocks::pos_to_linear_index`]). Block entities, biomes, and metadata are not included.
+    pub fn to_json(&self) -> String {
+        json::to_json(self)
+    }
+
+    /// Decode the preview image Litematica stores in `Metadata.PreviewImageData`, such as to show a
+    /// thumbnail in a gallery without rendering the schematic.
+    ///
+    /// The image is stored as an int array with one ARGB pixel per entry, and it is always square.
+    /// Returns `None` if there is no preview or its length isn't a square number. The raw data is
+    /// kept in [`Schematic::metadata`], so it is written back when saving as Litematica.
+    pub fn preview_image(&self) -> Option<PreviewImage> {
+        let Some(nbt::Value::IntArray(data)) = self.metadata.as_ref()?.get("PreviewImageData")
+        else {
+            return None;
+        };
+        // The length is exact as a double, so the square root of a square number is exact as well
+        let size = (data.len() as f64).sqrt() as usize;
+        if size == 0 || size * size != data.len() {
+            return None;
+        }
+        let rgba = data
+            .iter()
+            .flat_map(|&argb| {
+                let [a, r, g, b] = argb.to_be_bytes();
+                [r, g, b, a]
+            })
+            .collect();
+        Some(PreviewImage {
+            size: size as u32,
+            rgba,
+        })
+    }
+
+    /// Compute a hash of the contents of this schematic.
+    ///
+    /// Only the size, the block at each position, and the block entities are hashed, so two
+    /// schematics with the same contents have the same hash regardless of the order of their
+    /// palettes. Metadata (such as the author or creation date), the data version, biomes, and the
+    /// origin and paste offset are not included. The hash does not depend on the platform or the
+    /// Rust version.
+    pub fn content_hash(&self) -> u64 {
+        hash::content_hash(self)
+    }
+
+    /// Serialize this schematic in the given format, read it back, and check that nothing was lost.
+    /// This is meant for testing the crate against a corpus of schematics.
+    ///
+    /// The size, the block at each position, the data version, the block entities, and the
+    /// entities (in order, with their positions and data) are compared. Like
+    /// [`Schematic::content_hash`], the order of the palette, biomes, metadata, and the origin and
+    /// paste offset are not compared. The first difference found is returned as
+    /// [`SchematicError::RoundTripMismatch`], and errors from serializing or reading are returned
+    /// as is.
+    pub fn verify_roundtrip(&self, format: SchematicFormat) -> Result<(), SchematicError> {
+        let read = Schematic::deserialize(&self.serialize(format)?)?;
+        let mismatch = |msg: String| Err(SchematicError::RoundTripMismatch(msg));
+
+        if read.size() != self.size() {
+            return mismatch(format!(
+                "size {:?} instead of {:?}",
+                read.size(),
+                self.size()
+            ));
+        }
+        if read.data_version != self.data_version {
+            return mismatch(format!(
+                "data version {:?} instead of {:?}",
+                read.data_version, self.data_version
+            ));
+        }
+        // Comparing the hashes is cheaper than comparing every block and block entity
+        if read.content_hash() != self.content_hash() {
+            for ((pos, block), (_, read_block)) in self.blocks.cells().zip(read.blocks.cells()) {
+                if block != read_block {
+                    return mismatch(format!(
+                        "block at {pos:?} is {read_block} instead of {block}"
+                    ));
+                }
+            }
+            for (pos, block_entity) in self.block_entities_sorted() {
+                match read.block_entities.get(pos) {
+                    None => return mismatch(format!("block entity at {pos:?} is missing")),
+                    Some(read_block_entity)
+                        if read_block_entity.id != block_entity.id
+                            || read_block_entity.data != block_entity.data =>
+                    {
+                        return mismatch(format!("block entity at {pos:?} differs"));
+                    }
+                    Some(_) => {}
+                }
+            }
+            if let Some(pos) = read
+                .block_entities
+                .keys()
+                .find(|pos| !self.block_entities.contains_key(pos))
+            {
+                return mismatch(format!("unexpected block entity at {pos:?}"));
+            }
+        }
+
+        if read.entities.len() != self.entities.len() {
+            return mismatch(format!(
+                "{} entities instead of {}",
+                read.entities.len(),
+                self.entities.len()
+            ));
+        }
+        for (i, (entity, read_entity)) in self.entities.iter().zip(&read.entities).enumerate() {
+            if read_entity.id != entity.id
+                || read_entity.pos != entity.pos
+                || read_entity.data != entity.data
+            {
+                return mismatch(format!("entity {i} ({}) differs", entity.id));
+            }
+        }
+        Ok(())
+    }
+
+    /// Deserialize a schematic from a raw byte slice.
+    ///
+    /// This function will attempt to detect which format the schematic is encoded in. If the format
+    /// cannot be recognized, [`SchematicError::UnrecognizedFormat`] is returned. Not all
+    /// schematic formats representable with [`SchematicFormat`] are deserializable. In that case,
+    /// [`SchematicError::UnsupportedFormat`] is returned. The data may be gzip or zlib compressed.
+    pub fn deserialize(data: &[u8]) -> Result<Schematic, SchematicError> {
+        let nbt = Self::read_nbt(data)?;
+        Self::deserialize_blob(&nbt, ReadOptions::new(&mut |_, _| {}))
+    }
+
+    /// Deserialize a schematic from a raw byte slice, reporting progress while decoding the blocks.
+    ///
+    /// `progress` is called with the number of blocks decoded so far and the total number of
+    /// blocks, at least once per layer of the schematic. Otherwise this behaves like
+    /// [`Schematic::deserialize`].
+    pub fn deserialize_with_progress(
+        data: &[u8],
+        progress: &mut impl FnMut(u64, u64),
+    ) -> Result<Schematic, SchematicError> {
+        let nbt = Self::read_nbt(data)?;
+        Self::deserialize_blob(&nbt, ReadOptions::new(progress))
+    }
+
+    /// Deserialize a schematic from a raw byte slice, refusing to load schematics with more than
+    /// `max_cells` blocks.
+    ///
+    /// The size of the schematic is checked before any block data is decoded or allocated, so this
+    /// can safely be used on untrusted input. If the schematic is too large,
+    /// [`SchematicError::TooLarge`] is returned. Otherwise this behaves like
+    /// [`Schematic::deserialize`].
+    pub fn deserialize_with_limit(
+        data: &[u8],
+        max_cells: u64,
+    ) -> Result<Schematic, SchematicError> {
+        let nbt = Self::read_nbt(data)?;
+        Self::deserialize_blob(
+            &nbt,
+            ReadOptions {
+                max_cells: Some(max_cells),
+                ..ReadOptions::new(&mut |_, _| {})
+            },
+        )
+    }
+
+    /// Deserialize only the blocks of a schematic from a raw byte slice.
+    ///
+    /// Block entities, entities, and biomes are skipped entirely, which is faster when only the
+    /// blocks are needed. Otherwise this behaves like [`Schematic::deserialize`].
+    pub fn deserialize_blocks_only(data: &[u8]) -> Result<Blocks, SchematicError> {
+        let nbt = Self::read_nbt(data)?;
+        Ok(Self::deserialize_blob(
+            &nbt,
+            ReadOptions {
+                blocks_only: true,
+                ..ReadOptions::new(&mut |_, _| {})
+            },
+        )?
+        .blocks)
+    }
+
+    /// Deserialize a schematic from a raw byte slice, keeping the palette exactly as stored in the
+    /// file.
+    ///
+    /// By default, `minecraft:air` is put at index 0 of the palette of Sponge schematics, as the
+    /// background block. With this, the palette is instead ordered by the ids in the file, so the
+    /// index of every entry is the same as in the file as long as the ids have no gaps. For
+    /// Litematica schematics, the palette of the first region is kept as is. That is the first
+    /// region in the order written by this library, or else the one whose name sorts first.
+    /// Unused entries are kept in both cases. Otherwise this behaves like [`Schematic::deserialize`].
+    pub fn deserialize_preserve_palette(data: &[u8]) -> Result<Schematic, SchematicError> {
+        let nbt = Self::read_nbt(data)?;
+        Self::deserialize_blob(
+            &nbt,
+            ReadOptions {
+                preserve_palette: true,
+                ..ReadOptions::new(&mut |_, _| {})
+            },
+        )
+    }
+
+    /// Deserialize a schematic from a raw byte slice, renaming the palette entries in `aliases` to
+    /// `minecraft:air` instead of those in [`AIR_ALIASES`].
+    ///
+    /// Pass an empty list to keep every palette entry as it is spelled in the file. Otherwise this
+    /// behaves like [`Schematic::deserialize`].
+    pub fn deserialize_with_air_aliases(
+        data: &[u8],
+        aliases: &[&str],
+    ) -> Result<Schematic, SchematicError> {
+        let nbt = Self::read_nbt(data)?;
+        Self::deserialize_blob(
+            &nbt,
+            ReadOptions {
+                air_aliases: aliases,
+                ..ReadOptions::new(&mut |_, _| {})
+            },
+        )
+    }
+
+    /// Deserialize a schematic from a raw byte slice, sorting the properties of every block state
+    /// by name.
+    ///
+    /// The same block state written with properties in a different order ends up as a single
+    /// palette entry, see [`Blocks::canonicalize_block_states`]. By default, block states are kept
+    /// as they are written in the file. Otherwise this behaves like [`Schematic::deserialize`].
+    pub fn deserialize_canonical(data: &[u8]) -> Result<Schematic, SchematicError> {
+        let nbt = Self::read_nbt(data)?;
+        Self::deserialize_blob(
+            &nbt,
+            ReadOptions {
+                canonicalize_block_states: true,
+                ..ReadOptions::new(&mut |_, _| {})
+            },
+        )
+    }
+
+    /// Deserialize a schematic from a raw byte slice, accepting Sponge schematics with incorrectly
+    /// cased tags.
+    ///
+    /// Some non-conformant tools write tags such as `width` instead of `Width`. If one of the
+    /// dimension or palette tags is missing, a tag with the same name in a different case is used
+    /// instead. All other tags must still be cased correctly. Otherwise this behaves like
+    /// [`Schematic::deserialize`], which is strict about the casing of all tags.
+    pub fn deserialize_lenient(data: &[u8]) -> Result<Schematic, SchematicError> {
+        let mut nbt = Self::read_nbt(data)?;
+        sponge::fix_tag_case(&mut nbt.content);
+        Self::deserialize_blob(&nbt, ReadOptions::new(&mut |_, _| {}))
+    }
+
+    /// Deserialize a schematic stored as the entry with the given name in a zip archive. The format
+    /// is detected the same way as in [`Schematic::deserialize`].
+    #[cfg(feature = "zip")]
+    pub fn from_zip_entry<R: std::io::Read + std::io::Seek>(
+        archive: R,
+        entry_name: &str,
+    ) -> Result<Schematic, SchematicError> {
+        use std::io::Read;
+
+        let mut archive = zip::ZipArchive::new(archive)?;
+        let mut entry = archive.by_name(entry_name)?;
+        let mut data = Vec::new();
+        entry
+            .read_to_end(&mut data)
+            .map_err(zip::result::ZipError::from)?;
+        Self::deserialize(&data)
+    }
+
+    /// Deserialize a schematic nested somewhere inside of already parsed NBT.
+    ///
+    /// `path` is a dot separated list of compound names leading to the compound that contains the
+    /// schematic (e.g. `data.schematic`). An empty path refers to the root compound. The format of
+    /// the schematic is detected the same way as in [`Schematic::deserialize`].
+    pub fn deserialize_from_nbt(nbt: &nbt::Blob, path: &str) -> Result<Schematic, SchematicError> {
+        if path.is_empty() {
+            return Self::deserialize_blob(nbt, ReadOptions::new(&mut |_, _| {}));
+        }
+
+        let mut compound = &nbt.content;
+        let mut title = "";
+        for name in path.split('.') {
+            compound = match compound.get(name) {
+                Some(nbt::Value::Compound(compound)) => compound,
+                Some(_) => return Err(SchematicError::MistypedField(path.to_owned())),
+                None => return Err(SchematicError::MissingRequiredField(path.to_owned())),
+            };
+            title = name;
+        }
+        let nested = nbt::Blob {
+            title: title.to_owned(),
+            content: compound.clone(),
+        };
+        Self::deserialize_blob(&nested, ReadOptions::new(&mut |_, _| {}))
+    }
+
+    /// Read the compressed NBT of a schematic. Schematics are usually gzip compressed, but some
+    /// WorldEdit forks write zlib compressed files instead.
+    ///
+    /// Files mangled by text mode transfers can start with a UTF-8 BOM or a few stray bytes, which
+    /// are skipped if a gzip header follows within the first [`MAX_JUNK_PREFIX`] bytes.
+    fn read_nbt(data: &[u8]) -> Result<nbt::Blob, SchematicError> {
+        let data = data.strip_prefix(b"\xef\xbb\xbf").unwrap_or(data);
+        // A zlib header starts with the deflate method and a checksum making it a multiple of 31
+        let nbt = match data {
+            [cmf, flg, ..] if cmf & 0x0f == 8 && (*cmf as u16 * 256 + *flg as u16) % 31 == 0 => {
+                nbt::Blob::from_zlib_reader(&mut std::io::Cursor::new(data))?
+            }
+            _ => nbt::Blob::from_gzip_reader(&mut std::io::Cursor::new(skip_junk_prefix(data)))?,
+        };
+        Ok(nbt)
+    }
+
+    fn deserialize_blob(
+        nbt: &nbt::Blob,
+        options: ReadOptions,
+    ) -> Result<Schematic, SchematicError> {
+        let preserve_palette = options.preserve_palette;
+        let air_aliases = options.air_aliases;
+        let canonicalize = options.canonicalize_block_states;
+        let mut schem = Self::deserialize_format(nbt, options)?;
+        if !preserve_palette {
+            schem.blocks.normalize_air(air_aliases);
+        }
+        if canonicalize {
+            schem.blocks.canonicalize_block_states();
+        }
+        Ok(schem)
+    }
+
+    fn deserialize_format(
+        nbt: &nbt::Blob,
+        options: ReadOptions,
+    ) -> Result<Schematic, SchematicError> {
+        if nbt.get("Regions").is_some() {
+            // This is a Litematica schematic, which checks its own version
+            return litematica::deserialize(nbt, options);
+        }
+        // Sponge v3 and newer stores the Schematic schema in a nested tag. This is checked first
+        // since some tools add sibling tags, such as their own version, to the root.
+        if is_sponge_v3(&nbt.content) {
+            return sponge::deserialize(nbt, 3, options);
+        }
+        if let Some(nbt::Value::Int(version)) = nbt.get("Version") {
+            let version = *version as u32;
+            if matches!(version, 1 | 2) {
+                // This is a Sponge schematic (v1 or v2)
+                return sponge::deserialize(nbt, version, options);
+            }
+        }
+        // Some tools wrap the nested Schematic compound of v3 in another compound
+        let wrapper = nbt.content.values().find_map(|value| match value {
+            nbt::Value::Compound(wrapper) if is_sponge_v3(wrapper) => Some(wrapper),
+            _ => None,
+        });
+        if let Some(wrapper) = wrapper {
+            let unwrapped = nbt::Blob {
+                title: nbt.title.clone(),
+                content: wrapper.clone(),
+            };
+            return sponge::deserialize(&unwrapped, 3, options);
+        }
+
+        Err(SchematicError::UnrecognizedFormat)
+    }
+
+    /// Estimate the size in bytes of this schematic once serialized, before compression is
+    /// applied.
+    ///
+    /// This is only an approximation meant to cheaply reject schematics that are too large; it is
+    /// not the exact size [`Schematic::serialize`] would produce. Because the output of
+    /// [`Schematic::serialize`] is compressed, the actual size is usually much smaller. For
+    /// Litematica, every region is assumed to use the whole palette, so schematics with many
+    /// regions are overestimated. Formats that cannot be serialized return
+    /// [`SchematicError::UnsupportedFormat`].
+    pub fn estimate_serialized_size(
+        &self,
+        format: SchematicFormat,
+    ) -> Result<usize, SchematicError> {
+        match format {
+            SchematicFormat::Sponge(version @ (2 | 3)) => Ok(sponge::estimate_size(self, version)),
+            SchematicFormat::Litematica(6) => Ok(litematica::estimate_size(self)),
+            SchematicFormat::Schematica(SchematicaFormat::Alpha) => {
+                Ok(schematica::estimate_alpha_size(self))
+            }
+            _ => Err(SchematicError::UnsupportedFormat(format)),
+        }
+    }
+
+    /// Upgrade all blocks with numeric ids from before Minecraft 1.13 (see
+    /// [`is_legacy_block_name`]) to modern block states.
+    ///
+    /// `upgrade` is given each legacy block name and should return the modern block state it
+    /// corresponds to. [`upgrade_legacy_block`] implements this for common blocks. If it returns
+    /// `None` for any block that is placed somewhere, [`SchematicError::LegacyBlock`] is returned
+    /// and the schematic is left unchanged. Unused palette entries that can't be upgraded and
+    /// blocks that aren't legacy are kept as is.
+    pub fn upgrade_legacy_blocks(
+        &mut self,
+        mut upgrade: impl FnMut(&str) -> Option<String>,
+    ) -> Result<(), SchematicError> {
+        let used = self.blocks.used_ids();
+        // The palette entries are renamed in order
+        let mut id = 0;
+        self.blocks.rename_palette(|block| {
+            id += 1;
+            if !is_legacy_block_name(block) {
+                return Ok(block.to_owned());
+            }
+            match upgrade(block) {
+                Some(upgraded) => Ok(upgraded),
+                None if used[id - 1] => Err(SchematicError::LegacyBlock(block.to_owned())),
+                None => Ok(block.to_owned()),
+            }
+        })
+    }
+
+    /// Replace the block at every position with the result of `f`, building a new palette that only
+    /// contains the blocks that are actually used.
+    ///
+    /// `f` is called at most once for each distinct block, since the result only depends on the
+    /// block name. The background block is mapped as well, even if no position uses it.
+    pub fn map_blocks<F: FnMut(&str) -> String>(&mut self, mut f: F) {
+        let old = &self.blocks;
+        let (size_x, size_y, size_z) = old.size();
+        let mut blocks = Blocks::new(size_x, size_y, size_z, &f(old.background_block()));
+        let mut cache: Vec<Option<u32>> = vec![None; old.palette.len()];
+        cache[old.background_id() as usize] = Some(blocks.background_id());
+        for (idx, &id) in old.indices.iter().enumerate() {
+            let new_id = match cache[id as usize] {
+                Some(new_id) => new_id,
+                None => {
+                    let new_id = blocks.get_block_id_for(&f(&old.palette[id as usize]));
+                    cache[id as usize] = Some(new_id);
+                    new_id
+                }
+            };
+            blocks.indices[idx] = new_id;
+        }
+        if old.non_air.is_some() {
+            blocks.track_non_air();
+        }
+        self.blocks = blocks;
+    }
+
+    /// Replace all air with structure voids. Vanilla structures use `minecraft:structure_void` for
+    /// cells that should be left untouched when loading, which is what air means in schematics.
+    pub fn air_to_structure_void(&mut self) {
+        self.replace_block("minecraft:air", "minecraft:structure_void");
+    }
+
+    /// Replace all structure voids with air. This is the inverse of
+    /// [`Schematic::air_to_structure_void`].
+    pub fn structure_void_to_air(&mut self) {
+        self.replace_block("minecraft:structure_void", "minecraft:air");
+    }
+
+    fn replace_block(&mut self, from: &str, to: &str) {
+        // The renaming function never fails
+        self.blocks
+            .rename_palette(|block| Ok(if block == from { to } else { block }.to_owned()))
+            .unwrap();
+    }
+
+    /// Convert all blocks to numeric ids from before Minecraft 1.13 (such as `35:14`), which is
+    /// required to write [`SchematicaFormat::Alpha`]. This is the inverse of
+    /// [`Schematic::upgrade_legacy_blocks`].
+    ///
+    /// `downgrade` is given each modern block state and should return the legacy block it
+    /// corresponds to. [`downgrade_block`] implements this for common blocks. If it returns `None`
+    /// for any block that is placed somewhere, [`SchematicError::NoLegacyId`] is returned and the
+    /// schematic is left unchanged. Air, blocks that are legacy already, and unused palette
+    /// entries that can't be downgraded are kept as is.
+    pub fn downgrade_blocks(
+        &mut self,
+        mut downgrade: impl FnMut(&str) -> Option<String>,
+    ) -> Result<(), SchematicError> {
+        let used = self.blocks.used_ids();
+        // The palette entries are renamed in order
+        let mut id = 0;
+        self.blocks.rename_palette(|block| {
+            id += 1;
+            if block == "minecraft:air" || is_legacy_block_name(block) {
+                return Ok(block.to_owned());
+            }
+            match downgrade(block) {
+                Some(downgraded) => Ok(downgraded),
+                None if used[id - 1] => Err(SchematicError::NoLegacyId(block.to_owned())),
+                None => Ok(block.to_owned()),
+            }
+        })
+    }
+
+    /// Convert the schematic file at `src` to `format` and write it to `dst`.
+    ///
+    /// Blocks with legacy numeric ids (see [`is_legacy_block_name`]) are upgraded with
+    /// [`upgrade_legacy_block`] unless converting to [`SchematicaFormat::Alpha`], which can only
+    /// store legacy blocks. If a block that is placed somewhere has no modern equivalent,
+    /// [`SchematicError::LegacyBlock`] is returned and nothing is written.
+    ///
+    /// Reading is not streamed: the whole file is read and parsed into NBT, which is decoded into
+    /// a full [`Schematic`] with one palette index per cell and then dropped. Writing is streamed:
+    /// the output NBT is built, the schematic is dropped, and the NBT is compressed directly into
+    /// `dst`. Block data is decoded straight into the schematic and encoded straight into the
+    /// output NBT, without any other buffer holding an entry per cell. The peak memory use is
+    /// therefore the larger of the parsed input NBT plus the schematic, and the schematic plus the
+    /// output NBT. Compared to [`Schematic::deserialize`] and [`Schematic::serialize`], this only
+    /// saves the compressed output. Use [`Schematic::deserialize_with_limit`] and
+    /// [`Schematic::serialize`] directly to reject files that are too large first.
+    pub fn convert_file(
+        src: impl AsRef<std::path::Path>,
+        dst: impl AsRef<std::path::Path>,
+        format: SchematicFormat,
+    ) -> Result<(), SchematicError> {
+        use std::io::Write;
+
+        let mut schem = {
+            let nbt = Self::read_nbt(&std::fs::read(src)?)?;
+            Self::deserialize_blob(&nbt, ReadOptions::new(&mut |_, _| {}))?
+        };
+        if format != SchematicFormat::Schematica(SchematicaFormat::Alpha) {
+            schem.upgrade_legacy_blocks(upgrade_legacy_block)?;
+        }
+        let root = schem.to_nbt(format)?;
+        drop(schem);
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(dst)?);
+        root.to_gzip_writer(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Serialize a schematic into raw bytes.
+    ///
+    /// Not all schematic formats representable with [`SchematicFormat`] are serializable. In that
+    /// case, [`SchematicError::UnsupportedFormat`] is returned. If any block entity is positioned
+    /// outside of the schematic's bounds, [`SchematicError::InvalidValue`] is returned. Blocks with
+    /// legacy numeric ids cause [`SchematicError::LegacyBlock`] to be returned; use
+    /// [`Schematic::upgrade_legacy_blocks`] to convert them first, which
+    /// [`Schematic::convert_file`] does by itself. Unused palette entries with legacy ids are left
+    /// out of the palette.
+    ///
+    /// [`SchematicaFormat::Alpha`] is the exception, since it can only store legacy blocks. Use
+    /// [`Schematic::downgrade_blocks`] to convert blocks first, otherwise
+    /// [`SchematicError::NoLegacyId`] is returned for any modern block other than air that is
+    /// placed somewhere.
+    pub fn serialize(&self, format: SchematicFormat) -> Result<Vec<u8>, SchematicError> {
+        let root = self.to_nbt(format)?;
+        let mut data = Vec::new();
+        root.to_gzip_writer(&mut data)?;
+        Ok(data)
+    }
+
+    /// Build the NBT tree that [`Schematic::serialize`] would write, before it is compressed.
+    ///
+    /// This can be used to inspect the output or to add custom tags before compressing it
+    /// yourself. The same errors as for [`Schematic::serialize`] are returned.
+    pub fn to_nbt(&self, format: SchematicFormat) -> Result<nbt::Blob, SchematicError> {
+        let (size_x, size_y, size_z) = self.size();
+        if self
+            .block_entities
+            .keys()
+            .any(|&(x, y, z)| x >= size_x || y >= size_y || z >= size_z)
+        {
+            return Err(SchematicError::InvalidValue("BlockEntities".to_owned()));
+        }
+        if let SchematicFormat::Schematica(SchematicaFormat::Alpha) = format {
+            return schematica::serialize_alpha(self);
+        }
+        // All other supported formats require modern block states, so writing legacy blocks would
+        // only produce a file that fails to paste. Unused entries are left out of the palette by
+        // the writers.
+        let used = self.blocks.used_ids();
+        for (id, block) in self.blocks.blocks_in_palette().enumerate() {
+            if used[id] && is_legacy_block_name(block) {
+                return Err(SchematicError::LegacyBlock(block.to_owned()));
+            }
+        }
+
+        match format {
+            SchematicFormat::Sponge(version) => {
+                if !matches!(version, 2 | 3) {
+                    return Err(SchematicError::UnsupportedFormat(format));
+                }
+
+                sponge::serialize(self, version)
+            }
+            SchematicFormat::Litematica(version) => {
+                if version != 6 {
+                    return Err(SchematicError::UnsupportedFormat(format));
+                }
+
+                litematica::serialize(self, version)
+            }
+            _ => Err(SchematicError::UnsupportedFormat(format)),
+        }
     }
 }
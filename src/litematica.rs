@@ -0,0 +1,713 @@
+use super::{
+    compound_size, named_tag_size, parse_block_state, read_entity_pos, read_palette_entry,
+    write_entity_pos, BlockEntity, Blocks, Entity, ReadOptions, Schematic, SchematicError,
+    SchematicFormat,
+};
+use nbt::Value;
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The sub-version written alongside the schematic version if none was read
+const SUB_VERSION: i32 = 1;
+
+/// The NBT library keeps compound entries in a `HashMap`, so the order of the `Regions` compound in
+/// the file is lost when parsing. The region names are additionally written to a list under this
+/// root tag, which Litematica ignores, so the order survives a round trip through this library.
+const REGION_ORDER_KEY: &str = "RegionOrder";
+
+fn read_vec3(nbt: &HashMap<String, Value>, name: &str) -> Result<(i32, i32, i32), SchematicError> {
+    let vec = required_nbt!(nbt, name, Compound);
+    Ok((
+        *required_nbt!(vec, "x", Int),
+        *required_nbt!(vec, "y", Int),
+        *required_nbt!(vec, "z", Int),
+    ))
+}
+
+/// Read an entry from palette indices packed into longs. If `straddle` is true, entries are packed
+/// tightly and may span across two longs. Otherwise, every long holds as many whole entries as fit
+/// and the remaining bits are unused, like chunks since Minecraft 1.16.
+fn unpack_block_state(longs: &[i64], bits: u32, index: usize, straddle: bool) -> Option<u32> {
+    let (idx, offset) = if straddle {
+        let start = index * bits as usize;
+        (start / 64, start % 64)
+    } else {
+        let per_long = 64 / bits as usize;
+        (index / per_long, index % per_long * bits as usize)
+    };
+    let mask = (1u64 << bits) - 1;
+    let mut val = (*longs.get(idx)? as u64) >> offset;
+    if offset + bits as usize > 64 {
+        val |= (*longs.get(idx + 1)? as u64) << (64 - offset);
+    }
+    Some((val & mask) as u32)
+}
+
+/// A sub-region of a Litematica schematic, with its bounds relative to the schematic's origin
+struct Region<'a> {
+    name: &'a str,
+    nbt: &'a HashMap<String, Value>,
+    min: (i32, i32, i32),
+    size: (u32, u32, u32),
+}
+
+fn read_region<'a>(
+    name: &'a str,
+    nbt: &'a HashMap<String, Value>,
+) -> Result<Region<'a>, SchematicError> {
+    let pos = read_vec3(nbt, "Position")?;
+    let size = read_vec3(nbt, "Size")?;
+    // Sizes can be negative, in which case the region extends from the position in the negative
+    // direction. Both ends of the region have to fit into an int.
+    let axis = |pos: i32, size: i32| {
+        let min = if size < 0 {
+            pos as i64 + size as i64 + 1
+        } else {
+            pos as i64
+        };
+        let size = size.unsigned_abs();
+        match i32::try_from(min) {
+            Ok(min) if min as i64 + size as i64 <= i32::MAX as i64 + 1 => Ok((min, size)),
+            _ => Err(SchematicError::InvalidValue("Size".to_owned())),
+        }
+    };
+    let (min_x, size_x) = axis(pos.0, size.0)?;
+    let (min_y, size_y) = axis(pos.1, size.1)?;
+    let (min_z, size_z) = axis(pos.2, size.2)?;
+    Ok(Region {
+        name,
+        nbt,
+        min: (min_x, min_y, min_z),
+        size: (size_x, size_y, size_z),
+    })
+}
+
+pub fn deserialize(nbt: &nbt::Blob, options: ReadOptions) -> Result<Schematic, SchematicError> {
+    // Check the version before anything else, so files of other versions are reported as such
+    // rather than failing on whichever tag changed
+    let version = *required_nbt!(nbt, "Version", Int);
+    let version = match u32::try_from(version) {
+        Ok(version) if (4..=7).contains(&version) => version,
+        _ => {
+            return Err(SchematicError::UnsupportedFormat(
+                SchematicFormat::Litematica(version as u32),
+            ))
+        }
+    };
+    let data_version = typed_nbt!(nbt, "MinecraftDataVersion", Int).map(|v| *v as u32);
+    let sub_version = typed_nbt!(nbt, "SubVersion", Int).copied();
+    let metadata = typed_nbt!(nbt, "Metadata", Compound)
+        .cloned()
+        .unwrap_or_default();
+
+    // The order of the regions in the file is lost when parsing (see `REGION_ORDER_KEY`), so the
+    // order list written by this library is used if it exists. Regions missing from it, such as
+    // all regions of files saved by Litematica itself, follow in order of their names to keep the
+    // result deterministic where regions overlap.
+    let nbt_regions = required_nbt!(nbt, "Regions", Compound);
+    let mut names: Vec<&String> = Vec::with_capacity(nbt_regions.len());
+    if let Some(order) = typed_nbt!(nbt, REGION_ORDER_KEY, List) {
+        for name in order {
+            if let Value::String(name) = name {
+                if let Some((name, _)) = nbt_regions.get_key_value(name) {
+                    if !names.contains(&name) {
+                        names.push(name);
+                    }
+                }
+            }
+        }
+    }
+    let mut unordered: Vec<&String> = nbt_regions
+        .keys()
+        .filter(|name| !names.contains(name))
+        .collect();
+    unordered.sort();
+    names.extend(unordered);
+    let mut regions = Vec::new();
+    for name in names {
+        let Value::Compound(region) = &nbt_regions[name] else {
+            return Err(SchematicError::MistypedField(name.clone()));
+        };
+        regions.push(read_region(name, region)?);
+    }
+
+    // All regions are merged into one container enclosing all of them. The max corner of a region
+    // may be just past `i32::MAX`, so it is computed with longs.
+    let mut min = (i32::MAX, i32::MAX, i32::MAX);
+    let mut max = (i64::MIN, i64::MIN, i64::MIN);
+    for region in &regions {
+        min.0 = min.0.min(region.min.0);
+        min.1 = min.1.min(region.min.1);
+        min.2 = min.2.min(region.min.2);
+        max.0 = max.0.max(region.min.0 as i64 + region.size.0 as i64);
+        max.1 = max.1.max(region.min.1 as i64 + region.size.1 as i64);
+        max.2 = max.2.max(region.min.2 as i64 + region.size.2 as i64);
+    }
+    if regions.is_empty() {
+        min = (0, 0, 0);
+        max = (0, 0, 0);
+    }
+    let axis_size = |min: i32, max: i64| {
+        u32::try_from(max - min as i64).map_err(|_| SchematicError::InvalidValue("Size".to_owned()))
+    };
+    let size = (
+        axis_size(min.0, max.0)?,
+        axis_size(min.1, max.1)?,
+        axis_size(min.2, max.2)?,
+    );
+    // Blocks are indexed with ints, so larger containers can't be represented at all
+    let cells = (size.0 as u64)
+        .checked_mul(size.1 as u64)
+        .and_then(|cells| cells.checked_mul(size.2 as u64))
+        .unwrap_or(u64::MAX);
+    let limit = options.max_cells.unwrap_or(u64::MAX).min(u32::MAX as u64);
+    if cells > limit {
+        return Err(SchematicError::TooLarge { cells, limit });
+    }
+
+    // Check that every region has enough block data before allocating anything, so small inputs
+    // can't claim huge regions
+    let mut layouts = Vec::with_capacity(regions.len());
+    for region in &regions {
+        let palette = required_nbt!(region.nbt, "BlockStatePalette", List);
+        let longs = required_nbt!(region.nbt, "BlockStates", LongArray);
+        let bits = (usize::BITS - palette.len().saturating_sub(1).leading_zeros()).max(2);
+        // The region fits into the container, so this can't overflow
+        let volume = region.size.0 as u64 * region.size.1 as u64 * region.size.2 as u64;
+        // Litematica itself always packs entries tightly, but some tools writing Litematica files
+        // don't let entries span across longs. The two only differ if the number of bits doesn't
+        // divide 64, in which case the tight packing needs fewer longs, so the scheme is guessed
+        // from the length of the array.
+        let straddled_len = (volume * bits as u64).div_ceil(64);
+        let aligned_len = volume.div_ceil(64 / bits as u64);
+        let len = longs.len() as u64;
+        if len < straddled_len {
+            return Err(SchematicError::InvalidValue("BlockStates".to_owned()));
+        }
+        let straddle = len < aligned_len || len == straddled_len;
+        layouts.push((palette, longs, bits, straddle));
+    }
+
+    let total = regions
+        .iter()
+        .map(|r| r.size.0 as u64 * r.size.1 as u64 * r.size.2 as u64)
+        .sum();
+    let mut done = 0;
+
+    let mut block_entities = HashMap::new();
+    let mut entities = Vec::new();
+    // Palettes of Litematica regions start with air, but that isn't guaranteed when preserving
+    // the palette of the first region
+    let background = match regions.first() {
+        Some(region) if options.preserve_palette => {
+            match required_nbt!(region.nbt, "BlockStatePalette", List).first() {
+                Some(entry) => read_palette_entry("BlockStatePalette", entry)?,
+                None => "minecraft:air".to_owned(),
+            }
+        }
+        _ => "minecraft:air".to_owned(),
+    };
+    let mut blocks = Blocks::new(size.0, size.1, size.2, &background);
+    let mut schem_regions = Vec::with_capacity(regions.len());
+    for (region, (nbt_palette, longs, bits, straddle)) in regions.into_iter().zip(layouts) {
+        let offset = (
+            region.min.0.abs_diff(min.0),
+            region.min.1.abs_diff(min.1),
+            region.min.2.abs_diff(min.2),
+        );
+        let (size_x, size_y, size_z) = region.size;
+        schem_regions.push(crate::Region {
+            name: region.name.to_owned(),
+            offset,
+            size: region.size,
+        });
+
+        let mut palette = Vec::with_capacity(nbt_palette.len());
+        for entry in nbt_palette {
+            palette.push(blocks.get_block_id_for(&read_palette_entry("BlockStatePalette", entry)?));
+        }
+
+        let mut index = 0;
+        // Litematica orders blocks by y, then z, then x
+        for y in 0..size_y {
+            for z in 0..size_z {
+                for x in 0..size_x {
+                    let id = unpack_block_state(longs, bits, index, straddle)
+                        .and_then(|id| palette.get(id as usize))
+                        .ok_or_else(|| SchematicError::InvalidValue("BlockStates".to_owned()))?;
+                    blocks.set_block_id_at(offset.0 + x, offset.1 + y, offset.2 + z, *id);
+                    index += 1;
+                }
+            }
+            done += size_x as u64 * size_z as u64;
+            (options.progress)(done, total);
+        }
+
+        if options.blocks_only {
+            continue;
+        }
+
+        let tile_entities = typed_nbt!(region.nbt, "TileEntities", List)
+            .map(|l| l.as_slice())
+            .unwrap_or_default();
+        for tile_entity in tile_entities {
+            let Value::Compound(tile_entity) = tile_entity else {
+                return Err(SchematicError::MistypedField("TileEntities".to_owned()));
+            };
+            let mut data = tile_entity.clone();
+            let mut pos = [0; 3];
+            for (i, axis) in ["x", "y", "z"].into_iter().enumerate() {
+                let val = *required_nbt!(tile_entity, axis, Int);
+                let region_size = [size_x, size_y, size_z][i];
+                pos[i] = match u32::try_from(val) {
+                    Ok(val) if val < region_size => val + [offset.0, offset.1, offset.2][i],
+                    _ => return Err(SchematicError::InvalidValue("TileEntities".to_owned())),
+                };
+                data.remove(axis);
+            }
+            let pos = (pos[0], pos[1], pos[2]);
+            // Not all versions store the id, so fall back to the name of the block
+            let id = match data.remove("id") {
+                Some(Value::String(id)) => id,
+                Some(_) => return Err(SchematicError::MistypedField("id".to_owned())),
+                None => {
+                    let block = blocks.get_block_at(pos.0, pos.1, pos.2);
+                    block
+                        .split_once('[')
+                        .map_or(block, |(name, _)| name)
+                        .to_owned()
+                }
+            };
+            block_entities.insert(pos, BlockEntity { id, data });
+        }
+
+        let nbt_entities = typed_nbt!(region.nbt, "Entities", List)
+            .map(|l| l.as_slice())
+            .unwrap_or_default();
+        for entity in nbt_entities {
+            let Value::Compound(entity) = entity else {
+                return Err(SchematicError::MistypedField("Entities".to_owned()));
+            };
+            // Entity positions are relative to the region
+            let pos = read_entity_pos(entity)?;
+            let pos = (
+                pos.0 + offset.0 as f64,
+                pos.1 + offset.1 as f64,
+                pos.2 + offset.2 as f64,
+            );
+            let mut data = entity.clone();
+            data.remove("Pos");
+            let id = match data.remove("id") {
+                Some(Value::String(id)) => id,
+                Some(_) => return Err(SchematicError::MistypedField("id".to_owned())),
+                None => return Err(SchematicError::MissingRequiredField("id".to_owned())),
+            };
+            entities.push(Entity { id, pos, data });
+        }
+    }
+
+    Ok(Schematic {
+        blocks,
+        origin: None,
+        paste_offset: Some(min),
+        biomes: None,
+        data_version,
+        block_entities,
+        entities,
+        metadata: if metadata.is_empty() {
+            None
+        } else {
+            Some(metadata)
+        },
+        sub_version,
+        root_title: Some(nbt.title.clone()),
+        regions: schem_regions,
+        source_format: Some(SchematicFormat::Litematica(version)),
+    })
+}
+
+fn to_int(name: &str, val: u32) -> Result<Value, SchematicError> {
+    let val = val
+        .try_into()
+        .map_err(|_| SchematicError::InvalidValue(name.to_owned()))?;
+    Ok(Value::Int(val))
+}
+
+fn write_vec3(name: &str, (x, y, z): (u32, u32, u32)) -> Result<Value, SchematicError> {
+    let mut nbt = HashMap::new();
+    nbt.insert("x".to_owned(), to_int(name, x)?);
+    nbt.insert("y".to_owned(), to_int(name, y)?);
+    nbt.insert("z".to_owned(), to_int(name, z)?);
+    Ok(Value::Compound(nbt))
+}
+
+/// Split a block state such as `minecraft:repeater[delay=2,facing=north]` into its name and
+/// properties
+fn write_block_state(block: &str) -> Value {
+    let mut nbt = HashMap::new();
+    let (name, properties) = parse_block_state(block);
+    nbt.insert("Name".to_owned(), Value::String(name.to_owned()));
+    if !properties.is_empty() {
+        let properties = properties
+            .into_iter()
+            .map(|(key, value)| (key.to_owned(), Value::String(value.to_owned())))
+            .collect();
+        nbt.insert("Properties".to_owned(), Value::Compound(properties));
+    }
+    Value::Compound(nbt)
+}
+
+/// Pack `len` palette indices tightly into longs. Unlike the vanilla format, entries may span
+/// across two longs.
+fn pack_block_states(len: usize, indices: impl Iterator<Item = u32>, bits: u32) -> Vec<i64> {
+    let mut longs = vec![0u64; (len * bits as usize).div_ceil(64)];
+    for (i, id) in indices.take(len).enumerate() {
+        let start = i * bits as usize;
+        let (idx, offset) = (start / 64, start % 64);
+        longs[idx] |= (id as u64) << offset;
+        if offset + bits as usize > 64 {
+            longs[idx + 1] |= (id as u64) >> (64 - offset);
+        }
+    }
+    longs.into_iter().map(|l| l as i64).collect()
+}
+
+/// A box of the schematic that is written as one Litematica region
+struct RegionBox<'a> {
+    name: &'a str,
+    offset: (u32, u32, u32),
+    size: (u32, u32, u32),
+}
+
+impl RegionBox<'_> {
+    fn contains(&self, pos: (u32, u32, u32)) -> bool {
+        (self.offset.0..self.offset.0 + self.size.0).contains(&pos.0)
+            && (self.offset.1..self.offset.1 + self.size.1).contains(&pos.1)
+            && (self.offset.2..self.offset.2 + self.size.2).contains(&pos.2)
+    }
+}
+
+/// Get the regions to write, which are the regions of the schematic if they cover all of its
+/// contents. Like [`Schematic::split_regions`], block entities and entities are assigned to the
+/// last region containing them, and the indices of those regions are returned along with them.
+fn region_boxes(schem: &Schematic) -> Option<(Vec<RegionBox<'_>>, Vec<usize>, Vec<usize>)> {
+    let (size_x, size_y, size_z) = schem.blocks.size();
+    let boxes: Vec<RegionBox> = schem
+        .regions
+        .iter()
+        .map(|region| RegionBox {
+            name: &region.name,
+            offset: region.offset,
+            size: region.size,
+        })
+        .collect();
+    let fits = |r: &RegionBox| {
+        r.offset.0 as u64 + r.size.0 as u64 <= size_x as u64
+            && r.offset.1 as u64 + r.size.1 as u64 <= size_y as u64
+            && r.offset.2 as u64 + r.size.2 as u64 <= size_z as u64
+    };
+    let unique_names = boxes
+        .iter()
+        .enumerate()
+        .all(|(i, r)| boxes[..i].iter().all(|other| other.name != r.name));
+    if boxes.is_empty() || !unique_names || !boxes.iter().all(fits) {
+        return None;
+    }
+
+    let covered = |pos| boxes.iter().any(|r| r.contains(pos));
+    if !schem
+        .blocks
+        .cells()
+        .all(|(pos, block)| block == "minecraft:air" || covered(pos))
+    {
+        return None;
+    }
+    let owner = |pos| boxes.iter().rposition(|r| r.contains(pos));
+    let block_entities = schem
+        .block_entities_sorted()
+        .into_iter()
+        .map(|(pos, _)| owner(*pos))
+        .collect::<Option<Vec<_>>>()?;
+    let entities = schem
+        .entities
+        .iter()
+        .map(|entity| {
+            let (x, y, z) = entity.pos;
+            if x < 0.0 || y < 0.0 || z < 0.0 {
+                return None;
+            }
+            owner((x as u32, y as u32, z as u32))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some((boxes, block_entities, entities))
+}
+
+/// Write one region of the schematic, returning it along with the number of blocks in it that
+/// aren't air. Blocks in cells that are also part of one of the `earlier` regions aren't counted,
+/// so that the counts of all regions add up to the number of blocks in the merged volume.
+fn write_region(
+    schem: &Schematic,
+    region: &RegionBox,
+    earlier: &[RegionBox],
+    block_entities: &[(&(u32, u32, u32), &BlockEntity)],
+    entities: &[&Entity],
+) -> Result<(Value, u32), SchematicError> {
+    let blocks = &schem.blocks;
+    let (size_x, size_y, size_z) = region.size;
+    let (offset_x, offset_y, offset_z) = region.offset;
+
+    // The palette only holds the blocks used by this region. Litematica expects air to be its
+    // first entry.
+    let mut palette = vec!["minecraft:air"];
+    let mut remap: Vec<Option<u32>> = blocks
+        .palette
+        .iter()
+        .map(|block| (block == "minecraft:air").then_some(0))
+        .collect();
+
+    // Litematica orders blocks by y, then z, then x
+    let positions = || {
+        (0..size_y).flat_map(move |y| {
+            (0..size_z).flat_map(move |z| {
+                (0..size_x).map(move |x| (offset_x + x, offset_y + y, offset_z + z))
+            })
+        })
+    };
+    let block_id = |(x, y, z)| blocks.get_block_id_at(x, y, z) as usize;
+    // The number of bits per block depends on the size of the palette, so the palette is built
+    // first and the blocks are packed while going through them a second time. This avoids keeping
+    // the index of every block around in between.
+    let mut total_blocks = 0u32;
+    for pos in positions() {
+        let block_id = block_id(pos);
+        let id = *remap[block_id].get_or_insert_with(|| {
+            palette.push(&blocks.palette[block_id]);
+            palette.len() as u32 - 1
+        });
+        if id != 0 && !earlier.iter().any(|r| r.contains(pos)) {
+            total_blocks += 1;
+        }
+    }
+    let bits = (32 - (palette.len() as u32 - 1).leading_zeros()).max(2);
+    let volume = size_x as usize * size_y as usize * size_z as usize;
+    // Every block has been given an id in the first pass
+    let indices = positions().map(|pos| remap[block_id(pos)].unwrap());
+    let block_states = pack_block_states(volume, indices, bits);
+
+    let mut tile_entities = Vec::new();
+    for (pos, block_entity) in block_entities {
+        let mut data = block_entity.data.clone();
+        data.insert("id".to_owned(), Value::String(block_entity.id.clone()));
+        data.insert("x".to_owned(), to_int("TileEntities", pos.0 - offset_x)?);
+        data.insert("y".to_owned(), to_int("TileEntities", pos.1 - offset_y)?);
+        data.insert("z".to_owned(), to_int("TileEntities", pos.2 - offset_z)?);
+        tile_entities.push(Value::Compound(data));
+    }
+
+    // Entity positions are relative to the region
+    let mut nbt_entities = Vec::new();
+    for entity in entities {
+        let mut data = entity.data.clone();
+        data.insert("id".to_owned(), Value::String(entity.id.clone()));
+        let pos = (
+            entity.pos.0 - offset_x as f64,
+            entity.pos.1 - offset_y as f64,
+            entity.pos.2 - offset_z as f64,
+        );
+        data.insert("Pos".to_owned(), write_entity_pos(pos));
+        nbt_entities.push(Value::Compound(data));
+    }
+
+    let (pos_x, pos_y, pos_z) = schem.paste_offset.unwrap_or_default();
+    let position_axis = |pos: i32, offset: u32| {
+        i32::try_from(offset)
+            .ok()
+            .and_then(|offset| pos.checked_add(offset))
+            .ok_or_else(|| SchematicError::InvalidValue("Position".to_owned()))
+    };
+    let mut position = HashMap::new();
+    position.insert("x".to_owned(), Value::Int(position_axis(pos_x, offset_x)?));
+    position.insert("y".to_owned(), Value::Int(position_axis(pos_y, offset_y)?));
+    position.insert("z".to_owned(), Value::Int(position_axis(pos_z, offset_z)?));
+
+    let mut nbt = HashMap::new();
+    nbt.insert("Position".to_owned(), Value::Compound(position));
+    nbt.insert("Size".to_owned(), write_vec3("Size", region.size)?);
+    nbt.insert(
+        "BlockStatePalette".to_owned(),
+        Value::List(palette.iter().map(|b| write_block_state(b)).collect()),
+    );
+    nbt.insert("BlockStates".to_owned(), Value::LongArray(block_states));
+    nbt.insert("TileEntities".to_owned(), Value::List(tile_entities));
+    nbt.insert("Entities".to_owned(), Value::List(nbt_entities));
+    nbt.insert("PendingBlockTicks".to_owned(), Value::List(Vec::new()));
+    nbt.insert("PendingFluidTicks".to_owned(), Value::List(Vec::new()));
+    Ok((Value::Compound(nbt), total_blocks))
+}
+
+pub fn serialize(schem: &Schematic, version: u32) -> Result<nbt::Blob, SchematicError> {
+    let mut metadata = schem.metadata.clone().unwrap_or_default();
+    let name = match metadata.get("Name") {
+        Some(Value::String(name)) => name.clone(),
+        _ => "Unnamed".to_owned(),
+    };
+
+    // Without regions covering everything, the whole schematic is written as one region named
+    // after the schematic
+    let block_entities = schem.block_entities_sorted();
+    let (boxes, block_entity_owners, entity_owners) = region_boxes(schem).unwrap_or_else(|| {
+        let whole = RegionBox {
+            name: &name,
+            offset: (0, 0, 0),
+            size: schem.blocks.size(),
+        };
+        (
+            vec![whole],
+            vec![0; block_entities.len()],
+            vec![0; schem.entities.len()],
+        )
+    });
+
+    let mut regions = HashMap::new();
+    let mut order = Vec::with_capacity(boxes.len());
+    let mut total_volume = 0u64;
+    let mut total_blocks = 0u64;
+    for (i, region) in boxes.iter().enumerate() {
+        let region_block_entities: Vec<_> = block_entities
+            .iter()
+            .zip(&block_entity_owners)
+            .filter(|(_, &owner)| owner == i)
+            .map(|(block_entity, _)| *block_entity)
+            .collect();
+        let region_entities: Vec<&Entity> = schem
+            .entities
+            .iter()
+            .zip(&entity_owners)
+            .filter(|(_, &owner)| owner == i)
+            .map(|(entity, _)| entity)
+            .collect();
+        let (nbt, blocks) = write_region(
+            schem,
+            region,
+            &boxes[..i],
+            &region_block_entities,
+            &region_entities,
+        )?;
+        regions.insert(region.name.to_owned(), nbt);
+        order.push(Value::String(region.name.to_owned()));
+        total_volume += region.size.0 as u64 * region.size.1 as u64 * region.size.2 as u64;
+        total_blocks += blocks as u64;
+    }
+    let total = |name: &str, val: u64| {
+        u32::try_from(val)
+            .map_err(|_| SchematicError::InvalidValue(name.to_owned()))
+            .and_then(|val| to_int(name, val))
+    };
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default();
+    metadata.insert("Name".to_owned(), Value::String(name.clone()));
+    for key in ["Author", "Description"] {
+        metadata
+            .entry(key.to_owned())
+            .or_insert_with(|| Value::String(String::new()));
+    }
+    metadata
+        .entry("TimeCreated".to_owned())
+        .or_insert(Value::Long(now));
+    metadata.insert("TimeModified".to_owned(), Value::Long(now));
+    metadata.insert(
+        "EnclosingSize".to_owned(),
+        write_vec3("EnclosingSize", schem.blocks.size())?,
+    );
+    metadata.insert(
+        "RegionCount".to_owned(),
+        total("RegionCount", boxes.len() as u64)?,
+    );
+    metadata.insert(
+        "TotalVolume".to_owned(),
+        total("TotalVolume", total_volume)?,
+    );
+    metadata.insert(
+        "TotalBlocks".to_owned(),
+        total("TotalBlocks", total_blocks)?,
+    );
+
+    let title = schem.root_title_for(SchematicFormat::Litematica(version));
+    let mut root = nbt::Blob::named(title.unwrap_or_default());
+    root.insert("Version", Value::Int(version as i32))?;
+    root.insert(
+        "SubVersion",
+        Value::Int(schem.sub_version.unwrap_or(SUB_VERSION)),
+    )?;
+    root.insert(
+        "MinecraftDataVersion",
+        Value::Int(
+            schem
+                .data_version
+                .ok_or_else(|| SchematicError::MissingRequiredField("DataVersion".to_owned()))?
+                as i32,
+        ),
+    )?;
+    root.insert("Metadata", Value::Compound(metadata))?;
+    root.insert("Regions", Value::Compound(regions))?;
+    root.insert(REGION_ORDER_KEY, Value::List(order))?;
+
+    Ok(root)
+}
+
+pub fn estimate_size(schem: &Schematic) -> usize {
+    // Root compound, versions, and the generated metadata
+    let mut size = 256;
+    size += schem.metadata.as_ref().map_or(0, compound_size);
+
+    // Finding the blocks used by each region would mean going through all of them, so every
+    // region is assumed to use the whole palette
+    let blocks = &schem.blocks;
+    let palette_size = named_tag_size("BlockStatePalette")
+        + 5
+        + blocks
+            .palette
+            .iter()
+            .map(|block| write_block_state(block).len_bytes())
+            .sum::<usize>();
+    let bits = (32
+        - (blocks.palette.len() as u32)
+            .saturating_sub(1)
+            .leading_zeros())
+    .max(2);
+    // Without regions, the whole schematic is written as one region named after the schematic
+    let regions: Vec<(usize, (u32, u32, u32))> = if schem.regions.is_empty() {
+        vec![("Unnamed".len(), blocks.size())]
+    } else {
+        schem
+            .regions
+            .iter()
+            .map(|region| (region.name.len(), region.size))
+            .collect()
+    };
+    size += named_tag_size("Regions") + named_tag_size(REGION_ORDER_KEY) + 6;
+    for (name_len, (size_x, size_y, size_z)) in regions {
+        // The name in the order list and the region compound with its vectors and tick lists
+        size += 2 * name_len + 3 + 1;
+        size += 2 * (named_tag_size("Position") + 3 * (named_tag_size("x") + 4) + 1);
+        size += 4 * (named_tag_size("PendingBlockTicks") + 5);
+        size += palette_size;
+        let volume = size_x as usize * size_y as usize * size_z as usize;
+        size += named_tag_size("BlockStates") + 4 + (volume * bits as usize).div_ceil(64) * 8;
+    }
+
+    for block_entity in schem.block_entities.values() {
+        size += compound_size(&block_entity.data);
+        size += named_tag_size("id") + 2 + block_entity.id.len();
+        size += 3 * (named_tag_size("x") + 4);
+    }
+    for entity in &schem.entities {
+        size += compound_size(&entity.data);
+        size += named_tag_size("id") + 2 + entity.id.len();
+        size += named_tag_size("Pos") + 5 + 3 * 8;
+    }
+    size
+}
@@ -0,0 +1,22 @@
+/// Get a tag of a specific type from a compound, returning an error from the current function if
+/// it is missing or has the wrong type
+macro_rules! required_nbt {
+    ($nbt:expr, $name:tt, $ty:ident) => {
+        match $nbt.get($name) {
+            Some(Value::$ty(value)) => value,
+            Some(_) => return Err(SchematicError::MistypedField($name.to_owned())),
+            None => return Err(SchematicError::MissingRequiredField($name.to_owned())),
+        }
+    };
+}
+
+/// Like `required_nbt`, but evaluates to `None` if the tag is missing
+macro_rules! typed_nbt {
+    ($nbt:expr, $name:tt, $ty:ident) => {
+        match $nbt.get($name) {
+            Some(Value::$ty(value)) => Some(value),
+            Some(_) => return Err(SchematicError::MistypedField($name.to_owned())),
+            None => None,
+        }
+    };
+}
@@ -0,0 +1,148 @@
+use super::{compound_size, named_tag_size, write_entity_pos, Schematic, SchematicError};
+use nbt::Value;
+use std::collections::HashMap;
+
+/// Get the numeric id and data value of a block. Only legacy blocks (see
+/// [`is_legacy_block_name`](super::is_legacy_block_name)) and air can be represented.
+fn legacy_id(block: &str) -> Result<(u16, u8), SchematicError> {
+    if block == "minecraft:air" {
+        return Ok((0, 0));
+    }
+    let err = || SchematicError::NoLegacyId(block.to_owned());
+    let (id, data) = block.split_once(':').unwrap_or((block, "0"));
+    let id = id
+        .parse::<u16>()
+        .ok()
+        .filter(|id| *id < 4096)
+        .ok_or_else(err)?;
+    let data = data
+        .parse::<u8>()
+        .ok()
+        .filter(|data| *data < 16)
+        .ok_or_else(err)?;
+    Ok((id, data))
+}
+
+pub fn serialize_alpha(schem: &Schematic) -> Result<nbt::Blob, SchematicError> {
+    let blocks = &schem.blocks;
+    // Unused palette entries aren't written, so they don't need a legacy id
+    let used = blocks.used_ids();
+    let palette = blocks
+        .palette
+        .iter()
+        .zip(used)
+        .map(|(block, used)| if used { legacy_id(block) } else { Ok((0, 0)) })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let volume = blocks.indices.len();
+    let mut block_ids = Vec::with_capacity(volume);
+    let mut block_data = Vec::with_capacity(volume);
+    let mut add_blocks = vec![0u8; volume.div_ceil(2)];
+    let mut has_add = false;
+    // Blocks are ordered by y, then z, then x
+    for y in 0..blocks.size_y {
+        for z in 0..blocks.size_z {
+            for x in 0..blocks.size_x {
+                let (id, data) = palette[blocks.get_block_id_at(x, y, z) as usize];
+                let index = block_ids.len();
+                block_ids.push(id as u8 as i8);
+                block_data.push(data as i8);
+                // The upper 4 bits of the id are packed two per byte, low nibble first
+                let add = (id >> 8) as u8;
+                if add != 0 {
+                    has_add = true;
+                    add_blocks[index >> 1] |= if index & 1 == 0 { add } else { add << 4 };
+                }
+            }
+        }
+    }
+
+    let mut nbt = HashMap::new();
+    for (name, size) in [
+        ("Width", blocks.size_x),
+        ("Height", blocks.size_y),
+        ("Length", blocks.size_z),
+    ] {
+        let size =
+            i16::try_from(size).map_err(|_| SchematicError::InvalidValue(name.to_owned()))?;
+        nbt.insert(name.to_owned(), Value::Short(size));
+    }
+    nbt.insert("Materials".to_owned(), Value::String("Alpha".to_owned()));
+    nbt.insert("Blocks".to_owned(), Value::ByteArray(block_ids));
+    nbt.insert("Data".to_owned(), Value::ByteArray(block_data));
+    if has_add {
+        let add_blocks = add_blocks.into_iter().map(|b| b as i8).collect();
+        nbt.insert("AddBlocks".to_owned(), Value::ByteArray(add_blocks));
+    }
+
+    // WorldEdit stores the origin and paste offset in these non-standard tags
+    if let Some((x, y, z)) = schem.origin {
+        nbt.insert("WEOriginX".to_owned(), Value::Int(x));
+        nbt.insert("WEOriginY".to_owned(), Value::Int(y));
+        nbt.insert("WEOriginZ".to_owned(), Value::Int(z));
+    }
+    if let Some((x, y, z)) = schem.paste_offset {
+        nbt.insert("WEOffsetX".to_owned(), Value::Int(x));
+        nbt.insert("WEOffsetY".to_owned(), Value::Int(y));
+        nbt.insert("WEOffsetZ".to_owned(), Value::Int(z));
+    }
+
+    let mut tile_entities = Vec::new();
+    for (pos, block_entity) in &schem.block_entities {
+        let mut data = block_entity.data.clone();
+        data.insert("id".to_owned(), Value::String(block_entity.id.clone()));
+        data.insert("x".to_owned(), Value::Int(pos.0 as i32));
+        data.insert("y".to_owned(), Value::Int(pos.1 as i32));
+        data.insert("z".to_owned(), Value::Int(pos.2 as i32));
+        tile_entities.push(Value::Compound(data));
+    }
+    nbt.insert("TileEntities".to_owned(), Value::List(tile_entities));
+
+    let mut entities = Vec::new();
+    for entity in &schem.entities {
+        let mut data = entity.data.clone();
+        data.insert("id".to_owned(), Value::String(entity.id.clone()));
+        data.insert("Pos".to_owned(), write_entity_pos(entity.pos));
+        entities.push(Value::Compound(data));
+    }
+    nbt.insert("Entities".to_owned(), Value::List(entities));
+
+    let root = nbt::Blob {
+        title: "Schematic".to_owned(),
+        content: nbt,
+    };
+    Ok(root)
+}
+
+pub fn estimate_alpha_size(schem: &Schematic) -> usize {
+    // Root compound, dimensions, Materials, and the WorldEdit origin and offset tags
+    let mut size = 160;
+
+    // Every block takes up one byte of Blocks and Data, and half a byte of AddBlocks if any id
+    // doesn't fit into a byte
+    let blocks = &schem.blocks;
+    let volume = blocks.indices.len();
+    size += named_tag_size("Blocks") + 4 + volume;
+    size += named_tag_size("Data") + 4 + volume;
+    if blocks
+        .palette
+        .iter()
+        .any(|block| legacy_id(block).is_ok_and(|(id, _)| id > 255))
+    {
+        size += named_tag_size("AddBlocks") + 4 + volume.div_ceil(2);
+    }
+
+    size += named_tag_size("TileEntities") + 5;
+    for block_entity in schem.block_entities.values() {
+        size += compound_size(&block_entity.data);
+        size += named_tag_size("id") + 2 + block_entity.id.len();
+        size += 3 * (named_tag_size("x") + 4);
+    }
+    size += named_tag_size("Entities") + 5;
+    for entity in &schem.entities {
+        size += compound_size(&entity.data);
+        size += named_tag_size("id") + 2 + entity.id.len();
+        size += named_tag_size("Pos") + 5 + 3 * 8;
+    }
+    size
+}
@@ -0,0 +1,71 @@
+use super::json::write_string;
+use nbt::Value;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Write the name of a compound entry, quoting it if it contains characters that need quoting
+fn write_key(out: &mut String, key: &str) {
+    let unquoted = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '+'));
+    if unquoted {
+        out.push_str(key);
+    } else {
+        write_string(out, key);
+    }
+}
+
+fn write_array<T: std::fmt::Display>(out: &mut String, prefix: &str, suffix: &str, vals: &[T]) {
+    write!(out, "[{prefix};").unwrap();
+    for (i, val) in vals.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write!(out, "{val}{suffix}").unwrap();
+    }
+    out.push(']');
+}
+
+fn write_value(out: &mut String, value: &Value) {
+    match value {
+        Value::Byte(val) => write!(out, "{val}b").unwrap(),
+        Value::Short(val) => write!(out, "{val}s").unwrap(),
+        Value::Int(val) => write!(out, "{val}").unwrap(),
+        Value::Long(val) => write!(out, "{val}L").unwrap(),
+        Value::Float(val) => write!(out, "{val}f").unwrap(),
+        Value::Double(val) => write!(out, "{val}d").unwrap(),
+        Value::ByteArray(vals) => write_array(out, "B", "b", vals),
+        Value::String(val) => write_string(out, val),
+        Value::List(vals) => {
+            out.push('[');
+            for (i, val) in vals.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_value(out, val);
+            }
+            out.push(']');
+        }
+        Value::Compound(compound) => write_compound(out, compound),
+        Value::IntArray(vals) => write_array(out, "I", "", vals),
+        Value::LongArray(vals) => write_array(out, "L", "L", vals),
+    }
+}
+
+/// Write a compound as SNBT, the text format used by commands. Entries are sorted by name so the
+/// output is deterministic.
+pub(crate) fn write_compound(out: &mut String, compound: &HashMap<String, Value>) {
+    let mut entries: Vec<_> = compound.iter().collect();
+    entries.sort_by_key(|(name, _)| *name);
+    out.push('{');
+    for (i, (name, val)) in entries.into_iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write_key(out, name);
+        out.push(':');
+        write_value(out, val);
+    }
+    out.push('}');
+}
@@ -1,78 +1,214 @@
-use super::{BlockEntity, Blocks, Schematic, SchematicError, SchematicFormat};
+use super::{
+    compound_size, is_legacy_block_name, named_tag_size, read_entity_pos, read_palette_entry,
+    read_varint, write_entity_pos, write_varint, BlockEntity, Blocks, Entity, ReadOptions,
+    Schematic, SchematicError, SchematicFormat,
+};
 use nbt::Value;
 use std::collections::HashMap;
 
-macro_rules! required_nbt {
-    ($nbt:expr, $name:tt, $ty:ident) => {
-        match $nbt.get($name) {
-            Some(Value::$ty(value)) => value,
-            Some(_) => return Err(SchematicError::MistypedField($name.to_owned())),
-            None => return Err(SchematicError::MissingRequiredField($name.to_owned())),
-        }
+/// Read the index of a palette entry. Not all tools agree on the integer type used for this, so all
+/// of them are accepted.
+fn read_palette_id(name: &str, value: &Value) -> Result<u32, SchematicError> {
+    let id = match *value {
+        Value::Byte(id) => id.try_into().ok(),
+        Value::Short(id) => id.try_into().ok(),
+        Value::Int(id) => id.try_into().ok(),
+        Value::Long(id) => id.try_into().ok(),
+        _ => return Err(SchematicError::MistypedField(name.to_owned())),
     };
+    id.ok_or_else(|| SchematicError::InvalidValue(name.to_owned()))
 }
 
-macro_rules! typed_nbt {
-    ($nbt:expr, $name:tt, $ty:ident) => {
-        match $nbt.get($name) {
-            Some(Value::$ty(value)) => Some(value),
-            Some(_) => return Err(SchematicError::MistypedField($name.to_owned())),
-            None => None,
-        }
-    };
+/// Count the varints in a byte array. The last byte of every varint has the continuation bit
+/// cleared, so incomplete varints at the end are not counted.
+fn count_varints(data: &[i8]) -> u64 {
+    data.iter().filter(|&&b| b >= 0).count() as u64
 }
 
-fn read_block_container(
-    version: u32,
+/// Read a palette and the varint encoded palette indices that refer to it. Entries are ordered by
+/// y, then z, then x. Palette entries are added in the order of their ids after the initial
+/// entry. If no initial entry is given, the palette entry with the lowest id is used, so the
+/// palette is kept as is if its ids have no gaps. `progress` is called after every layer.
+///
+/// The palette is usually a compound mapping block states to ids, but vanilla style lists of
+/// `{Name, Properties}` compounds, where the id of every entry is its index, are accepted as well.
+///
+/// Some buggy tools list the same block several times with different ids. Since the palette is a
+/// compound, only the last of these entries survives parsing the NBT, and data using one of the
+/// other ids is rejected like any unknown id. An id used by several blocks is ambiguous and
+/// results in an error as well.
+#[allow(clippy::too_many_arguments)]
+fn read_palette_data(
+    palette_name: &str,
+    data_name: &str,
+    initial: Option<&str>,
     size_x: u32,
     size_y: u32,
     size_z: u32,
     nbt: &HashMap<String, Value>,
-) -> Result<(Blocks, HashMap<(u32, u32, u32), BlockEntity>), SchematicError> {
-    let mut blocks = Blocks::new(size_x, size_y, size_z, "minecraft:air");
+    progress: &mut dyn FnMut(u64, u64),
+) -> Result<Blocks, SchematicError> {
+    let mut palette_ids = Vec::new();
+    match nbt.get(palette_name) {
+        Some(Value::Compound(nbt_palette)) => {
+            for (name, value) in nbt_palette.iter() {
+                palette_ids.push((read_palette_id(name, value)?, name.clone()));
+            }
+        }
+        // Vanilla style palettes list the blocks in the order of their ids
+        Some(Value::List(nbt_palette)) => {
+            for (id, entry) in nbt_palette.iter().enumerate() {
+                palette_ids.push((id as u32, read_palette_entry(palette_name, entry)?));
+            }
+        }
+        Some(_) => return Err(SchematicError::MistypedField(palette_name.to_owned())),
+        None => {
+            return Err(SchematicError::MissingRequiredField(
+                palette_name.to_owned(),
+            ))
+        }
+    }
+    // Keep the order of the palette instead of the order of the compound, which is random
+    palette_ids.sort_unstable();
+    let initial = initial.or_else(|| palette_ids.first().map(|(_, name)| name.as_str()));
+    let block_arr = required_nbt!(nbt, data_name, ByteArray);
+    // Checking this before allocating the container also keeps small inputs from claiming huge
+    // dimensions
+    let volume = size_x as usize * size_y as usize * size_z as usize;
+    let mut entries = count_varints(block_arr);
+    // Data ending in the middle of a varint is truncated even if the complete varints add up. The
+    // incomplete varint is reported as an extra entry then, so the counts in the error differ.
+    let truncated = block_arr.last().is_some_and(|&b| b < 0);
+    if truncated && entries == volume as u64 {
+        entries += 1;
+    }
+    if entries != volume as u64 {
+        return Err(SchematicError::CorruptData {
+            expected: volume as u64,
+            actual: entries,
+        });
+    }
+    let mut blocks = Blocks::new(size_x, size_y, size_z, initial.unwrap_or("minecraft:air"));
 
-    let nbt_palette = required_nbt!(nbt, "Palette", Compound);
     let mut palette = HashMap::new();
-    for (name, value) in nbt_palette.iter() {
-        let Value::Int(value) = value else {
-            return Err(SchematicError::MistypedField(name.to_string()));
-        };
-        palette.insert(*value as u32, blocks.get_block_id_for(name));
+    for (id, name) in &palette_ids {
+        if palette.insert(*id, blocks.get_block_id_for(name)).is_some() {
+            return Err(SchematicError::InvalidValue(palette_name.to_owned()));
+        }
     }
 
-    let data_name = match version {
-        2 => "BlockData",
-        3 => "Data",
-        _ => unreachable!(),
-    };
-    let block_arr: Vec<u8> = required_nbt!(nbt, data_name, ByteArray)
-        .iter()
-        .map(|b| *b as u8)
-        .collect();
-    let mut i = 0;
+    let mut pos = 0;
+    let layer = size_x as u64 * size_z as u64;
     for y in 0..size_y {
         for z in 0..size_z {
             for x in 0..size_x {
-                let mut blockstate_id = 0;
-                // Max varint length is 5
-                for varint_len in 0..=5 {
-                    blockstate_id |= ((block_arr[i] & 127) as u32) << (varint_len * 7);
-                    if (block_arr[i] & 128) != 128 {
-                        i += 1;
-                        break;
-                    }
-                    i += 1;
-                }
-                let id = palette[&blockstate_id];
+                let blockstate_id = read_varint(block_arr, &mut pos)
+                    .map_err(|_| SchematicError::InvalidValue(data_name.to_owned()))?;
+                let Some(&id) = palette.get(&blockstate_id) else {
+                    return Err(SchematicError::InvalidValue(data_name.to_owned()));
+                };
                 blocks.set_block_id_at(x, y, z, id);
             }
         }
+        progress((y + 1) as u64 * layer, volume as u64);
+    }
+    Ok(blocks)
+}
+
+/// Read biomes, which some tools store at a quarter of the resolution of the blocks (one entry per
+/// 4x4x4 cube, or 4x4 column in version 2) rather than one per block or column.
+///
+/// The resolution is guessed from the number of entries in the data: if it matches the full volume,
+/// the biomes are read as is, if it matches the quarter volume, every entry is scaled up to fill its
+/// cube. Anything else is an invalid value.
+fn read_biomes(
+    palette_name: &str,
+    data_name: &str,
+    size_x: u32,
+    size_y: u32,
+    size_z: u32,
+    nbt: &HashMap<String, Value>,
+) -> Result<Blocks, SchematicError> {
+    let entries = count_varints(required_nbt!(nbt, data_name, ByteArray));
+    if entries == size_x as u64 * size_y as u64 * size_z as u64 {
+        return read_palette_data(
+            palette_name,
+            data_name,
+            None,
+            size_x,
+            size_y,
+            size_z,
+            nbt,
+            &mut |_, _| {},
+        );
+    }
+
+    let (quarter_x, quarter_y, quarter_z) =
+        (size_x.div_ceil(4), size_y.div_ceil(4), size_z.div_ceil(4));
+    if entries != quarter_x as u64 * quarter_y as u64 * quarter_z as u64 {
+        return Err(SchematicError::InvalidValue(data_name.to_owned()));
+    }
+    let quarter = read_palette_data(
+        palette_name,
+        data_name,
+        None,
+        quarter_x,
+        quarter_y,
+        quarter_z,
+        nbt,
+        &mut |_, _| {},
+    )?;
+    let mut biomes = Blocks::new(size_x, size_y, size_z, quarter.background_block());
+    let remap = biomes.import_palette(&quarter);
+    for x in 0..size_x {
+        for y in 0..size_y {
+            for z in 0..size_z {
+                let id = quarter.get_block_id_at(x / 4, y / 4, z / 4);
+                biomes.set_block_id_at(x, y, z, remap[id as usize]);
+            }
+        }
     }
+    Ok(biomes)
+}
+
+fn read_block_container(
+    version: u32,
+    size_x: u32,
+    size_y: u32,
+    size_z: u32,
+    nbt: &HashMap<String, Value>,
+    options: &mut ReadOptions,
+) -> Result<(Blocks, HashMap<(u32, u32, u32), BlockEntity>), SchematicError> {
+    let data_name = match version {
+        2 => "BlockData",
+        3 => "Data",
+        _ => unreachable!(),
+    };
+    // Starting with air keeps the background block the same as for other formats, unless the
+    // palette has to be kept exactly as is
+    let initial = if options.preserve_palette {
+        None
+    } else {
+        Some("minecraft:air")
+    };
+    let blocks = read_palette_data(
+        "Palette",
+        data_name,
+        initial,
+        size_x,
+        size_y,
+        size_z,
+        nbt,
+        options.progress,
+    )?;
 
+    let mut block_entities = HashMap::new();
+    if options.blocks_only {
+        return Ok((blocks, block_entities));
+    }
     let nbt_block_entities = typed_nbt!(nbt, "BlockEntities", List)
         .map(|l| l.as_slice())
         .unwrap_or_default();
-    let mut block_entities = HashMap::new();
     for block_entity in nbt_block_entities {
         let Value::Compound(val) = block_entity else {
             return Err(SchematicError::MistypedField(
@@ -85,15 +221,22 @@ fn read_block_container(
             ));
         };
         let pos_array = required_nbt!(val, "Pos", IntArray);
-        let pos = (
-            pos_array[0] as u32,
-            pos_array[1] as u32,
-            pos_array[2] as u32,
-        );
+        let pos = match pos_array[..] {
+            [x, y, z] if x >= 0 && y >= 0 && z >= 0 => (x as u32, y as u32, z as u32),
+            _ => return Err(SchematicError::InvalidValue("Pos".to_owned())),
+        };
         let id = required_nbt!(val, "Id", String);
-        let mut data = val.clone();
-        data.remove("Pos");
-        data.remove("Id");
+        // Version 3 nests the block entity's data in a separate compound
+        let data = if version == 3 {
+            typed_nbt!(val, "Data", Compound)
+                .cloned()
+                .unwrap_or_default()
+        } else {
+            let mut data = val.clone();
+            data.remove("Pos");
+            data.remove("Id");
+            data
+        };
 
         block_entities.insert(
             pos,
@@ -106,7 +249,131 @@ fn read_block_container(
     Ok((blocks, block_entities))
 }
 
-pub fn deserialize(nbt: &nbt::Blob, version: u32) -> Result<Schematic, SchematicError> {
+fn read_entities(
+    version: u32,
+    nbt: &HashMap<String, Value>,
+) -> Result<Vec<Entity>, SchematicError> {
+    let nbt_entities = typed_nbt!(nbt, "Entities", List)
+        .map(|l| l.as_slice())
+        .unwrap_or_default();
+    let mut entities = Vec::with_capacity(nbt_entities.len());
+    for entity in nbt_entities {
+        let Value::Compound(val) = entity else {
+            return Err(SchematicError::MistypedField("Entities".to_owned()));
+        };
+        let pos = read_entity_pos(val)?;
+        let id = required_nbt!(val, "Id", String);
+        // Like block entities, version 3 nests the entity's data in a separate compound
+        let data = if version == 3 {
+            typed_nbt!(val, "Data", Compound)
+                .cloned()
+                .unwrap_or_default()
+        } else {
+            let mut data = val.clone();
+            data.remove("Pos");
+            data.remove("Id");
+            data
+        };
+        entities.push(Entity {
+            id: id.clone(),
+            pos,
+            data,
+        });
+    }
+    Ok(entities)
+}
+
+/// Rename tags that only differ in case from one of the given names, unless the correctly cased tag
+/// exists already
+fn rename_miscased(nbt: &mut HashMap<String, Value>, names: &[&str]) {
+    for name in names {
+        if nbt.contains_key(*name) {
+            continue;
+        }
+        let found = nbt
+            .keys()
+            .find(|key| key.eq_ignore_ascii_case(name))
+            .cloned();
+        if let Some(value) = found.and_then(|key| nbt.remove(&key)) {
+            nbt.insert((*name).to_owned(), value);
+        }
+    }
+}
+
+/// Fix the case of the dimension and palette tags of a version 2 or 3 schematic
+pub fn fix_tag_case(nbt: &mut HashMap<String, Value>) {
+    const NAMES: [&str; 5] = ["Width", "Height", "Length", "Palette", "BlockData"];
+    rename_miscased(nbt, &NAMES);
+    if let Some(Value::Compound(schem)) = nbt.get_mut("Schematic") {
+        rename_miscased(schem, &NAMES);
+        if let Some(Value::Compound(blocks)) = schem.get_mut("Blocks") {
+            rename_miscased(blocks, &["Palette", "Data"]);
+        }
+    }
+}
+
+/// Read one of the dimensions of the schematic. These are specified as unsigned shorts, but some
+/// tools write them as ints instead.
+fn read_dimension(nbt: &HashMap<String, Value>, name: &str) -> Result<u32, SchematicError> {
+    match nbt.get(name) {
+        Some(Value::Short(size)) => Ok(*size as u16 as u32),
+        Some(Value::Int(size)) => u16::try_from(*size)
+            .map(u32::from)
+            .map_err(|_| SchematicError::InvalidValue(name.to_owned())),
+        Some(_) => Err(SchematicError::MistypedField(name.to_owned())),
+        None => Err(SchematicError::MissingRequiredField(name.to_owned())),
+    }
+}
+
+/// Remove `WorldEdit.Origin` from the metadata of a version 3 schematic. Like the `WEOffset` tags
+/// of older versions, this is non-standard, so it is ignored if it is mistyped.
+fn take_worldedit_origin(metadata: &mut Option<HashMap<String, Value>>) -> Option<(i32, i32, i32)> {
+    let Value::Compound(worldedit) = metadata.as_mut()?.get_mut("WorldEdit")? else {
+        return None;
+    };
+    let Some(Value::IntArray(origin)) = worldedit.get("Origin") else {
+        return None;
+    };
+    let &[x, y, z] = origin.as_slice() else {
+        return None;
+    };
+    worldedit.remove("Origin");
+    Some((x, y, z))
+}
+
+/// Remove the paste offset from the metadata of a version 1 or 2 schematic. This is non-standard,
+/// so we're pretty relaxed about reading it. The keys are tried in this order:
+///
+/// 1. `WEOffsetX`, `WEOffsetY`, and `WEOffsetZ`, as written by WorldEdit. Missing or mistyped
+///    components are read as 0.
+/// 2. The same keys in another case, such as `WeOffsetX`.
+/// 3. An `Offset` int array of length 3, as written by some forks.
+fn take_metadata_offset(metadata: &mut HashMap<String, Value>) -> Option<(i32, i32, i32)> {
+    const NAMES: [&str; 3] = ["WEOffsetX", "WEOffsetY", "WEOffsetZ"];
+    rename_miscased(metadata, &NAMES);
+    if metadata.contains_key("WEOffsetX") {
+        let [x, y, z] = NAMES.map(|name| match metadata.remove(name) {
+            Some(Value::Int(val)) => val,
+            _ => 0,
+        });
+        return Some((x, y, z));
+    }
+    let Some(Value::IntArray(offset)) = metadata.get("Offset") else {
+        return None;
+    };
+    let &[x, y, z] = offset.as_slice() else {
+        return None;
+    };
+    metadata.remove("Offset");
+    Some((x, y, z))
+}
+
+pub fn deserialize(
+    nbt: &nbt::Blob,
+    version: u32,
+    mut options: ReadOptions,
+) -> Result<Schematic, SchematicError> {
+    let root_title = nbt.title.clone();
     let nbt = match version {
         2 => &nbt.content,
         3 => required_nbt!(nbt, "Schematic", Compound),
@@ -118,83 +385,116 @@ pub fn deserialize(nbt: &nbt::Blob, version: u32) -> Result<Schematic, Schematic
     };
 
     let data_version = *required_nbt!(nbt, "DataVersion", Int) as u32;
-    let size_x = *required_nbt!(nbt, "Width", Short) as u32;
-    let size_y = *required_nbt!(nbt, "Height", Short) as u32;
-    let size_z = *required_nbt!(nbt, "Length", Short) as u32;
+    let size_x = read_dimension(nbt, "Width")?;
+    let size_y = read_dimension(nbt, "Height")?;
+    let size_z = read_dimension(nbt, "Length")?;
+    if let Some(limit) = options.max_cells {
+        let cells = size_x as u64 * size_y as u64 * size_z as u64;
+        if cells > limit {
+            return Err(SchematicError::TooLarge { cells, limit });
+        }
+    }
 
     let mut metadata = typed_nbt!(nbt, "Metadata", Compound).cloned();
     let paste_offset = if version == 3 {
-        let offset = required_nbt!(nbt, "Offset", IntArray);
-        if offset.len() != 3 {
-            return Err(SchematicError::MistypedField("Offset".to_owned()));
-        }
-        Some((offset[0], offset[1], offset[2]))
-    } else if let Some(metadata) = &mut metadata {
-        // We're pretty relaxed about reading this since it's non-standard
-        if metadata.contains_key("WEOffsetX") {
-            let offset = Some((
-                typed_nbt!(metadata, "WEOffsetX", Int)
-                    .copied()
-                    .unwrap_or_default(),
-                typed_nbt!(metadata, "WEOffsetY", Int)
-                    .copied()
-                    .unwrap_or_default(),
-                typed_nbt!(metadata, "WEOffsetZ", Int)
-                    .copied()
-                    .unwrap_or_default(),
-            ));
-            metadata.remove_entry("WEOffsetX");
-            metadata.remove_entry("WEOffsetY");
-            metadata.remove_entry("WEOffsetZ");
-            offset
-        } else {
-            None
+        // The offset is required by the specification, but some tools omit it
+        match typed_nbt!(nbt, "Offset", IntArray).map(Vec::as_slice) {
+            Some(&[x, y, z]) => Some((x, y, z)),
+            Some(_) => return Err(SchematicError::MistypedField("Offset".to_owned())),
+            None => None,
         }
     } else {
-        None
+        metadata.as_mut().and_then(take_metadata_offset)
     };
 
-    if metadata.as_ref().is_some_and(HashMap::is_empty) {
-        metadata = None;
-    }
-
     // Worldedit encodes the origin as offset in v1 and v2 due to a misunderstanding of the spec
     let origin = if version < 3 {
-        typed_nbt!(nbt, "Offset", IntArray).map(|vec| (vec[0], vec[1], vec[2]))
+        match typed_nbt!(nbt, "Offset", IntArray).map(Vec::as_slice) {
+            Some(&[x, y, z]) => Some((x, y, z)),
+            Some(_) => return Err(SchematicError::MistypedField("Offset".to_owned())),
+            None => None,
+        }
     } else {
-        None
+        // In version 3, WorldEdit stores the position the schematic was copied from, which the
+        // paste offset is relative to
+        take_worldedit_origin(&mut metadata).map(|(x, y, z)| {
+            let (dx, dy, dz) = paste_offset.unwrap_or_default();
+            (x + dx, y + dy, z + dz)
+        })
     };
 
+    if metadata.as_ref().is_some_and(HashMap::is_empty) {
+        metadata = None;
+    }
+
     let block_container = if version == 3 {
         &required_nbt!(nbt, "Blocks", Compound)
     } else {
         nbt
     };
-    let (blocks, block_entities) =
-        read_block_container(version, size_x, size_y, size_z, block_container)?;
+    let (blocks, block_entities) = read_block_container(
+        version,
+        size_x,
+        size_y,
+        size_z,
+        block_container,
+        &mut options,
+    )?;
+
+    let entities = if options.blocks_only {
+        Vec::new()
+    } else {
+        read_entities(version, nbt)?
+    };
+
+    // Version 2 stores biomes per column, while version 3 stores them per block
+    let biomes = if options.blocks_only {
+        None
+    } else if version == 3 {
+        match typed_nbt!(nbt, "Biomes", Compound) {
+            Some(biomes) => Some(read_biomes(
+                "Palette", "Data", size_x, size_y, size_z, biomes,
+            )?),
+            None => None,
+        }
+    } else if nbt.contains_key("BiomeData") {
+        Some(read_biomes(
+            "BiomePalette",
+            "BiomeData",
+            size_x,
+            1,
+            size_z,
+            nbt,
+        )?)
+    } else {
+        None
+    };
 
     Ok(Schematic {
         blocks,
         data_version: Some(data_version),
         paste_offset,
         origin,
-        // TODO
-        biomes: None,
+        biomes,
         block_entities,
+        entities,
         metadata,
+        sub_version: None,
+        root_title: Some(root_title),
+        regions: Vec::new(),
+        source_format: Some(SchematicFormat::Sponge(version)),
     })
 }
 
-macro_rules! convert_or_err {
-    ($nbt:expr, $name:tt, $ty:ident, $val:expr) => {
-        $nbt.insert(
-            $name.to_owned(),
-            Value::$ty(
-                $val.try_into()
-                    .map_err(|_| SchematicError::InvalidValue($name.to_owned()))?,
-            ),
-        )
-    };
+/// Write one of the dimensions of the schematic as an unsigned short, see [`read_dimension`]
+fn write_dimension(
+    nbt: &mut HashMap<String, Value>,
+    name: &str,
+    size: u32,
+) -> Result<(), SchematicError> {
+    let size = u16::try_from(size).map_err(|_| SchematicError::InvalidValue(name.to_owned()))?;
+    nbt.insert(name.to_owned(), Value::Short(size as i16));
+    Ok(())
 }
 
 fn write_block_container(
@@ -205,7 +505,10 @@ fn write_block_container(
 ) {
     let mut palette = HashMap::new();
     for (idx, name) in blocks.palette.iter().enumerate() {
-        palette.insert(name.to_string(), Value::Int(idx as i32));
+        // Only unused entries can still have legacy ids here, and they would fail to parse
+        if !is_legacy_block_name(name) {
+            palette.insert(name.to_string(), Value::Int(idx as i32));
+        }
     }
     nbt.insert("Palette".to_owned(), Value::Compound(palette));
 
@@ -213,19 +516,7 @@ fn write_block_container(
     for y in 0..blocks.size_y {
         for z in 0..blocks.size_z {
             for x in 0..blocks.size_x {
-                let mut idx = blocks.get_block_id_at(x, y, z);
-                // TODO: check max size for varint (5)
-                loop {
-                    let mut temp = (idx & 0b1111_1111) as u8;
-                    idx >>= 7;
-                    if idx != 0 {
-                        temp |= 0b1000_0000;
-                    }
-                    bytes.push(temp as i8);
-                    if idx == 0 {
-                        break;
-                    }
-                }
+                write_varint(&mut bytes, blocks.get_block_id_at(x, y, z));
             }
         }
     }
@@ -238,7 +529,16 @@ fn write_block_container(
 
     let mut nbt_block_entities = Vec::new();
     for (pos, block_entity) in block_entities {
-        let mut data = block_entity.data.clone();
+        let mut data = if version == 3 {
+            let mut data = HashMap::new();
+            data.insert(
+                "Data".to_owned(),
+                nbt::Value::Compound(block_entity.data.clone()),
+            );
+            data
+        } else {
+            block_entity.data.clone()
+        };
         data.insert("Id".to_owned(), nbt::Value::String(block_entity.id.clone()));
         let pos_arr = vec![pos.0 as i32, pos.1 as i32, pos.2 as i32];
         data.insert("Pos".to_owned(), nbt::Value::IntArray(pos_arr));
@@ -250,7 +550,24 @@ fn write_block_container(
     );
 }
 
-pub fn serialize(schem: &Schematic, version: u32) -> Result<Vec<u8>, SchematicError> {
+fn write_entities(version: u32, entities: &[Entity], nbt: &mut HashMap<String, Value>) {
+    let mut nbt_entities = Vec::new();
+    for entity in entities {
+        let mut data = if version == 3 {
+            let mut data = HashMap::new();
+            data.insert("Data".to_owned(), Value::Compound(entity.data.clone()));
+            data
+        } else {
+            entity.data.clone()
+        };
+        data.insert("Id".to_owned(), Value::String(entity.id.clone()));
+        data.insert("Pos".to_owned(), write_entity_pos(entity.pos));
+        nbt_entities.push(Value::Compound(data));
+    }
+    nbt.insert("Entities".to_owned(), Value::List(nbt_entities));
+}
+
+pub fn serialize(schem: &Schematic, version: u32) -> Result<nbt::Blob, SchematicError> {
     let mut nbt = HashMap::new();
 
     nbt.insert("Version".to_owned(), Value::Int(version as i32));
@@ -263,12 +580,16 @@ pub fn serialize(schem: &Schematic, version: u32) -> Result<Vec<u8>, SchematicEr
                 as i32,
         ),
     );
-    convert_or_err!(nbt, "Width", Short, schem.blocks.size_x);
-    convert_or_err!(nbt, "Height", Short, schem.blocks.size_y);
-    convert_or_err!(nbt, "Length", Short, schem.blocks.size_z);
+    write_dimension(&mut nbt, "Width", schem.blocks.size_x)?;
+    write_dimension(&mut nbt, "Height", schem.blocks.size_y)?;
+    write_dimension(&mut nbt, "Length", schem.blocks.size_z)?;
 
-    // WorldEdit puts the paste offset into the metadata for version < 3, so we will do the same
-    if version < 3 && schem.paste_offset.is_some() || schem.metadata.is_some() {
+    // WorldEdit puts the paste offset into the metadata for version < 3, and the position the
+    // schematic was copied from for version 3, so we will do the same
+    if version < 3 && schem.paste_offset.is_some()
+        || version == 3 && schem.origin.is_some()
+        || schem.metadata.is_some()
+    {
         let mut metadata = if let Some(metadata) = &schem.metadata {
             metadata.clone()
         } else {
@@ -280,13 +601,25 @@ pub fn serialize(schem: &Schematic, version: u32) -> Result<Vec<u8>, SchematicEr
                 metadata.insert("WEOffsetY".to_owned(), Value::Int(offset.1));
                 metadata.insert("WEOffsetZ".to_owned(), Value::Int(offset.2));
             }
+        } else if let Some((x, y, z)) = schem.origin {
+            let (dx, dy, dz) = schem.paste_offset.unwrap_or_default();
+            let worldedit = match metadata
+                .entry("WorldEdit".to_owned())
+                .or_insert_with(|| Value::Compound(HashMap::new()))
+            {
+                Value::Compound(worldedit) => worldedit,
+                _ => return Err(SchematicError::MistypedField("WorldEdit".to_owned())),
+            };
+            worldedit.insert(
+                "Origin".to_owned(),
+                Value::IntArray(vec![x - dx, y - dy, z - dz]),
+            );
         }
+        nbt.insert("Metadata".to_owned(), Value::Compound(metadata));
     }
 
     if version == 3 {
-        let offset = schem
-            .paste_offset
-            .ok_or_else(|| SchematicError::MissingRequiredField("Offset".to_owned()))?;
+        let offset = schem.paste_offset.unwrap_or_default();
         nbt.insert(
             "Offset".to_owned(),
             nbt::Value::IntArray(vec![offset.0, offset.1, offset.2]),
@@ -312,19 +645,87 @@ pub fn serialize(schem: &Schematic, version: u32) -> Result<Vec<u8>, SchematicEr
         nbt.insert("Blocks".to_owned(), Value::Compound(container));
     };
 
+    write_entities(version, &schem.entities, &mut nbt);
+
     let root = match version {
         2 => nbt::Blob {
             content: nbt,
-            title: String::new(),
+            title: schem
+                .root_title_for(SchematicFormat::Sponge(2))
+                .unwrap_or("Schematic")
+                .to_owned(),
         },
         3 => {
-            let mut blob = nbt::Blob::new();
+            let title = schem.root_title_for(SchematicFormat::Sponge(3));
+            let mut blob = nbt::Blob::named(title.unwrap_or_default());
             blob.insert("Schematic", nbt::Value::Compound(nbt)).unwrap();
             blob
         }
         _ => unreachable!(),
     };
-    let mut data = Vec::new();
-    root.to_gzip_writer(&mut data)?;
-    Ok(data)
+    Ok(root)
+}
+
+pub fn estimate_size(schem: &Schematic, version: u32) -> usize {
+    // Root compound, Version, DataVersion, dimensions, and offsets
+    let mut size = 128;
+
+    let blocks = &schem.blocks;
+    size += named_tag_size("Palette") + 1;
+    size += blocks
+        .palette
+        .iter()
+        .map(|name| named_tag_size(name) + 4)
+        .sum::<usize>();
+
+    // Every block is written as a varint of its palette index
+    let varint_len = |id: u32| (32 - id.leading_zeros()).div_ceil(7).max(1) as usize;
+    let data_name = match version {
+        2 => "BlockData",
+        3 => "Data",
+        _ => unreachable!(),
+    };
+    size += named_tag_size(data_name) + 4;
+    size += blocks
+        .indices
+        .iter()
+        .map(|&id| varint_len(id))
+        .sum::<usize>();
+
+    // Version 3 nests the NBT of block entities and entities in a `Data` compound
+    let data_size = |data| match version {
+        3 => named_tag_size("Data") + compound_size(data) + 1,
+        _ => compound_size(data),
+    };
+    size += named_tag_size("BlockEntities") + 5;
+    for block_entity in schem.block_entities.values() {
+        size += data_size(&block_entity.data);
+        size += named_tag_size("Id") + 2 + block_entity.id.len();
+        size += named_tag_size("Pos") + 4 + 3 * 4;
+    }
+
+    size += named_tag_size("Entities") + 5;
+    for entity in &schem.entities {
+        size += data_size(&entity.data);
+        size += named_tag_size("Id") + 2 + entity.id.len();
+        size += named_tag_size("Pos") + 5 + 3 * 8;
+    }
+
+    let writes_offset = match version {
+        3 => schem.origin.is_some(),
+        _ => schem.paste_offset.is_some(),
+    };
+    if writes_offset || schem.metadata.is_some() {
+        size += named_tag_size("Metadata");
+        size += schem.metadata.as_ref().map_or(1, compound_size);
+        if writes_offset {
+            // Roughly the size of the offset tags added by WorldEdit
+            size += 3 * (named_tag_size("WEOffsetX") + 4);
+        }
+    }
+    if version == 3 {
+        // Nested Schematic and Blocks compounds
+        size += named_tag_size("Schematic") + named_tag_size("Blocks") + 2;
+    }
+    size
 }
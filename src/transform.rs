@@ -0,0 +1,330 @@
+//! Block state transformations used when rotating or mirroring schematics.
+
+use nbt::Value;
+use std::collections::HashMap;
+
+/// A clockwise rotation around the y axis, as seen from above
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    Clockwise90,
+    Clockwise180,
+    CounterClockwise90,
+}
+
+/// A mirror across one of the horizontal axes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mirror {
+    /// Flip the x axis, swapping east and west
+    X,
+    /// Flip the z axis, swapping north and south
+    Z,
+}
+
+impl Rotation {
+    /// Get the rotation made of the given number of clockwise quarter turns, or `None` if it is a
+    /// multiple of four
+    pub fn from_quarter_turns(quarter_turns: u32) -> Option<Rotation> {
+        match quarter_turns % 4 {
+            1 => Some(Rotation::Clockwise90),
+            2 => Some(Rotation::Clockwise180),
+            3 => Some(Rotation::CounterClockwise90),
+            _ => None,
+        }
+    }
+
+    /// The number of clockwise quarter turns making up this rotation
+    pub fn quarter_turns(self) -> u32 {
+        match self {
+            Rotation::Clockwise90 => 1,
+            Rotation::Clockwise180 => 2,
+            Rotation::CounterClockwise90 => 3,
+        }
+    }
+}
+
+const HORIZONTAL: [&str; 4] = ["north", "east", "south", "west"];
+
+fn rotate_direction(dir: &str, rotation: Rotation) -> &str {
+    let Some(idx) = HORIZONTAL.iter().position(|d| *d == dir) else {
+        return dir;
+    };
+    HORIZONTAL[(idx + rotation.quarter_turns() as usize) % 4]
+}
+
+fn mirror_direction(dir: &str, mirror: Mirror) -> &str {
+    match (dir, mirror) {
+        ("east", Mirror::X) => "west",
+        ("west", Mirror::X) => "east",
+        ("north", Mirror::Z) => "south",
+        ("south", Mirror::Z) => "north",
+        _ => dir,
+    }
+}
+
+/// Rail shapes are made of one or two directions joined by `_`, with north or south always coming
+/// first in curves
+fn transform_rail_shape(shape: &str, f: impl Fn(&str) -> &str) -> String {
+    if let Some(dir) = shape.strip_prefix("ascending_") {
+        return format!("ascending_{}", f(dir));
+    }
+    let Some((a, b)) = shape.split_once('_') else {
+        return shape.to_owned();
+    };
+    let (a, b) = (f(a), f(b));
+    let is_z = |d: &str| d == "north" || d == "south";
+    match (is_z(a), is_z(b)) {
+        (true, true) => "north_south".to_owned(),
+        (false, false) => "east_west".to_owned(),
+        (true, false) => format!("{a}_{b}"),
+        (false, true) => format!("{b}_{a}"),
+    }
+}
+
+fn swap_left_right(value: &str) -> String {
+    if let Some(prefix) = value.strip_suffix("left") {
+        format!("{prefix}right")
+    } else if let Some(prefix) = value.strip_suffix("right") {
+        format!("{prefix}left")
+    } else {
+        value.to_owned()
+    }
+}
+
+/// Apply a transformation to every property of a block state, keeping the property order intact
+fn map_properties(block: &str, mut f: impl FnMut(&str, &str) -> (String, String)) -> String {
+    let Some((name, properties)) = block.split_once('[') else {
+        return block.to_owned();
+    };
+    let properties: Vec<String> = properties
+        .trim_end_matches(']')
+        .split(',')
+        .map(|property| match property.split_once('=') {
+            Some((key, value)) => {
+                let (key, value) = f(key, value);
+                format!("{key}={value}")
+            }
+            None => property.to_owned(),
+        })
+        .collect();
+    format!("{name}[{}]", properties.join(","))
+}
+
+/// Rotate a block state such as `minecraft:oak_door[facing=north,half=upper]` around the y axis.
+///
+/// Directional properties (`facing`, `axis`, `rotation`, rail `shape`, and the `north`/`east`/...
+/// connection properties) are rotated. Properties describing the parts of multi-block structures
+/// (`part=head/foot` of beds, `half=upper/lower` of doors, `type=left/right` of chests) are
+/// relative to the block's facing and are therefore kept as is, which keeps these structures
+/// intact as long as every part is rotated.
+pub fn rotate_block_state(block: &str, rotation: Rotation) -> String {
+    let is_rail = block.contains("rail[");
+    map_properties(block, |key, value| match key {
+        "facing" => (key.to_owned(), rotate_direction(value, rotation).to_owned()),
+        "axis" if rotation != Rotation::Clockwise180 => {
+            let value = match value {
+                "x" => "z",
+                "z" => "x",
+                _ => value,
+            };
+            (key.to_owned(), value.to_owned())
+        }
+        "rotation" => match value.parse::<u32>() {
+            Ok(rot) => (
+                key.to_owned(),
+                ((rot + rotation.quarter_turns() * 4) % 16).to_string(),
+            ),
+            Err(_) => (key.to_owned(), value.to_owned()),
+        },
+        "shape" if is_rail => (
+            key.to_owned(),
+            transform_rail_shape(value, |d| rotate_direction(d, rotation)),
+        ),
+        "north" | "east" | "south" | "west" => {
+            (rotate_direction(key, rotation).to_owned(), value.to_owned())
+        }
+        _ => (key.to_owned(), value.to_owned()),
+    })
+}
+
+/// Mirror a block state such as `minecraft:chest[facing=east,type=left]` across a horizontal axis.
+///
+/// In addition to the directional properties handled by [`rotate_block_state`], mirroring flips the
+/// handedness of blocks, so `type=left/right` of chests, `hinge=left/right` of doors, and the
+/// `inner_left`/`outer_right`/... shapes of stairs are swapped. Bed `part` and door `half` are
+/// unaffected.
+pub fn mirror_block_state(block: &str, mirror: Mirror) -> String {
+    let is_rail = block.contains("rail[");
+    map_properties(block, |key, value| match key {
+        "facing" => (key.to_owned(), mirror_direction(value, mirror).to_owned()),
+        "rotation" => {
+            let axis = match mirror {
+                Mirror::X => 16,
+                Mirror::Z => 8,
+            };
+            match value.parse::<u32>() {
+                Ok(rot) => (key.to_owned(), ((axis + 16 - rot) % 16).to_string()),
+                Err(_) => (key.to_owned(), value.to_owned()),
+            }
+        }
+        "shape" if is_rail => (
+            key.to_owned(),
+            transform_rail_shape(value, |d| mirror_direction(d, mirror)),
+        ),
+        "type" | "hinge" | "shape" => (key.to_owned(), swap_left_right(value)),
+        "north" | "east" | "south" | "west" => {
+            (mirror_direction(key, mirror).to_owned(), value.to_owned())
+        }
+        _ => (key.to_owned(), value.to_owned()),
+    })
+}
+
+fn direction_offset(dir: &str) -> Option<(i32, i32, i32)> {
+    Some(match dir {
+        "north" => (0, 0, -1),
+        "east" => (1, 0, 0),
+        "south" => (0, 0, 1),
+        "west" => (-1, 0, 0),
+        _ => return None,
+    })
+}
+
+/// Get the offset from a block to the other part of its multi-block structure, if it is part of
+/// one. This covers beds (`part`), doors and tall plants (`half=upper/lower`), and double chests
+/// (`type=left/right`).
+pub fn other_part_offset(block: &str) -> Option<(i32, i32, i32)> {
+    let (_, properties) = block.split_once('[')?;
+    let property = |name: &str| {
+        properties
+            .trim_end_matches(']')
+            .split(',')
+            .filter_map(|property| property.split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value)
+    };
+
+    match (property("part"), property("half"), property("type")) {
+        (Some("foot"), _, _) => direction_offset(property("facing")?),
+        (Some("head"), _, _) => {
+            let facing = rotate_direction(property("facing")?, Rotation::Clockwise180);
+            direction_offset(facing)
+        }
+        (_, Some("lower"), _) => Some((0, 1, 0)),
+        (_, Some("upper"), _) => Some((0, -1, 0)),
+        (_, _, Some("left")) => {
+            let facing = rotate_direction(property("facing")?, Rotation::Clockwise90);
+            direction_offset(facing)
+        }
+        (_, _, Some("right")) => {
+            let facing = rotate_direction(property("facing")?, Rotation::CounterClockwise90);
+            direction_offset(facing)
+        }
+        _ => None,
+    }
+}
+
+/// Rotate the position of a block within a box of the given horizontal size. Returns the new
+/// `(x, z)` within the rotated box, whose x and z sizes are swapped for quarter turns.
+pub(crate) fn rotate_block_pos(
+    (x, z): (u32, u32),
+    (size_x, size_z): (u32, u32),
+    rotation: Rotation,
+) -> (u32, u32) {
+    match rotation {
+        Rotation::Clockwise90 => (size_z - 1 - z, x),
+        Rotation::Clockwise180 => (size_x - 1 - x, size_z - 1 - z),
+        Rotation::CounterClockwise90 => (z, size_x - 1 - x),
+    }
+}
+
+/// Like [`rotate_block_pos`], but for points that aren't bound to the block grid, such as entity
+/// positions
+pub(crate) fn rotate_point(
+    (x, z): (f64, f64),
+    (size_x, size_z): (f64, f64),
+    rotation: Rotation,
+) -> (f64, f64) {
+    match rotation {
+        Rotation::Clockwise90 => (size_z - z, x),
+        Rotation::Clockwise180 => (size_x - x, size_z - z),
+        Rotation::CounterClockwise90 => (z, size_x - x),
+    }
+}
+
+/// The horizontal directions in the order of Minecraft's 2D direction ids
+const HORIZONTAL_2D: [&str; 4] = ["south", "west", "north", "east"];
+/// All directions in the order of Minecraft's 3D direction ids
+const DIRECTIONS_3D: [&str; 6] = ["down", "up", "north", "south", "west", "east"];
+
+/// Transform a direction stored as a byte id in the order of `ids`
+fn transform_direction_id(value: &mut Value, ids: &[&str], f: impl Fn(&str) -> &str) {
+    if let Value::Byte(id) = value {
+        if let Some(dir) = usize::try_from(*id).ok().and_then(|id| ids.get(id)) {
+            let dir = f(dir);
+            *id = ids.iter().position(|d| *d == dir).unwrap_or_default() as i8;
+        }
+    }
+}
+
+/// Rotate the NBT of a block entity. Only legacy skulls store their own orientation (`Rot`, in
+/// sixteenths of a full turn); everything else is oriented by its block state.
+pub(crate) fn rotate_block_entity_data(data: &mut HashMap<String, Value>, rotation: Rotation) {
+    if let Some(Value::Byte(rot)) = data.get_mut("Rot") {
+        *rot = ((*rot as u32 + rotation.quarter_turns() * 4) % 16) as i8;
+    }
+}
+
+/// Mirror the NBT of a block entity. See [`rotate_block_entity_data`].
+pub(crate) fn mirror_block_entity_data(data: &mut HashMap<String, Value>, mirror: Mirror) {
+    if let Some(Value::Byte(rot)) = data.get_mut("Rot") {
+        let axis = match mirror {
+            Mirror::X => 16,
+            Mirror::Z => 8,
+        };
+        *rot = ((axis + 16 - *rot as u32 % 16) % 16) as i8;
+    }
+}
+
+/// Transform the yaw of an entity, which is the first angle of its `Rotation`
+fn transform_yaw(data: &mut HashMap<String, Value>, f: impl Fn(f32) -> f32) {
+    if let Some(Value::List(angles)) = data.get_mut("Rotation") {
+        if let Some(Value::Float(yaw)) = angles.first_mut() {
+            *yaw = f(*yaw).rem_euclid(360.0);
+        }
+    }
+}
+
+/// Transform the direction hanging entities such as item frames and paintings are facing
+/// (`Facing` or `facing`)
+fn transform_facing(id: &str, data: &mut HashMap<String, Value>, f: impl Fn(&str) -> &str) {
+    // Paintings use 2D direction ids, both in the old `Facing` tag and in `facing`, which only
+    // paintings have
+    let is_painting = matches!(id, "minecraft:painting" | "Painting");
+    if let Some(facing) = data.get_mut("Facing") {
+        let ids: &[&str] = if is_painting {
+            &HORIZONTAL_2D
+        } else {
+            &DIRECTIONS_3D
+        };
+        transform_direction_id(facing, ids, &f);
+    }
+    if let Some(facing) = data.get_mut("facing") {
+        transform_direction_id(facing, &HORIZONTAL_2D, &f);
+    }
+}
+
+/// Rotate the NBT of an entity. This turns the yaw of the entity (`Rotation`) and the direction
+/// hanging entities are facing.
+pub(crate) fn rotate_entity_data(id: &str, data: &mut HashMap<String, Value>, rotation: Rotation) {
+    // A yaw of 0 faces south and increases clockwise as seen from above
+    transform_yaw(data, |yaw| yaw + rotation.quarter_turns() as f32 * 90.0);
+    transform_facing(id, data, |d| rotate_direction(d, rotation));
+}
+
+/// Mirror the NBT of an entity. See [`rotate_entity_data`].
+pub(crate) fn mirror_entity_data(id: &str, data: &mut HashMap<String, Value>, mirror: Mirror) {
+    transform_yaw(data, |yaw| match mirror {
+        Mirror::X => -yaw,
+        Mirror::Z => 180.0 - yaw,
+    });
+    transform_facing(id, data, |d| mirror_direction(d, mirror));
+}
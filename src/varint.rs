@@ -0,0 +1,48 @@
+//! The variable length integers used by Sponge schematics to store palette indices.
+
+use super::SchematicError;
+
+/// A `u32` takes up at most 5 bytes as a varint
+const MAX_LEN: usize = 5;
+
+/// Read a varint starting at `pos`, moving `pos` past it. Every byte carries 7 bits of the value,
+/// starting with the lowest bits, and has its highest bit set if more bytes follow. `buf` holds
+/// signed bytes, like the NBT byte arrays the varints are stored in.
+///
+/// Varints that run past the end of `buf`, are longer than 5 bytes, or don't fit in a `u32` are
+/// rejected with [`SchematicError::InvalidValue`].
+pub fn read_varint(buf: &[i8], pos: &mut usize) -> Result<u32, SchematicError> {
+    let mut value = 0u32;
+    for i in 0..MAX_LEN {
+        let Some(&byte) = buf.get(*pos + i) else {
+            break;
+        };
+        let byte = byte as u8;
+        let bits = (byte & 0b0111_1111) as u32;
+        // The fifth byte only has room for the 4 highest bits
+        if i == MAX_LEN - 1 && bits > 0b1111 {
+            break;
+        }
+        value |= bits << (i * 7);
+        if byte & 0b1000_0000 == 0 {
+            *pos += i + 1;
+            return Ok(value);
+        }
+    }
+    Err(SchematicError::InvalidValue("varint".to_owned()))
+}
+
+/// Append a value to `out` as a varint. See [`read_varint`].
+pub fn write_varint(out: &mut Vec<i8>, mut value: u32) {
+    loop {
+        let mut byte = (value & 0b0111_1111) as u8;
+        value >>= 7;
+        if value != 0 {
+            byte |= 0b1000_0000;
+        }
+        out.push(byte as i8);
+        if value == 0 {
+            break;
+        }
+    }
+}
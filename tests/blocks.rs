@@ -0,0 +1,537 @@
+use mc_schems::{AxisOrder, Blocks, Run, SchematicError};
+use std::collections::HashMap;
+
+#[test]
+fn cells() {
+    let mut blocks = Blocks::new(2, 3, 4, "minecraft:air");
+    blocks.set_block_at(1, 2, 3, "minecraft:stone");
+    blocks.set_block_at(0, 1, 2, "minecraft:dirt");
+
+    let cells: Vec<_> = blocks.cells().collect();
+    assert_eq!(cells.len(), 2 * 3 * 4);
+    for ((x, y, z), block) in cells {
+        assert_eq!(blocks.get_block_at(x, y, z), block);
+    }
+
+    let palette: Vec<&str> = (&blocks).into_iter().collect();
+    assert_eq!(
+        palette,
+        ["minecraft:air", "minecraft:stone", "minecraft:dirt"]
+    );
+}
+
+#[test]
+fn downsample() {
+    let mut blocks = Blocks::new(5, 4, 4, "minecraft:air");
+    blocks.set_block_at(0, 0, 0, "minecraft:stone");
+    blocks.set_block_at(1, 1, 1, "minecraft:dirt");
+    blocks.set_block_at(1, 0, 1, "minecraft:dirt");
+    blocks.set_block_at(2, 2, 2, "minecraft:stone");
+    blocks.set_block_at(3, 3, 3, "minecraft:dirt");
+    blocks.set_block_at(4, 0, 0, "minecraft:gold_block");
+
+    let small = blocks.downsample(2);
+    assert_eq!(small.size(), (3, 2, 2));
+    assert_eq!(small.get_block_at(0, 0, 0), "minecraft:dirt");
+    // Tie between stone and dirt, stone comes first in the palette
+    assert_eq!(small.get_block_at(1, 1, 1), "minecraft:stone");
+    assert_eq!(small.get_block_at(2, 0, 0), "minecraft:gold_block");
+    assert_eq!(small.get_block_at(0, 1, 0), "minecraft:air");
+}
+
+#[test]
+fn replace_data() {
+    let mut blocks = Blocks::new(2, 1, 2, "minecraft:air");
+    let palette = vec!["minecraft:stone".to_owned(), "minecraft:dirt".to_owned()];
+
+    assert!(blocks.replace_data(palette.clone(), vec![0, 1, 0]).is_err());
+    assert!(blocks
+        .replace_data(palette.clone(), vec![0, 1, 2, 0])
+        .is_err());
+    assert_eq!(blocks.get_block_at(0, 0, 1), "minecraft:air");
+
+    blocks.replace_data(palette, vec![0, 1, 1, 0]).unwrap();
+    assert_eq!(blocks.get_block_at(0, 0, 1), "minecraft:dirt");
+    assert_eq!(blocks.get_block_at(1, 0, 1), "minecraft:stone");
+    assert_eq!(blocks.get_block_id_for("minecraft:dirt"), 1);
+}
+
+#[test]
+fn linear_index() {
+    let mut blocks = Blocks::new(3, 4, 5, "minecraft:air");
+    let stone = blocks.get_block_id_for("minecraft:stone");
+    blocks.set_block_id_at(2, 1, 3, stone);
+
+    let index = blocks.pos_to_linear_index(2, 1, 3);
+    assert_eq!(blocks.linear_index_to_pos(index), (2, 1, 3));
+    assert_eq!(blocks.get_by_linear_index(index), stone);
+    for i in 0..3 * 4 * 5 {
+        let (x, y, z) = blocks.linear_index_to_pos(i);
+        assert_eq!(blocks.pos_to_linear_index(x, y, z), i);
+    }
+}
+
+#[test]
+fn from_fn() {
+    let blocks = Blocks::from_fn(4, 2, 3, |x, y, _| {
+        if y == 0 {
+            "minecraft:stone".to_owned()
+        } else {
+            format!("minecraft:wool_{x}")
+        }
+    });
+    assert_eq!(blocks.get_block_at(3, 0, 2), "minecraft:stone");
+    assert_eq!(blocks.get_block_at(2, 1, 1), "minecraft:wool_2");
+}
+
+#[test]
+fn connected_components() {
+    let mut blocks = Blocks::new(5, 1, 3, "minecraft:air");
+    for z in 0..3 {
+        blocks.set_block_at(0, 0, z, "minecraft:stone");
+        blocks.set_block_at(3, 0, z, "minecraft:stone");
+    }
+    blocks.set_block_at(4, 0, 1, "minecraft:dirt");
+
+    let is_solid = |block: &str| block != "minecraft:air";
+    let filled = blocks.flood_fill((3, 0, 0), is_solid);
+    assert_eq!(filled.len(), 4);
+    assert!(filled.contains(&(4, 0, 1)));
+    assert!(blocks.flood_fill((1, 0, 0), is_solid).is_empty());
+
+    let mut components = blocks.connected_components(is_solid);
+    components.sort_by_key(|c| c.len());
+    assert_eq!(components.len(), 2);
+    assert_eq!(components[0].len(), 3);
+    assert_eq!(components[1].len(), 4);
+}
+
+#[test]
+fn render_layer_ascii() {
+    let mut blocks = Blocks::new(3, 2, 2, "minecraft:air");
+    blocks.set_block_at(0, 1, 0, "minecraft:stone");
+    blocks.set_block_at(2, 1, 1, "minecraft:redstone_wire[power=0]");
+
+    assert_eq!(blocks.render_layer_ascii(0), "   \n   \n");
+    assert_eq!(blocks.render_layer_ascii(1), "s  \n  r\n");
+
+    let glyphs: HashMap<&str, char> = [("minecraft:stone", '#')].into_iter().collect();
+    assert_eq!(blocks.render_layer_ascii_with(1, &glyphs), "#  \n  r\n");
+}
+
+#[test]
+fn compact_palette() {
+    let mut blocks = Blocks::new(2, 1, 1, "minecraft:air");
+    blocks.set_block_at(0, 0, 0, "minecraft:dirt");
+    blocks.set_block_at(1, 0, 0, "minecraft:stone");
+    blocks.set_block_at(0, 0, 0, "minecraft:stone");
+    blocks.compact_palette();
+
+    // Air isn't used anymore but stays the background block
+    let palette: Vec<&str> = (&blocks).into_iter().collect();
+    assert_eq!(palette, ["minecraft:air", "minecraft:stone"]);
+    assert_eq!(blocks.background_id(), 0);
+    assert_eq!(blocks.get_block_at(0, 0, 0), "minecraft:stone");
+    assert_eq!(blocks.get_block_id_at(1, 0, 0), 1);
+}
+
+#[test]
+fn background_id() {
+    let mut blocks = Blocks::new(3, 1, 1, "minecraft:glass");
+    blocks.set_block_at(1, 0, 0, "minecraft:stone");
+    // Renaming the background into an existing entry keeps it at the background index
+    let table = [("minecraft:glass".to_owned(), "minecraft:stone".to_owned())]
+        .into_iter()
+        .collect();
+    let mut renamed = blocks.clone();
+    renamed.apply_rename_table(&table);
+    assert_eq!(renamed.background_block(), "minecraft:stone");
+    assert_eq!(renamed.palette_size_live(), 1);
+    assert_eq!(renamed.get_block_id_at(1, 0, 0), renamed.background_id());
+}
+
+#[test]
+fn import_palette() {
+    let mut blocks = Blocks::new(3, 1, 1, "minecraft:glass");
+    blocks.set_block_at(1, 0, 0, "minecraft:stone");
+    let mut other = Blocks::new(3, 1, 1, "minecraft:air");
+    other.set_block_at(0, 0, 0, "minecraft:dirt");
+    other.set_block_at(1, 0, 0, "minecraft:stone");
+    let remap = blocks.import_palette(&other);
+    // Existing entries are reused and new ones are appended, leaving the background in place
+    assert_eq!(remap, [2, 3, 1]);
+    assert_eq!(blocks.background_id(), 0);
+    assert_eq!(blocks.background_block(), "minecraft:glass");
+    let palette: Vec<&str> = blocks.blocks_in_palette().collect();
+    assert_eq!(
+        palette,
+        [
+            "minecraft:glass",
+            "minecraft:stone",
+            "minecraft:air",
+            "minecraft:dirt"
+        ]
+    );
+
+    for x in 0..3 {
+        let id = remap[other.get_block_id_at(x, 0, 0) as usize];
+        blocks.set_block_id_at(x, 0, 0, id);
+        assert_eq!(blocks.get_block_at(x, 0, 0), other.get_block_at(x, 0, 0));
+    }
+    // Importing again adds nothing
+    assert_eq!(blocks.import_palette(&other), remap);
+    assert_eq!(blocks.palette_size_live(), 4);
+}
+
+#[test]
+fn assert_palette_within() {
+    let mut blocks = Blocks::new(2, 1, 1, "minecraft:air");
+    blocks.set_block_at(0, 0, 0, "minecraft:stone");
+    blocks.set_block_at(1, 0, 0, "minecraft:dirt");
+    assert!(blocks.assert_palette_within(3).is_ok());
+    assert!(matches!(
+        blocks.assert_palette_within(2),
+        Err(SchematicError::PaletteTooLarge { len: 3, limit: 2 })
+    ));
+}
+
+#[test]
+fn sanitize_palette() {
+    let mut blocks = Blocks::new(3, 1, 1, "minecraft:air");
+    blocks.set_block_at(0, 0, 0, " Minecraft:Stone ");
+    blocks.set_block_at(1, 0, 0, "minecraft:stone");
+    blocks.set_block_at(
+        2,
+        0,
+        0,
+        "Minecraft:Oak_Sign[Rotation = 4, waterlogged=false]",
+    );
+    blocks.sanitize_palette();
+
+    assert_eq!(blocks.get_block_at(0, 0, 0), "minecraft:stone");
+    assert_eq!(
+        blocks.get_block_id_at(0, 0, 0),
+        blocks.get_block_id_at(1, 0, 0)
+    );
+    assert_eq!(
+        blocks.get_block_at(2, 0, 0),
+        "minecraft:oak_sign[rotation=4,waterlogged=false]"
+    );
+    assert_eq!(blocks.blocks_in_palette().count(), 3);
+}
+
+#[test]
+fn palette_matches() {
+    let mut a = Blocks::new(2, 1, 1, "minecraft:air");
+    a.set_block_at(0, 0, 0, "minecraft:stone");
+    let mut b = Blocks::new(3, 3, 3, "minecraft:air");
+    b.set_block_at(2, 2, 2, "minecraft:stone");
+    assert!(a.palette_matches(&b));
+
+    let mut c = Blocks::new(2, 1, 1, "minecraft:stone");
+    c.set_block_at(0, 0, 0, "minecraft:air");
+    assert!(!a.palette_matches(&c));
+}
+
+#[test]
+fn non_air_count() {
+    let mut blocks = Blocks::new(2, 2, 2, "minecraft:air");
+    blocks.set_block_at(0, 0, 0, "minecraft:stone");
+    assert_eq!(blocks.non_air_count(), None);
+
+    blocks.track_non_air();
+    assert_eq!(blocks.non_air_count(), Some(1));
+    blocks.set_block_at(1, 1, 1, "minecraft:dirt");
+    blocks.set_block_at(1, 1, 1, "minecraft:stone");
+    assert_eq!(blocks.non_air_count(), Some(2));
+    blocks.set_block_at(0, 0, 0, "minecraft:air");
+    assert_eq!(blocks.non_air_count(), Some(1));
+    assert_eq!(blocks.palette_size_live(), 3);
+}
+
+#[test]
+fn map_region() {
+    let mut blocks = Blocks::from_fn(4, 4, 4, |x, _, _| {
+        if x % 2 == 0 {
+            "minecraft:stone"
+        } else {
+            "minecraft:air"
+        }
+    });
+    blocks
+        .map_region((1, 1, 1), (2, 2, 2), |_, block| {
+            (block != "minecraft:air").then(|| "minecraft:air".to_owned())
+        })
+        .unwrap();
+
+    for ((x, y, z), block) in blocks.cells() {
+        let inside = (1..=2).contains(&x) && (1..=2).contains(&y) && (1..=2).contains(&z);
+        if inside || x % 2 == 1 {
+            assert_eq!(block, "minecraft:air");
+        } else {
+            assert_eq!(block, "minecraft:stone");
+        }
+    }
+
+    assert!(matches!(
+        blocks.map_region((0, 0, 0), (4, 0, 0), |_, _| None),
+        Err(SchematicError::OutOfBounds(_))
+    ));
+}
+
+#[test]
+fn canonicalize_block_states() {
+    let mut blocks = Blocks::new(2, 1, 1, "minecraft:air");
+    blocks.set_block_at(0, 0, 0, "minecraft:oak_stairs[half=top,facing=north]");
+    blocks.set_block_at(1, 0, 0, "minecraft:oak_stairs[facing=north,half=top]");
+    assert_eq!(blocks.blocks_in_palette().count(), 3);
+
+    blocks.canonicalize_block_states();
+    assert_eq!(blocks.blocks_in_palette().count(), 2);
+    assert_eq!(
+        blocks.get_block_at(0, 0, 0),
+        "minecraft:oak_stairs[facing=north,half=top]"
+    );
+    assert_eq!(blocks.get_block_at(1, 0, 0), blocks.get_block_at(0, 0, 0));
+}
+
+#[test]
+fn item_counts() {
+    let mut blocks = Blocks::new(2, 2, 2, "minecraft:air");
+    blocks.set_block_at(0, 0, 0, "minecraft:oak_slab[type=double,waterlogged=false]");
+    blocks.set_block_at(1, 0, 0, "minecraft:oak_slab[type=bottom,waterlogged=false]");
+    blocks.set_block_at(0, 0, 1, "minecraft:oak_door[facing=north,half=lower]");
+    blocks.set_block_at(0, 1, 1, "minecraft:oak_door[facing=north,half=upper]");
+    blocks.set_block_at(1, 0, 1, "minecraft:oak_wall_sign[facing=east]");
+    blocks.set_block_at(1, 1, 1, "minecraft:candle[candles=3,lit=false]");
+
+    let counts = blocks.item_counts();
+    assert_eq!(counts.len(), 4);
+    assert_eq!(counts["minecraft:oak_slab"], 3);
+    assert_eq!(counts["minecraft:oak_door"], 1);
+    assert_eq!(counts["minecraft:oak_sign"], 1);
+    assert_eq!(counts["minecraft:candle"], 3);
+}
+
+#[test]
+fn iter_blocks_order() {
+    let blocks = Blocks::from_fn(2, 3, 4, |x, y, z| format!("minecraft:b_{x}_{y}_{z}"));
+    let positions = |order| -> Vec<(u32, u32, u32)> {
+        blocks
+            .iter_blocks_order(order)
+            .map(|(pos, _)| pos)
+            .collect()
+    };
+
+    let cells: Vec<_> = blocks.cells().collect();
+    assert_eq!(
+        blocks.iter_blocks_order(AxisOrder::Xyz).collect::<Vec<_>>(),
+        cells
+    );
+
+    let yzx = positions(AxisOrder::Yzx);
+    assert_eq!(yzx.len(), 24);
+    assert_eq!(&yzx[..3], [(0, 0, 0), (1, 0, 0), (0, 0, 1)]);
+    assert_eq!(yzx[8], (0, 1, 0));
+
+    let zyx = positions(AxisOrder::Zyx);
+    assert_eq!(&zyx[..3], [(0, 0, 0), (1, 0, 0), (0, 1, 0)]);
+    assert_eq!(zyx[6], (0, 0, 1));
+
+    for ((x, y, z), block) in blocks.iter_blocks_order(AxisOrder::Zxy) {
+        assert_eq!(block, format!("minecraft:b_{x}_{y}_{z}"));
+    }
+}
+
+#[test]
+fn assert_same_size() {
+    let a = Blocks::new(2, 3, 4, "minecraft:air");
+    let b = Blocks::new(2, 3, 4, "minecraft:stone");
+    assert!(a.assert_same_size(&b).is_ok());
+
+    let c = Blocks::new(4, 3, 2, "minecraft:air");
+    assert!(matches!(
+        a.assert_same_size(&c),
+        Err(SchematicError::InvalidValue(message)) if message == "size (2, 3, 4) does not match (4, 3, 2)"
+    ));
+}
+
+#[test]
+fn heightmap() {
+    let mut blocks = Blocks::new(3, 4, 2, "minecraft:air");
+    blocks.set_block_at(0, 0, 0, "minecraft:stone");
+    blocks.set_block_at(0, 2, 0, "minecraft:grass_block");
+    blocks.set_block_at(1, 3, 0, "minecraft:glass");
+    blocks.set_block_at(2, 1, 1, "minecraft:stone");
+    blocks.set_block_at(2, 3, 1, "minecraft:water");
+
+    let heightmap =
+        blocks.heightmap(|block| block != "minecraft:air" && block != "minecraft:water");
+    assert_eq!(heightmap, [[2, 3, 0], [0, 0, 1]]);
+}
+
+#[test]
+fn runs() {
+    let mut blocks = Blocks::new(3, 1, 2, "minecraft:stone");
+    blocks.set_block_at(2, 0, 0, "minecraft:dirt");
+
+    let runs: Vec<Run> = blocks.runs(AxisOrder::Yzx).collect();
+    assert_eq!(
+        runs,
+        [
+            Run {
+                start: (0, 0, 0),
+                length: 2,
+                block: "minecraft:stone"
+            },
+            Run {
+                start: (2, 0, 0),
+                length: 1,
+                block: "minecraft:dirt"
+            },
+            // Runs don't continue into the next row
+            Run {
+                start: (0, 0, 1),
+                length: 3,
+                block: "minecraft:stone"
+            },
+        ]
+    );
+
+    let lengths: Vec<u32> = blocks.runs(AxisOrder::Xyz).map(|run| run.length).collect();
+    assert_eq!(lengths, [2, 2, 1, 1]);
+}
+
+#[test]
+fn apply_rename_table() {
+    let mut blocks = Blocks::new(3, 1, 1, "minecraft:air");
+    blocks.set_block_at(0, 0, 0, "minecraft:grass_path");
+    blocks.set_block_at(1, 0, 0, "minecraft:dirt_path");
+    blocks.set_block_at(2, 0, 0, "minecraft:stone");
+
+    let mut table = HashMap::new();
+    table.insert(
+        "minecraft:grass_path".to_owned(),
+        "minecraft:dirt_path".to_owned(),
+    );
+    table.insert("minecraft:unused".to_owned(), "minecraft:dirt".to_owned());
+    blocks.apply_rename_table(&table);
+
+    assert_eq!(blocks.get_block_at(0, 0, 0), "minecraft:dirt_path");
+    assert_eq!(blocks.get_block_at(1, 0, 0), "minecraft:dirt_path");
+    assert_eq!(blocks.get_block_at(2, 0, 0), "minecraft:stone");
+    // The two paths are merged into one entry
+    assert_eq!(blocks.blocks_in_palette().count(), 3);
+}
+
+#[test]
+fn positions_of() {
+    let mut blocks = Blocks::new(2, 2, 2, "minecraft:air");
+    blocks.set_block_at(1, 0, 1, "minecraft:chest[facing=north]");
+    blocks.set_block_at(0, 1, 0, "minecraft:chest[facing=south]");
+    blocks.set_block_at(1, 1, 0, "minecraft:redstone_wire");
+
+    assert_eq!(blocks.positions_of("minecraft:redstone_wire"), [(1, 1, 0)]);
+    assert!(blocks.positions_of("minecraft:stone").is_empty());
+    assert_eq!(
+        blocks.positions_where(|block| block.starts_with("minecraft:chest")),
+        [(0, 1, 0), (1, 0, 1)]
+    );
+}
+
+#[test]
+fn clear_region() {
+    let mut blocks = Blocks::new(4, 4, 4, "minecraft:air");
+    blocks.set_block_at(0, 0, 0, "minecraft:stone");
+    blocks.set_block_at(3, 3, 3, "minecraft:dirt");
+    blocks.set_block_at(2, 1, 2, "minecraft:stone");
+
+    let content_max = |blocks: &Blocks| {
+        blocks
+            .cells()
+            .filter(|(_, block)| *block != blocks.background_block())
+            .map(|(pos, _)| pos)
+            .max()
+    };
+    assert_eq!(content_max(&blocks), Some((3, 3, 3)));
+
+    blocks.clear_region((2, 2, 2), (3, 3, 3)).unwrap();
+    assert_eq!(blocks.get_block_at(3, 3, 3), "minecraft:air");
+    assert_eq!(blocks.get_block_at(2, 1, 2), "minecraft:stone");
+    assert_eq!(content_max(&blocks), Some((2, 1, 2)));
+
+    assert!(matches!(
+        blocks.clear_region((0, 0, 0), (4, 0, 0)),
+        Err(SchematicError::OutOfBounds(_))
+    ));
+    assert_eq!(blocks.get_block_at(0, 0, 0), "minecraft:stone");
+}
+
+#[test]
+fn is_empty() {
+    let mut blocks = Blocks::new(3, 3, 3, "minecraft:air");
+    assert!(blocks.is_empty());
+    blocks.set_block_at(1, 1, 1, "minecraft:stone");
+    assert!(!blocks.is_empty());
+    blocks.track_non_air();
+    assert!(!blocks.is_empty());
+    blocks.set_block_at(1, 1, 1, "minecraft:air");
+    assert!(blocks.is_empty());
+
+    let mut blocks = Blocks::new(2, 2, 2, "minecraft:stone");
+    blocks.set_block_at(0, 0, 0, "minecraft:air");
+    blocks.track_non_air();
+    assert!(!blocks.is_empty());
+    blocks.set_block_at(0, 0, 0, "minecraft:stone");
+    assert!(blocks.is_empty());
+}
+
+#[test]
+fn normalize_air() {
+    let mut blocks = Blocks::new(3, 1, 1, "minecraft:air");
+    blocks.set_block_at(0, 0, 0, "minecraft:cave_air");
+    blocks.set_block_at(1, 0, 0, "minecraft:stone");
+    blocks.track_non_air();
+    assert_eq!(blocks.non_air_count(), Some(2));
+
+    blocks.normalize_air(&["minecraft:cave_air"]);
+    assert_eq!(blocks.get_block_at(0, 0, 0), "minecraft:air");
+    assert_eq!(blocks.palette_size_live(), 2);
+    assert_eq!(blocks.non_air_count(), Some(1));
+}
+
+#[test]
+fn set_blocks() {
+    let mut blocks = Blocks::new(2, 2, 2, "minecraft:air");
+    let placements = [
+        ((0, 0, 0), "minecraft:stone"),
+        ((1, 0, 0), "minecraft:stone"),
+        ((1, 1, 1), "minecraft:dirt"),
+    ];
+    blocks.set_blocks(placements).unwrap();
+    assert_eq!(blocks.get_block_at(1, 0, 0), "minecraft:stone");
+    assert_eq!(blocks.get_block_at(1, 1, 1), "minecraft:dirt");
+    assert_eq!(blocks.palette_size_live(), 3);
+
+    // Placements before the out of bounds one stay applied
+    let placements = [((0, 1, 0), "minecraft:dirt"), ((2, 0, 0), "minecraft:dirt")];
+    assert!(matches!(
+        blocks.set_blocks(placements),
+        Err(SchematicError::OutOfBounds(_))
+    ));
+    assert_eq!(blocks.get_block_at(0, 1, 0), "minecraft:dirt");
+
+    // More distinct blocks than are cached, alternating with cached ones
+    let names: Vec<String> = (0..40).map(|i| format!("minecraft:block_{i}")).collect();
+    let mut blocks = Blocks::new(40, 1, 2, "minecraft:air");
+    let placements = names.iter().enumerate().flat_map(|(i, name)| {
+        [
+            ((i as u32, 0, 0), name.as_str()),
+            ((i as u32, 0, 1), "minecraft:stone"),
+        ]
+    });
+    blocks.set_blocks(placements).unwrap();
+    assert_eq!(blocks.get_block_at(39, 0, 0), "minecraft:block_39");
+    assert_eq!(blocks.get_block_at(39, 0, 1), "minecraft:stone");
+    assert_eq!(blocks.palette_size_live(), 42);
+}
@@ -0,0 +1,365 @@
+use mc_schems::{BlockEntity, Blocks, Region, Schematic, SchematicError, SchematicFormat};
+use nbt::Value;
+use std::collections::HashMap;
+
+fn get_vec3(nbt: &HashMap<String, Value>, name: &str) -> (i32, i32, i32) {
+    let Some(Value::Compound(vec)) = nbt.get(name) else {
+        panic!("missing {name}");
+    };
+    let get = |axis| match vec.get(axis) {
+        Some(Value::Int(val)) => *val,
+        _ => panic!("missing {name}.{axis}"),
+    };
+    (get("x"), get("y"), get("z"))
+}
+
+#[test]
+fn write_metadata() {
+    let mut blocks = Blocks::new(3, 2, 4, "minecraft:air");
+    blocks.set_block_at(0, 0, 0, "minecraft:stone");
+    blocks.set_block_at(2, 1, 3, "minecraft:repeater[delay=2,facing=north]");
+    blocks.set_block_at(1, 1, 1, "minecraft:stone");
+    let schem = Schematic::new(blocks).with_data_version(3700);
+
+    let bytes = schem.serialize(SchematicFormat::Litematica(6)).unwrap();
+    let nbt = nbt::Blob::from_gzip_reader(&mut bytes.as_slice()).unwrap();
+    let Some(Value::Compound(metadata)) = nbt.get("Metadata") else {
+        panic!("missing Metadata");
+    };
+
+    assert_eq!(get_vec3(metadata, "EnclosingSize"), (3, 2, 4));
+    assert_eq!(metadata.get("TotalVolume"), Some(&Value::Int(3 * 2 * 4)));
+    assert_eq!(metadata.get("TotalBlocks"), Some(&Value::Int(3)));
+    assert_eq!(metadata.get("RegionCount"), Some(&Value::Int(1)));
+    assert_eq!(
+        metadata.get("Name"),
+        Some(&Value::String("Unnamed".to_owned()))
+    );
+    for key in ["Author", "Description", "TimeCreated", "TimeModified"] {
+        assert!(metadata.contains_key(key), "missing {key}");
+    }
+
+    let Some(Value::Compound(regions)) = nbt.get("Regions") else {
+        panic!("missing Regions");
+    };
+    assert_eq!(regions.len(), 1);
+    let Some(Value::Compound(region)) = regions.get("Unnamed") else {
+        panic!("missing region");
+    };
+    assert_eq!(get_vec3(region, "Size"), (3, 2, 4));
+}
+
+fn test_schematic() -> Schematic {
+    let mut blocks = Blocks::new(3, 2, 4, "minecraft:air");
+    blocks.set_block_at(0, 0, 0, "minecraft:stone");
+    blocks.set_block_at(2, 1, 3, "minecraft:repeater[delay=2,facing=north]");
+    blocks.set_block_at(1, 1, 1, "minecraft:chest[facing=west,type=single]");
+    let mut block_entities = HashMap::new();
+    let mut data = HashMap::new();
+    data.insert("Lock".to_owned(), Value::String("key".to_owned()));
+    block_entities.insert(
+        (1, 1, 1),
+        BlockEntity {
+            id: "minecraft:chest".to_owned(),
+            data,
+        },
+    );
+    let mut schem = Schematic::new(blocks).with_data_version(3700);
+    schem.paste_offset = Some((-1, 0, 2));
+    schem.block_entities = block_entities;
+    schem
+}
+
+#[test]
+fn round_trip() {
+    let schem = test_schematic();
+    let bytes = schem.serialize(SchematicFormat::Litematica(6)).unwrap();
+    let read = Schematic::deserialize(&bytes).unwrap();
+
+    assert_eq!(read.size(), (3, 2, 4));
+    assert_eq!(read.content_hash(), schem.content_hash());
+    assert_eq!(read.paste_offset, Some((-1, 0, 2)));
+    assert_eq!(read.data_version, Some(3700));
+    assert_eq!(read.sub_version, Some(1));
+    assert!(read.metadata_get_i32("SubVersion").is_none());
+    assert_eq!(read.source_format(), Some(SchematicFormat::Litematica(6)));
+    assert_eq!(schem.source_format(), None);
+}
+
+#[test]
+fn sub_version_and_regions() {
+    let bytes = test_schematic()
+        .serialize(SchematicFormat::Litematica(6))
+        .unwrap();
+    let mut nbt = nbt::Blob::from_gzip_reader(&mut bytes.as_slice()).unwrap();
+    nbt.insert("SubVersion", Value::Int(2)).unwrap();
+
+    // Add a second region extending in the negative direction from (-1, 0, 2), containing stone
+    // and a repeater
+    let Some(Value::Compound(regions)) = nbt.content.get_mut("Regions") else {
+        panic!("missing Regions");
+    };
+    let Some(Value::Compound(region)) = regions.get("Unnamed") else {
+        panic!("missing region");
+    };
+    let mut second = region.clone();
+    let vec3 = |x, y, z| {
+        Value::Compound(
+            [("x", x), ("y", y), ("z", z)]
+                .into_iter()
+                .map(|(k, v)| (k.to_owned(), Value::Int(v)))
+                .collect(),
+        )
+    };
+    second.insert("Position".to_owned(), vec3(-1, 0, 2));
+    second.insert("Size".to_owned(), vec3(-2, 1, -1));
+    let Some(Value::List(palette)) = region.get("BlockStatePalette") else {
+        panic!("missing BlockStatePalette");
+    };
+    let entry = |name: &str| {
+        let name = Value::String(name.to_owned());
+        palette
+            .iter()
+            .find(
+                |entry| matches!(entry, Value::Compound(entry) if entry.get("Name") == Some(&name)),
+            )
+            .unwrap()
+            .clone()
+    };
+    second.insert(
+        "BlockStatePalette".to_owned(),
+        Value::List(vec![
+            entry("minecraft:air"),
+            entry("minecraft:stone"),
+            entry("minecraft:repeater"),
+        ]),
+    );
+    second.insert("BlockStates".to_owned(), Value::LongArray(vec![0b1001]));
+    second.insert("TileEntities".to_owned(), Value::List(Vec::new()));
+    regions.insert("Second".to_owned(), Value::Compound(second));
+
+    // The order list only names "Unnamed", so "Second" follows it and overwrites it at (1, 0, 0)
+    let mut data = Vec::new();
+    nbt.to_gzip_writer(&mut data).unwrap();
+    let read = Schematic::deserialize(&data).unwrap();
+    assert_eq!(read.size(), (4, 2, 4));
+    assert_eq!(read.paste_offset, Some((-2, 0, 2)));
+    assert_eq!(read.blocks.get_block_at(0, 0, 0), "minecraft:stone");
+    assert_eq!(
+        read.blocks.get_block_at(1, 0, 0),
+        "minecraft:repeater[delay=2,facing=north]"
+    );
+    assert_eq!(read.blocks.get_block_at(2, 0, 0), "minecraft:air");
+    assert_eq!(read.block_entities[&(2, 1, 1)].id, "minecraft:chest");
+    assert_eq!(read.sub_version, Some(2));
+    assert!(read.metadata_get_i32("SubVersion").is_none());
+    let names: Vec<&str> = read.regions.iter().map(|r| r.name.as_str()).collect();
+    assert_eq!(names, ["Unnamed", "Second"]);
+    assert_eq!(read.regions[0].offset, (1, 0, 0));
+
+    // Both regions and their order survive a round trip
+    let bytes = read.serialize(SchematicFormat::Litematica(6)).unwrap();
+    let written = nbt::Blob::from_gzip_reader(&mut bytes.as_slice()).unwrap();
+    assert_eq!(written.get("SubVersion"), Some(&Value::Int(2)));
+    // Each region only lists the blocks it uses, and blocks where the regions overlap are only
+    // counted once
+    let Some(Value::Compound(regions)) = written.get("Regions") else {
+        panic!("missing Regions");
+    };
+    let palette_len = |name| match regions.get(name) {
+        Some(Value::Compound(region)) => match region.get("BlockStatePalette") {
+            Some(Value::List(palette)) => palette.len(),
+            _ => panic!("missing BlockStatePalette"),
+        },
+        _ => panic!("missing region {name}"),
+    };
+    assert_eq!(read.blocks.palette_size_live(), 4);
+    assert_eq!(palette_len("Unnamed"), 3);
+    assert_eq!(palette_len("Second"), 3);
+    let Some(Value::Compound(metadata)) = written.get("Metadata") else {
+        panic!("missing Metadata");
+    };
+    assert_eq!(metadata.get("TotalBlocks"), Some(&Value::Int(4)));
+    let again = Schematic::deserialize(&bytes).unwrap();
+    assert_eq!(again.regions, read.regions);
+    assert_eq!(again.content_hash(), read.content_hash());
+    assert_eq!(again.paste_offset, read.paste_offset);
+
+    // SubVersion doesn't leak into the metadata of other formats
+    let bytes = read.serialize(SchematicFormat::Sponge(3)).unwrap();
+    let sponge = Schematic::deserialize(&bytes).unwrap();
+    assert!(sponge.metadata_get_i32("SubVersion").is_none());
+
+    // Listing "Second" first makes "Unnamed" overwrite it instead
+    nbt.insert(
+        "RegionOrder",
+        Value::List(vec![
+            Value::String("Second".to_owned()),
+            Value::String("Unnamed".to_owned()),
+        ]),
+    )
+    .unwrap();
+    let mut data = Vec::new();
+    nbt.to_gzip_writer(&mut data).unwrap();
+    let read = Schematic::deserialize(&data).unwrap();
+    let names: Vec<&str> = read.regions.iter().map(|r| r.name.as_str()).collect();
+    assert_eq!(names, ["Second", "Unnamed"]);
+    assert_eq!(read.blocks.get_block_at(1, 0, 0), "minecraft:stone");
+}
+
+#[test]
+fn split_regions() {
+    let mut schem = test_schematic();
+    schem.regions = vec![
+        Region {
+            name: "Bottom".to_owned(),
+            offset: (0, 0, 0),
+            size: (3, 1, 4),
+        },
+        Region {
+            name: "Top".to_owned(),
+            offset: (1, 1, 1),
+            size: (2, 1, 3),
+        },
+    ];
+    let parts = schem.split_regions();
+    assert_eq!(parts.len(), 2);
+
+    assert_eq!(parts[0].size(), (3, 1, 4));
+    assert_eq!(parts[0].origin, Some((-1, 0, 2)));
+    assert_eq!(parts[0].blocks.get_block_at(0, 0, 0), "minecraft:stone");
+    assert!(parts[0].block_entities.is_empty());
+
+    assert_eq!(parts[1].size(), (2, 1, 3));
+    assert_eq!(parts[1].origin, Some((0, 1, 3)));
+    assert_eq!(parts[1].paste_offset, Some((0, 1, 3)));
+    assert_eq!(
+        parts[1].blocks.get_block_at(1, 0, 2),
+        "minecraft:repeater[delay=2,facing=north]"
+    );
+    assert_eq!(parts[1].block_entities[&(0, 0, 0)].id, "minecraft:chest");
+    assert_eq!(parts[1].regions[0].name, "Top");
+}
+
+#[test]
+fn long_packing() {
+    // Both files contain the same blocks, with 3 bits per entry. One lets entries span across two
+    // longs, the other doesn't.
+    let straddle = Schematic::deserialize(include_bytes!("litematica_straddle.litematic")).unwrap();
+    let aligned = Schematic::deserialize(include_bytes!("litematica_aligned.litematic")).unwrap();
+
+    let palette = [
+        "minecraft:air",
+        "minecraft:stone",
+        "minecraft:dirt",
+        "minecraft:cobblestone",
+        "minecraft:oak_planks",
+    ];
+    for schem in [&straddle, &aligned] {
+        assert_eq!(schem.size(), (8, 2, 4));
+        let mut index = 0;
+        for y in 0..2 {
+            for z in 0..4 {
+                for x in 0..8 {
+                    assert_eq!(schem.blocks.get_block_at(x, y, z), palette[index % 5]);
+                    index += 1;
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn crafted_region_sizes() {
+    let bytes = test_schematic()
+        .serialize(SchematicFormat::Litematica(6))
+        .unwrap();
+    let nbt = nbt::Blob::from_gzip_reader(&mut bytes.as_slice()).unwrap();
+    let vec3 = |x, y, z| {
+        Value::Compound(
+            [("x", x), ("y", y), ("z", z)]
+                .into_iter()
+                .map(|(k, v)| (k.to_owned(), Value::Int(v)))
+                .collect(),
+        )
+    };
+    let with_region = |pos: Value, size: Value| {
+        let mut nbt = nbt.clone();
+        let Some(Value::Compound(regions)) = nbt.content.get_mut("Regions") else {
+            panic!("missing Regions");
+        };
+        let Some(Value::Compound(region)) = regions.get_mut("Unnamed") else {
+            panic!("missing region");
+        };
+        region.insert("Position".to_owned(), pos);
+        region.insert("Size".to_owned(), size);
+        let mut data = Vec::new();
+        nbt.to_gzip_writer(&mut data).unwrap();
+        Schematic::deserialize(&data)
+    };
+
+    // A large region without the block data to fill it is rejected before allocating
+    assert!(matches!(
+        with_region(vec3(0, 0, 0), vec3(1000, 1000, 1000)),
+        Err(SchematicError::InvalidValue(field)) if field == "BlockStates"
+    ));
+    // More cells than blocks can be indexed with
+    assert!(matches!(
+        with_region(vec3(0, 0, 0), vec3(i32::MAX, i32::MAX, 2)),
+        Err(SchematicError::TooLarge { .. })
+    ));
+    // Regions reaching past the range of ints
+    assert!(matches!(
+        with_region(vec3(i32::MAX, 0, 0), vec3(2, 1, 1)),
+        Err(SchematicError::InvalidValue(field)) if field == "Size"
+    ));
+    assert!(matches!(
+        with_region(vec3(i32::MIN, 0, 0), vec3(i32::MIN, 1, 1)),
+        Err(SchematicError::InvalidValue(field)) if field == "Size"
+    ));
+}
+
+#[test]
+fn unsupported_version() {
+    let bytes = include_bytes!("litematica_aligned.litematic");
+    let mut nbt = nbt::Blob::from_gzip_reader(&mut bytes.as_slice()).unwrap();
+    for version in [3, 8] {
+        nbt.insert("Version", Value::Int(version)).unwrap();
+        // Files of other versions may lay out their regions differently
+        nbt.insert("Regions", Value::Int(0)).unwrap();
+        let mut data = Vec::new();
+        nbt.to_gzip_writer(&mut data).unwrap();
+        assert!(matches!(
+            Schematic::deserialize(&data),
+            Err(SchematicError::UnsupportedFormat(SchematicFormat::Litematica(v))) if v == version as u32
+        ));
+    }
+
+    nbt.content.remove("Version");
+    let mut data = Vec::new();
+    nbt.to_gzip_writer(&mut data).unwrap();
+    assert!(matches!(
+        Schematic::deserialize(&data),
+        Err(SchematicError::MissingRequiredField(field)) if field == "Version"
+    ));
+}
+
+#[test]
+fn preview_image() {
+    let bytes = include_bytes!("litematica_preview.litematic");
+    let schem = Schematic::deserialize(bytes).unwrap();
+    let preview = schem.preview_image().unwrap();
+    assert_eq!(preview.size, 2);
+    assert_eq!(
+        preview.rgba,
+        [255, 0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255, 255, 255, 255, 128]
+    );
+
+    // The raw data is written back even though it was decoded
+    let bytes = schem.serialize(SchematicFormat::Litematica(6)).unwrap();
+    let read = Schematic::deserialize(&bytes).unwrap();
+    assert_eq!(read.preview_image(), Some(preview));
+
+    let bytes = include_bytes!("litematica_aligned.litematic");
+    assert_eq!(Schematic::deserialize(bytes).unwrap().preview_image(), None);
+}
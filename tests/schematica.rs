@@ -0,0 +1,87 @@
+use mc_schems::{
+    downgrade_block, upgrade_legacy_block, Blocks, Schematic, SchematicError, SchematicFormat,
+    SchematicaFormat,
+};
+use nbt::Value;
+
+#[test]
+fn write_alpha() {
+    let mut schem = Schematic::deserialize(include_bytes!("sponge_v2.schem")).unwrap();
+    schem.blocks = Blocks::new(3, 1, 1, "minecraft:air");
+    schem.blocks.set_block_at(1, 0, 0, "minecraft:white_wool");
+    schem.blocks.set_block_at(2, 0, 0, "minecraft:red_wool");
+    schem.block_entities.clear();
+    let format = SchematicFormat::Schematica(SchematicaFormat::Alpha);
+
+    assert!(matches!(
+        schem.serialize(format),
+        Err(SchematicError::NoLegacyId(_))
+    ));
+
+    schem
+        .downgrade_blocks(|block| match block {
+            "minecraft:white_wool" => Some("35".to_owned()),
+            "minecraft:red_wool" => Some("35:14".to_owned()),
+            _ => None,
+        })
+        .unwrap();
+    let bytes = schem.serialize(format).unwrap();
+    let nbt = nbt::Blob::from_gzip_reader(&mut bytes.as_slice()).unwrap();
+    assert_eq!(nbt.get("Width"), Some(&Value::Short(3)));
+    assert_eq!(nbt.get("Blocks"), Some(&Value::ByteArray(vec![0, 35, 35])));
+    assert_eq!(nbt.get("Data"), Some(&Value::ByteArray(vec![0, 0, 14])));
+    assert_eq!(nbt.get("AddBlocks"), None);
+
+    // Ids above 255 need the AddBlocks array
+    schem.blocks.set_block_at(1, 0, 0, "300:1");
+    let bytes = schem.serialize(format).unwrap();
+    let nbt = nbt::Blob::from_gzip_reader(&mut bytes.as_slice()).unwrap();
+    assert_eq!(nbt.get("Blocks"), Some(&Value::ByteArray(vec![0, 44, 35])));
+    assert_eq!(nbt.get("AddBlocks"), Some(&Value::ByteArray(vec![0x10, 0])));
+}
+
+#[test]
+fn legacy_table() {
+    assert_eq!(
+        downgrade_block("minecraft:red_wool").as_deref(),
+        Some("35:14")
+    );
+    assert_eq!(downgrade_block("minecraft:stone").as_deref(), Some("1"));
+    // Properties legacy blocks don't store are ignored
+    assert_eq!(
+        downgrade_block(
+            "minecraft:oak_stairs[facing=south,half=top,shape=inner_left,waterlogged=false]"
+        )
+        .as_deref(),
+        Some("53:6")
+    );
+    assert_eq!(
+        downgrade_block("minecraft:water[level=0]").as_deref(),
+        Some("9")
+    );
+    assert_eq!(downgrade_block("minecraft:oak_stairs"), None);
+    assert_eq!(downgrade_block("minecraft:deepslate"), None);
+    for block in ["1", "35:14", "53:6", "162:13", "251:15"] {
+        let upgraded = upgrade_legacy_block(block).unwrap();
+        assert_eq!(downgrade_block(&upgraded).as_deref(), Some(block));
+    }
+
+    let mut schem = Schematic::new(Blocks::new(2, 1, 1, "minecraft:air")).with_data_version(1343);
+    schem.blocks.set_block_at(0, 0, 0, "minecraft:birch_planks");
+    // Unused entries don't need a legacy id, neither when downgrading nor when writing
+    schem.blocks.set_block_at(1, 0, 0, "minecraft:deepslate");
+    schem.blocks.set_block_at(1, 0, 0, "minecraft:air");
+    schem.downgrade_blocks(downgrade_block).unwrap();
+    assert_eq!(schem.blocks.get_block_at(0, 0, 0), "5:2");
+    let format = SchematicFormat::Schematica(SchematicaFormat::Alpha);
+    let bytes = schem.serialize(format).unwrap();
+    let nbt = nbt::Blob::from_gzip_reader(&mut bytes.as_slice()).unwrap();
+    assert_eq!(nbt.get("Blocks"), Some(&Value::ByteArray(vec![5, 0])));
+    assert_eq!(nbt.get("Data"), Some(&Value::ByteArray(vec![2, 0])));
+
+    schem.blocks.set_block_at(1, 0, 0, "minecraft:deepslate");
+    assert!(matches!(
+        schem.downgrade_blocks(downgrade_block),
+        Err(SchematicError::NoLegacyId(block)) if block == "minecraft:deepslate"
+    ));
+}
@@ -1,4 +1,9 @@
-use mc_schems::Schematic;
+use mc_schems::{
+    block_state_from_nbt, upgrade_legacy_block, BlockEntity, Blocks, Entity, ItemStack, Region,
+    Schematic, SchematicError, SchematicFormat, SchematicaFormat, ITEM_COMPONENTS_DATA_VERSION,
+};
+use nbt::Value;
+use std::collections::HashMap;
 
 #[test]
 fn sponge_v2() {
@@ -11,6 +16,7 @@ fn sponge_v2() {
     assert_eq!(schem.paste_offset, Some((1, 0, 1)));
     assert_eq!(schem.origin, Some((1, 0, 2)));
     assert!(schem.metadata.is_none());
+    assert_eq!(schem.source_format(), Some(SchematicFormat::Sponge(2)));
 }
 
 #[test]
@@ -22,4 +28,1729 @@ fn sponge_v3() {
     assert!(schem.block_entities.is_empty());
     assert!(schem.biomes.is_none());
     assert_eq!(schem.paste_offset, Some((1, 0, 1)));
+    assert_eq!(schem.source_format(), Some(SchematicFormat::Sponge(3)));
+}
+
+#[test]
+fn sponge_v3_no_offset() {
+    let schem = Schematic::deserialize(include_bytes!("sponge_v3_no_offset.schem")).unwrap();
+    assert_eq!(schem.blocks.size(), (2, 2, 2));
+    assert_eq!(schem.paste_offset, None);
+
+    // The offset is required when writing, so it defaults to zero
+    let bytes = schem.serialize(SchematicFormat::Sponge(3)).unwrap();
+    let read = Schematic::deserialize(&bytes).unwrap();
+    assert_eq!(read.paste_offset, Some((0, 0, 0)));
+}
+
+#[test]
+fn block_entity_out_of_bounds() {
+    let bytes = include_bytes!("sponge_v2.schem");
+    let mut schem = Schematic::deserialize(bytes).unwrap();
+    schem.block_entities.insert(
+        (2, 0, 0),
+        BlockEntity {
+            id: "minecraft:chest".to_owned(),
+            data: HashMap::new(),
+        },
+    );
+
+    let result = schem.serialize(SchematicFormat::Sponge(2));
+    assert!(matches!(result, Err(SchematicError::InvalidValue(field)) if field == "BlockEntities"));
+
+    schem.block_entities.clear();
+    assert!(schem.serialize(SchematicFormat::Sponge(2)).is_ok());
+}
+
+#[test]
+fn sponge_v3_no_version() {
+    let bytes = include_bytes!("sponge_v3_no_version.schem");
+    let schem = Schematic::deserialize(bytes).unwrap();
+
+    assert_eq!(schem.blocks.size(), (2, 2, 2));
+    assert_eq!(schem.paste_offset, Some((1, 0, 1)));
+}
+
+#[test]
+fn sponge_v2_biomes() {
+    let bytes = include_bytes!("sponge_v2_biomes.schem");
+    let schem = Schematic::deserialize(bytes).unwrap();
+
+    let biomes = schem.biomes.unwrap();
+    assert_eq!(biomes.size(), (2, 1, 2));
+    assert_eq!(biomes.get_block_at(0, 0, 0), "minecraft:plains");
+    assert_eq!(biomes.get_block_at(1, 0, 0), "minecraft:desert");
+    assert_eq!(biomes.get_block_at(0, 0, 1), "minecraft:plains");
+    assert_eq!(biomes.get_block_at(1, 0, 1), "minecraft:desert");
+    assert_eq!(biomes.blocks_in_palette().count(), 2);
+}
+
+#[test]
+fn sponge_v2_quarter_biomes() {
+    let with_biomes = |data: Vec<i8>| {
+        modified_v2(|nbt| {
+            let mut palette = HashMap::new();
+            palette.insert("minecraft:desert".to_owned(), Value::Int(0));
+            palette.insert("minecraft:plains".to_owned(), Value::Int(1));
+            nbt.insert("BiomePalette".to_owned(), Value::Compound(palette));
+            nbt.insert("BiomeData".to_owned(), Value::ByteArray(data));
+        })
+    };
+
+    // A single entry covers all 2x2 columns
+    let schem = Schematic::deserialize(&with_biomes(vec![1])).unwrap();
+    let biomes = schem.biomes.unwrap();
+    assert_eq!(biomes.size(), (2, 1, 2));
+    assert_eq!(biomes.get_block_at(1, 0, 1), "minecraft:plains");
+
+    let schem = Schematic::deserialize(&with_biomes(vec![0, 1, 1, 0])).unwrap();
+    assert_eq!(
+        schem.biomes.unwrap().get_block_at(1, 0, 0),
+        "minecraft:plains"
+    );
+
+    assert!(matches!(
+        Schematic::deserialize(&with_biomes(vec![0, 1])),
+        Err(SchematicError::InvalidValue(_))
+    ));
+}
+
+fn modified_v2(f: impl FnOnce(&mut HashMap<String, Value>)) -> Vec<u8> {
+    let bytes = include_bytes!("sponge_v2.schem");
+    let mut nbt = nbt::Blob::from_gzip_reader(&mut bytes.as_slice()).unwrap();
+    f(&mut nbt.content);
+    let mut data = Vec::new();
+    nbt.to_gzip_writer(&mut data).unwrap();
+    data
+}
+
+#[test]
+fn malformed_input() {
+    let bytes = include_bytes!("sponge_v2.schem");
+    for len in 0..bytes.len() {
+        let _ = Schematic::deserialize(&bytes[..len]);
+    }
+
+    // Simple LCG so the test is deterministic
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    for _ in 0..1000 {
+        let data: Vec<u8> = (0..64)
+            .map(|_| {
+                state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+                (state >> 56) as u8
+            })
+            .collect();
+        assert!(Schematic::deserialize(&data).is_err());
+    }
+
+    let cases: Vec<Box<dyn FnOnce(&mut HashMap<String, Value>)>> = vec![
+        Box::new(|nbt| {
+            nbt.insert("Width".to_owned(), Value::Short(-1));
+        }),
+        Box::new(|nbt| {
+            nbt.insert("Height".to_owned(), Value::Short(i16::MAX));
+        }),
+        Box::new(|nbt| {
+            nbt.insert("BlockData".to_owned(), Value::ByteArray(vec![0; 3]));
+        }),
+        Box::new(|nbt| {
+            nbt.insert("BlockData".to_owned(), Value::ByteArray(vec![-1; 16]));
+        }),
+        Box::new(|nbt| {
+            nbt.insert("BlockData".to_owned(), Value::ByteArray(vec![100; 8]));
+        }),
+        Box::new(|nbt| {
+            nbt.insert("Offset".to_owned(), Value::IntArray(vec![1]));
+        }),
+        Box::new(|nbt| {
+            let mut block_entity = HashMap::new();
+            block_entity.insert("Id".to_owned(), Value::String("minecraft:chest".to_owned()));
+            block_entity.insert("Pos".to_owned(), Value::IntArray(vec![0, -1, 0]));
+            nbt.insert(
+                "BlockEntities".to_owned(),
+                Value::List(vec![Value::Compound(block_entity)]),
+            );
+        }),
+    ];
+    for case in cases {
+        assert!(Schematic::deserialize(&modified_v2(case)).is_err());
+    }
+}
+
+#[test]
+fn sponge_v2_byte_palette() {
+    let bytes = include_bytes!("sponge_v2_byte_palette.schem");
+    let schem = Schematic::deserialize(bytes).unwrap();
+
+    assert_eq!(schem.blocks.size(), (2, 2, 2));
+    assert_eq!(
+        schem.blocks.get_block_at(0, 0, 0),
+        "minecraft:polished_diorite"
+    );
+    assert_eq!(schem.blocks.get_block_at(1, 1, 1), "minecraft:air");
+}
+
+#[test]
+fn duplicate_palette_ids() {
+    // Two blocks sharing an id make the data ambiguous
+    let bytes = modified_v2(|nbt| {
+        let Some(Value::Compound(palette)) = nbt.get_mut("Palette") else {
+            panic!("missing Palette");
+        };
+        palette.insert("minecraft:stone".to_owned(), Value::Int(1));
+    });
+    assert!(matches!(
+        Schematic::deserialize(&bytes),
+        Err(SchematicError::InvalidValue(name)) if name == "Palette"
+    ));
+
+    // Unused ids are fine
+    let bytes = modified_v2(|nbt| {
+        let Some(Value::Compound(palette)) = nbt.get_mut("Palette") else {
+            panic!("missing Palette");
+        };
+        palette.insert("minecraft:stone".to_owned(), Value::Int(6));
+    });
+    let schem = Schematic::deserialize(&bytes).unwrap();
+    assert_eq!(
+        schem.blocks.get_block_at(0, 0, 0),
+        "minecraft:polished_diorite"
+    );
+}
+
+#[test]
+fn content_hash() {
+    let v2 = Schematic::deserialize(include_bytes!("sponge_v2.schem")).unwrap();
+    let mut v3 = Schematic::deserialize(include_bytes!("sponge_v3.schem")).unwrap();
+    // The two fixtures only differ in their format and metadata
+    assert_eq!(v2.content_hash(), v3.content_hash());
+
+    v3.blocks.set_block_at(0, 0, 0, "minecraft:stone");
+    assert_ne!(v2.content_hash(), v3.content_hash());
+}
+
+#[test]
+fn sponge_v2_round_trip() {
+    let schem = Schematic::deserialize(include_bytes!("sponge_v2.schem")).unwrap();
+    let bytes = schem.serialize(SchematicFormat::Sponge(2)).unwrap();
+
+    let nbt = nbt::Blob::from_gzip_reader(&mut bytes.as_slice()).unwrap();
+    assert_eq!(nbt.get("Version"), Some(&Value::Int(2)));
+    assert!(matches!(nbt.get("BlockData"), Some(Value::ByteArray(_))));
+
+    let read = Schematic::deserialize(&bytes).unwrap();
+    assert_eq!(read.size(), schem.size());
+    assert_eq!(read.content_hash(), schem.content_hash());
+    assert_eq!(read.paste_offset, schem.paste_offset);
+    assert_eq!(read.origin, schem.origin);
+    assert_eq!(read.data_version, schem.data_version);
+    assert_eq!(read.metadata, schem.metadata);
+}
+
+#[test]
+fn layers() {
+    let schem = Schematic::deserialize(include_bytes!("sponge_v2.schem")).unwrap();
+
+    let layers: Vec<_> = schem.layers().collect();
+    assert_eq!(layers.len(), 2);
+    for (y, layer) in layers.iter().enumerate() {
+        assert_eq!(layer.size(), (2, 1, 2));
+        for x in 0..2 {
+            for z in 0..2 {
+                assert_eq!(
+                    layer.get_block_at(x, 0, z),
+                    schem.blocks.get_block_at(x, y as u32, z)
+                );
+            }
+        }
+    }
+    assert!(matches!(
+        schem.layer(2),
+        Err(SchematicError::OutOfBounds(_))
+    ));
+}
+
+#[test]
+fn block_entity_data_round_trip() {
+    let mut schem = Schematic::deserialize(include_bytes!("sponge_v2.schem")).unwrap();
+    let mut data = HashMap::new();
+    data.insert("CustomName".to_owned(), Value::String("Loot".to_owned()));
+    schem.block_entities.insert(
+        (1, 0, 1),
+        BlockEntity {
+            id: "minecraft:chest".to_owned(),
+            data,
+        },
+    );
+
+    let v3 = schem.serialize(SchematicFormat::Sponge(3)).unwrap();
+    let nbt = nbt::Blob::from_gzip_reader(&mut v3.as_slice()).unwrap();
+    let Some(Value::Compound(nbt)) = nbt.get("Schematic") else {
+        panic!("missing Schematic");
+    };
+    let Some(Value::Compound(blocks)) = nbt.get("Blocks") else {
+        panic!("missing Blocks");
+    };
+    let Some(Value::List(block_entities)) = blocks.get("BlockEntities") else {
+        panic!("missing BlockEntities");
+    };
+    let Value::Compound(block_entity) = &block_entities[0] else {
+        panic!("mistyped block entity");
+    };
+    assert!(
+        matches!(block_entity.get("Data"), Some(Value::Compound(data)) if data.contains_key("CustomName"))
+    );
+    assert!(!block_entity.contains_key("CustomName"));
+
+    let from_v3 = Schematic::deserialize(&v3).unwrap();
+    let v2 = from_v3.serialize(SchematicFormat::Sponge(2)).unwrap();
+    let from_v2 = Schematic::deserialize(&v2).unwrap();
+    for read in [from_v3, from_v2] {
+        let block_entity = &read.block_entities[&(1, 0, 1)];
+        assert_eq!(block_entity.id, "minecraft:chest");
+        assert_eq!(block_entity.data, schem.block_entities[&(1, 0, 1)].data);
+    }
+}
+
+#[test]
+fn nested_in_nbt() {
+    let bytes = include_bytes!("sponge_v2.schem");
+    let schem_nbt = nbt::Blob::from_gzip_reader(&mut bytes.as_slice()).unwrap();
+    let mut data = HashMap::new();
+    data.insert("schematic".to_owned(), Value::Compound(schem_nbt.content));
+    let mut nbt = nbt::Blob::new();
+    nbt.insert("data", Value::Compound(data)).unwrap();
+
+    let schem = Schematic::deserialize_from_nbt(&nbt, "data.schematic").unwrap();
+    assert_eq!(schem.size(), (2, 2, 2));
+    assert!(matches!(
+        Schematic::deserialize_from_nbt(&nbt, "data.missing"),
+        Err(SchematicError::MissingRequiredField(_))
+    ));
+}
+
+#[test]
+fn size_limit() {
+    let bytes = include_bytes!("sponge_v3.schem");
+    assert!(Schematic::deserialize_with_limit(bytes, 8).is_ok());
+    assert!(matches!(
+        Schematic::deserialize_with_limit(bytes, 7),
+        Err(SchematicError::TooLarge { cells: 8, limit: 7 })
+    ));
+}
+
+#[test]
+fn upgrade_legacy_blocks() {
+    let mut schem = Schematic::deserialize(include_bytes!("sponge_v2.schem")).unwrap();
+    schem.blocks.set_block_at(0, 0, 0, "35:14");
+    schem.blocks.set_block_at(1, 0, 0, "1");
+    assert!(matches!(
+        schem.serialize(SchematicFormat::Sponge(3)),
+        Err(SchematicError::LegacyBlock(_))
+    ));
+
+    let upgrade = |block: &str| match block {
+        "35:14" => Some("minecraft:red_wool".to_owned()),
+        _ => None,
+    };
+    assert!(matches!(
+        schem.upgrade_legacy_blocks(upgrade),
+        Err(SchematicError::LegacyBlock(block)) if block == "1"
+    ));
+    assert_eq!(schem.blocks.get_block_at(0, 0, 0), "35:14");
+
+    let upgrade = |block: &str| match block {
+        "35:14" => Some("minecraft:red_wool".to_owned()),
+        "1" => Some("minecraft:stone".to_owned()),
+        _ => None,
+    };
+    schem.upgrade_legacy_blocks(upgrade).unwrap();
+    assert_eq!(schem.blocks.get_block_at(0, 0, 0), "minecraft:red_wool");
+    assert_eq!(schem.blocks.get_block_at(1, 0, 0), "minecraft:stone");
+    assert!(schem.serialize(SchematicFormat::Sponge(3)).is_ok());
+}
+
+#[test]
+fn legacy_table() {
+    assert_eq!(
+        upgrade_legacy_block("1").as_deref(),
+        Some("minecraft:stone")
+    );
+    assert_eq!(
+        upgrade_legacy_block("35:14").as_deref(),
+        Some("minecraft:red_wool")
+    );
+    assert_eq!(
+        upgrade_legacy_block("53:6").as_deref(),
+        Some("minecraft:oak_stairs[facing=south,half=top]")
+    );
+    assert_eq!(upgrade_legacy_block("35:16"), None);
+    assert_eq!(upgrade_legacy_block("minecraft:stone"), None);
+
+    let mut schem = Schematic::deserialize(include_bytes!("sponge_v2.schem")).unwrap();
+    schem.blocks.set_block_at(0, 0, 0, "5:2");
+    // Unused entries don't have to be upgraded and are left out when writing
+    schem.blocks.set_block_at(1, 0, 0, "26:3");
+    schem.blocks.set_block_at(1, 0, 0, "minecraft:stone");
+    schem.upgrade_legacy_blocks(upgrade_legacy_block).unwrap();
+    assert_eq!(schem.blocks.get_block_at(0, 0, 0), "minecraft:birch_planks");
+
+    let bytes = schem.serialize(SchematicFormat::Sponge(3)).unwrap();
+    let read = Schematic::deserialize(&bytes).unwrap();
+    assert!(read.blocks.blocks_in_palette().all(|block| block != "26:3"));
+    assert_eq!(read.blocks.get_block_at(0, 0, 0), "minecraft:birch_planks");
+
+    schem.blocks.set_block_at(1, 0, 0, "26:3");
+    assert!(matches!(
+        schem.upgrade_legacy_blocks(upgrade_legacy_block),
+        Err(SchematicError::LegacyBlock(block)) if block == "26:3"
+    ));
+}
+
+#[test]
+fn deserialize_canonical() {
+    let mut schem = Schematic::new(Blocks::new(3, 1, 1, "minecraft:air")).with_data_version(3700);
+    schem
+        .blocks
+        .set_block_at(0, 0, 0, "minecraft:oak_stairs[half=top,facing=north]");
+    schem
+        .blocks
+        .set_block_at(1, 0, 0, "minecraft:oak_stairs[facing=north,half=top]");
+    schem
+        .blocks
+        .set_block_at(2, 0, 0, "minecraft:repeater[facing=east,delay=2]");
+    let bytes = schem.serialize(SchematicFormat::Sponge(3)).unwrap();
+
+    // Block states are kept as written by default
+    let read = Schematic::deserialize(&bytes).unwrap();
+    assert_eq!(
+        read.blocks.get_block_at(0, 0, 0),
+        "minecraft:oak_stairs[half=top,facing=north]"
+    );
+    assert_eq!(read.blocks.palette_size_live(), 4);
+
+    let read = Schematic::deserialize_canonical(&bytes).unwrap();
+    assert_eq!(read.blocks.palette_size_live(), 3);
+    assert_eq!(
+        read.blocks.get_block_at(0, 0, 0),
+        "minecraft:oak_stairs[facing=north,half=top]"
+    );
+    assert_eq!(
+        read.blocks.get_block_at(1, 0, 0),
+        read.blocks.get_block_at(0, 0, 0)
+    );
+    assert_eq!(
+        read.blocks.get_block_at(2, 0, 0),
+        "minecraft:repeater[delay=2,facing=east]"
+    );
+}
+
+#[test]
+fn sponge_v2_int_size() {
+    let bytes = include_bytes!("sponge_v2_int_size.schem");
+    let schem = Schematic::deserialize(bytes).unwrap();
+
+    assert_eq!(schem.blocks.size(), (2, 2, 2));
+    assert_eq!(
+        schem.content_hash(),
+        Schematic::deserialize(include_bytes!("sponge_v2.schem"))
+            .unwrap()
+            .content_hash()
+    );
+}
+
+#[test]
+fn deserialize_with_progress() {
+    let bytes = include_bytes!("sponge_v2.schem");
+    let mut reports = Vec::new();
+    let schem =
+        Schematic::deserialize_with_progress(bytes, &mut |done, total| reports.push((done, total)))
+            .unwrap();
+
+    let (size_x, size_y, size_z) = schem.size();
+    let total = (size_x * size_y * size_z) as u64;
+    assert!(!reports.is_empty());
+    assert!(reports.windows(2).all(|w| w[0].0 <= w[1].0));
+    assert!(reports.iter().all(|&(_, t)| t == total));
+    assert_eq!(reports.last(), Some(&(total, total)));
+}
+
+#[test]
+fn root_title() {
+    let read_title = |data: Vec<u8>| {
+        nbt::Blob::from_gzip_reader(&mut data.as_slice())
+            .unwrap()
+            .title
+    };
+
+    let mut schem = Schematic::deserialize(include_bytes!("sponge_v2.schem")).unwrap();
+    assert_eq!(schem.root_title.as_deref(), Some("Schematic"));
+    schem.root_title = Some("Custom".to_owned());
+    let data = schem.serialize(SchematicFormat::Sponge(2)).unwrap();
+    assert_eq!(read_title(data), "Custom");
+
+    let mut schem = Schematic::deserialize(include_bytes!("sponge_v3.schem")).unwrap();
+    assert_eq!(schem.root_title.as_deref(), Some(""));
+
+    // Without a title, the default of the format is used
+    schem.root_title = None;
+    let data = schem.serialize(SchematicFormat::Sponge(2)).unwrap();
+    assert_eq!(read_title(data), "Schematic");
+
+    // The title is only kept when writing the format it was read from
+    let v2 = Schematic::deserialize(include_bytes!("sponge_v2.schem")).unwrap();
+    let v3 = Schematic::deserialize(include_bytes!("sponge_v3.schem")).unwrap();
+    let data = v2.serialize(SchematicFormat::Sponge(3)).unwrap();
+    assert_eq!(read_title(data), "");
+    let data = v3.serialize(SchematicFormat::Sponge(2)).unwrap();
+    assert_eq!(read_title(data), "Schematic");
+    let data = v2.serialize(SchematicFormat::Litematica(6)).unwrap();
+    assert_eq!(read_title(data), "");
+
+    // Schematics that weren't read from a file use their title for every format
+    let mut schem = Schematic::new(v2.blocks.clone()).with_data_version(3700);
+    schem.root_title = Some("Custom".to_owned());
+    let data = schem.serialize(SchematicFormat::Sponge(3)).unwrap();
+    assert_eq!(read_title(data), "Custom");
+}
+
+#[test]
+fn entity_round_trip() {
+    let mut schem = Schematic::deserialize(include_bytes!("sponge_v2.schem")).unwrap();
+    let mut data = HashMap::new();
+    data.insert("Invisible".to_owned(), Value::Byte(1));
+    schem.entities.push(Entity {
+        id: "minecraft:armor_stand".to_owned(),
+        pos: (1.5, 2.0, 3.25),
+        data,
+    });
+
+    for version in [2, 3] {
+        let bytes = schem.serialize(SchematicFormat::Sponge(version)).unwrap();
+        let read = Schematic::deserialize(&bytes).unwrap();
+        assert_eq!(read.entities.len(), 1);
+        let entity = &read.entities[0];
+        assert_eq!(entity.id, "minecraft:armor_stand");
+        assert_eq!(entity.pos, (1.5, 2.0, 3.25));
+        assert_eq!(entity.data.get("Invisible"), Some(&Value::Byte(1)));
+        assert!(!entity.data.contains_key("Pos"));
+    }
+}
+
+#[test]
+fn setblock_commands() {
+    let mut schem = Schematic::deserialize(include_bytes!("sponge_v2.schem")).unwrap();
+    schem.paste_offset = Some((1, 0, -1));
+    let (size_x, size_y, size_z) = schem.size();
+
+    let all = schem.to_setblock_commands(true);
+    assert_eq!(all.len(), (size_x * size_y * size_z) as usize);
+    assert!(all.contains(&format!(
+        "setblock ~1 ~0 ~-1 {}",
+        schem.blocks.get_block_at(0, 0, 0)
+    )));
+
+    let without_air = schem.to_setblock_commands(false);
+    let non_air = schem
+        .blocks
+        .cells()
+        .filter(|(_, block)| *block != "minecraft:air")
+        .count();
+    assert_eq!(without_air.len(), non_air);
+    assert!(without_air.iter().all(|c| !c.ends_with(" minecraft:air")));
+}
+
+#[test]
+fn fill_commands() {
+    let mut schem = Schematic::deserialize(include_bytes!("sponge_v2.schem")).unwrap();
+    schem.blocks = Blocks::new(5, 1, 2, "minecraft:stone");
+    schem
+        .blocks
+        .set_block_at(2, 0, 0, "minecraft:chest[facing=north]");
+    for x in 0..4 {
+        schem.blocks.set_block_at(x, 0, 1, "minecraft:air");
+    }
+    // The newline has to be escaped to keep the command on one line
+    let mut data = HashMap::new();
+    data.insert(
+        "CustomName".to_owned(),
+        Value::String("{\"text\":\"Loot\nChest\"}".to_owned()),
+    );
+    schem.block_entities.clear();
+    schem.block_entities.insert(
+        (2, 0, 0),
+        BlockEntity {
+            id: "minecraft:chest".to_owned(),
+            data,
+        },
+    );
+
+    let commands = schem.to_fill_commands((10, 64, -5), false);
+    assert_eq!(
+        commands,
+        [
+            "fill 10 64 -5 11 64 -5 minecraft:stone",
+            "fill 13 64 -5 14 64 -5 minecraft:stone",
+            "setblock 14 64 -4 minecraft:stone",
+            r#"setblock 12 64 -5 minecraft:chest[facing=north]{CustomName:"{\"text\":\"Loot\nChest\"}"}"#,
+        ]
+    );
+
+    let commands = schem.to_fill_commands((0, 0, 0), true);
+    assert_eq!(commands[2], "fill 0 0 1 3 0 1 minecraft:air");
+    assert_eq!(commands.len(), 5);
+}
+
+#[test]
+fn world_bounds() {
+    let mut schem = Schematic::deserialize(include_bytes!("sponge_v2.schem")).unwrap();
+    assert_eq!(schem.size(), (2, 2, 2));
+    schem.origin = None;
+    assert_eq!(schem.world_bounds(), Some(((0, 0, 0), (1, 1, 1))));
+    assert_eq!(schem.center(), Some((1.0, 1.0, 1.0)));
+
+    schem.origin = Some((-10, 64, 3));
+    assert_eq!(schem.world_bounds(), Some(((-10, 64, 3), (-9, 65, 4))));
+    assert_eq!(schem.center(), Some((-9.0, 65.0, 4.0)));
+}
+
+#[test]
+fn translate() {
+    let mut schem = Schematic::deserialize(include_bytes!("sponge_v2.schem")).unwrap();
+    schem.origin = Some((-10, 64, 3));
+    schem.paste_offset = None;
+    schem.block_entities.clear();
+    schem.block_entities.insert(
+        (1, 0, 1),
+        BlockEntity {
+            id: "minecraft:chest".to_owned(),
+            data: HashMap::new(),
+        },
+    );
+    schem.entities = vec![Entity {
+        id: "minecraft:armor_stand".to_owned(),
+        pos: (0.5, 1.0, 0.5),
+        data: HashMap::new(),
+    }];
+    schem.regions = vec![
+        Region {
+            name: "West".to_owned(),
+            offset: (0, 0, 0),
+            size: (1, 2, 2),
+        },
+        Region {
+            name: "East".to_owned(),
+            offset: (1, 0, 0),
+            size: (1, 2, 2),
+        },
+    ];
+    let before = schem.clone();
+
+    schem.translate(5, -4, 2);
+    assert_eq!(schem.origin, Some((-5, 60, 5)));
+    assert_eq!(schem.paste_offset, Some((5, -4, 2)));
+    // The contents keep their positions within the schematic, so they move along with it in the
+    // world
+    assert_eq!(schem.content_hash(), before.content_hash());
+    assert_eq!(schem.world_bounds(), Some(((-5, 60, 5), (-4, 61, 6))));
+    let world = |schem: &Schematic, (x, y, z): (u32, u32, u32)| {
+        let (ox, oy, oz) = schem.origin.unwrap();
+        (ox + x as i32, oy + y as i32, oz + z as i32)
+    };
+    let pos = *schem.block_entities.keys().next().unwrap();
+    assert_eq!(pos, (1, 0, 1));
+    assert_eq!(world(&schem, pos), (-4, 60, 6));
+    assert_eq!(schem.entities[0].pos, before.entities[0].pos);
+    assert_eq!(schem.regions[1].offset, before.regions[1].offset);
+    assert_eq!(world(&schem, schem.regions[1].offset), (-4, 60, 5));
+
+    // Litematica stores the position of every region relative to the player
+    let bytes = schem.serialize(SchematicFormat::Litematica(6)).unwrap();
+    let nbt = nbt::Blob::from_gzip_reader(&mut bytes.as_slice()).unwrap();
+    let Some(Value::Compound(regions)) = nbt.get("Regions") else {
+        panic!("missing Regions");
+    };
+    let Some(Value::Compound(east)) = regions.get("East") else {
+        panic!("missing region");
+    };
+    let Some(Value::Compound(position)) = east.get("Position") else {
+        panic!("missing Position");
+    };
+    assert_eq!(position.get("x"), Some(&Value::Int(6)));
+    assert_eq!(position.get("y"), Some(&Value::Int(-4)));
+    assert_eq!(position.get("z"), Some(&Value::Int(2)));
+}
+
+#[test]
+fn sponge_v3_chest() {
+    let bytes = include_bytes!("sponge_v3_chest.schem");
+    let schem = Schematic::deserialize(bytes).unwrap();
+
+    assert_eq!(
+        schem.blocks.get_block_at(1, 1, 0),
+        "minecraft:chest[facing=north,type=single,waterlogged=false]"
+    );
+    let chest = &schem.block_entities[&(1, 1, 0)];
+    assert_eq!(chest.id, "minecraft:chest");
+    // The Data compound is unwrapped
+    assert!(!chest.data.contains_key("Data"));
+    assert!(chest.data.contains_key("CustomName"));
+    let items = chest.items(schem.data_version).unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].id, "minecraft:diamond");
+    assert_eq!(items[0].count, 3);
+
+    // And wrapped again when writing
+    let bytes = schem.serialize(SchematicFormat::Sponge(3)).unwrap();
+    let read = Schematic::deserialize(&bytes).unwrap();
+    let chest = &read.block_entities[&(1, 1, 0)];
+    assert!(!chest.data.contains_key("Data"));
+    assert_eq!(chest.items(read.data_version).unwrap().len(), 1);
+}
+
+#[test]
+fn blocks_only() {
+    let bytes = include_bytes!("sponge_v3_chest.schem");
+    let schem = Schematic::deserialize(bytes).unwrap();
+    let blocks = Schematic::deserialize_blocks_only(bytes).unwrap();
+
+    let (size_x, size_y, size_z) = schem.size();
+    for x in 0..size_x {
+        for y in 0..size_y {
+            for z in 0..size_z {
+                assert_eq!(
+                    blocks.get_block_at(x, y, z),
+                    schem.blocks.get_block_at(x, y, z)
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn truncated_block_data() {
+    let bytes = include_bytes!("sponge_v2_truncated.schem");
+    assert!(matches!(
+        Schematic::deserialize(bytes),
+        Err(SchematicError::CorruptData {
+            expected: 8,
+            actual: 5
+        })
+    ));
+
+    // Data ending in the middle of a varint is rejected even if the complete varints add up
+    let with_block_data = |f: fn(&mut Vec<i8>)| {
+        modified_v2(|nbt| {
+            let Some(Value::ByteArray(data)) = nbt.get_mut("BlockData") else {
+                panic!("missing BlockData");
+            };
+            f(data);
+        })
+    };
+    let bytes = with_block_data(|data| data.push(0x80u8 as i8));
+    assert!(matches!(
+        Schematic::deserialize(&bytes),
+        Err(SchematicError::CorruptData {
+            expected: 8,
+            actual: 9
+        })
+    ));
+    let bytes = with_block_data(|data| *data.last_mut().unwrap() = 0x80u8 as i8);
+    assert!(matches!(
+        Schematic::deserialize(&bytes),
+        Err(SchematicError::CorruptData {
+            expected: 8,
+            actual: 7
+        })
+    ));
+}
+
+#[test]
+fn large_palette_round_trip() {
+    // Palette indices of 128 and above take up more than one byte
+    let mut schem = Schematic::deserialize(include_bytes!("sponge_v2.schem")).unwrap();
+    schem.blocks = Blocks::from_fn(16, 2, 16, |x, y, z| {
+        format!("minecraft:test_{}", (y * 256 + x * 16 + z) % 300)
+    });
+    schem.block_entities.clear();
+
+    for version in [2, 3] {
+        let bytes = schem.serialize(SchematicFormat::Sponge(version)).unwrap();
+        let read = Schematic::deserialize(&bytes).unwrap();
+        for ((x, y, z), block) in schem.blocks.cells() {
+            assert_eq!(read.blocks.get_block_at(x, y, z), block);
+        }
+    }
+}
+
+#[test]
+fn block_entity_ids() {
+    let mut schem = Schematic::deserialize(include_bytes!("sponge_v3_chest.schem")).unwrap();
+    assert_eq!(
+        schem.block_entity_ids().into_iter().collect::<Vec<_>>(),
+        ["minecraft:chest"]
+    );
+
+    schem
+        .block_entities
+        .insert((0, 0, 0), BlockEntity::sign(["", "", "", ""], 3700));
+    schem
+        .block_entities
+        .insert((0, 1, 0), BlockEntity::sign(["", "", "", ""], 3700));
+    let ids = schem.block_entity_ids();
+    assert_eq!(ids.len(), 2);
+    assert!(ids.contains("minecraft:sign"));
+}
+
+#[test]
+fn dangerous_block_entities() {
+    let mut schem = Schematic::deserialize(include_bytes!("sponge_v3_chest.schem")).unwrap();
+    assert!(!schem.contains_dangerous());
+    assert!(schem.contains_any_block_entity(&["chest"]));
+
+    schem.block_entities.insert(
+        (0, 0, 0),
+        BlockEntity {
+            id: "minecraft:command_block".to_owned(),
+            data: HashMap::new(),
+        },
+    );
+    assert!(schem.contains_dangerous());
+    assert!(!schem.contains_any_block_entity(&["minecraft:mob_spawner"]));
+}
+
+#[test]
+fn sponge_v3_metadata_round_trip() {
+    let mut schem = Schematic::deserialize(include_bytes!("sponge_v3.schem")).unwrap();
+    assert_eq!(schem.editing_platform(), Some("enginehub:fabric"));
+    assert_eq!(schem.worldedit_version(), Some("7.3.0"));
+    schem.metadata_set_str("Generator", "my-tool 1.0");
+
+    let bytes = schem.serialize(SchematicFormat::Sponge(3)).unwrap();
+    let read = Schematic::deserialize(&bytes).unwrap();
+    assert_eq!(read.metadata, schem.metadata);
+    assert_eq!(read.metadata_get_str("Generator"), Some("my-tool 1.0"));
+    assert_eq!(read.editing_platform(), Some("enginehub:fabric"));
+}
+
+#[test]
+fn metadata_helpers() {
+    let mut schem = Schematic::new(Blocks::new(1, 1, 1, "minecraft:stone")).with_data_version(3700);
+    assert_eq!(schem.metadata_get_str("Author"), None);
+    assert_eq!(schem.metadata_remove("Author"), None);
+    assert!(schem.metadata.is_none());
+
+    // The metadata is created on the first set
+    schem.metadata_set_str("Author", "someone");
+    schem.metadata_set_i32("Date", 42);
+    schem.metadata_set_i32("Removed", 7);
+    assert_eq!(schem.metadata_remove("Removed"), Some(Value::Int(7)));
+    // Values of the wrong type aren't returned
+    assert_eq!(schem.metadata_get_i32("Author"), None);
+    assert_eq!(schem.metadata_get_str("Date"), None);
+
+    let bytes = schem.serialize(SchematicFormat::Sponge(3)).unwrap();
+    let nbt = nbt::Blob::from_gzip_reader(&mut bytes.as_slice()).unwrap();
+    let Some(Value::Compound(inner)) = nbt.get("Schematic") else {
+        panic!("missing Schematic compound");
+    };
+    let Some(Value::Compound(metadata)) = inner.get("Metadata") else {
+        panic!("missing Metadata");
+    };
+    assert_eq!(
+        metadata.get("Author"),
+        Some(&Value::String("someone".to_owned()))
+    );
+    assert!(!metadata.contains_key("Removed"));
+
+    let mut read = Schematic::deserialize(&bytes).unwrap();
+    assert_eq!(read.metadata_get_str("Author"), Some("someone"));
+    assert_eq!(read.metadata_get_i32("Date"), Some(42));
+    assert_eq!(read.metadata_get_i32("Removed"), None);
+    assert_eq!(
+        read.metadata_remove("Author"),
+        Some(Value::String("someone".to_owned()))
+    );
+    assert_eq!(read.metadata_get_str("Author"), None);
+}
+
+#[test]
+fn set_and_remove_block_entity() {
+    let mut schem = Schematic::new(Blocks::new(2, 1, 2, "minecraft:air")).with_data_version(3700);
+    let chest = |name: &str| {
+        let mut data = HashMap::new();
+        data.insert("CustomName".to_owned(), Value::String(name.to_owned()));
+        BlockEntity {
+            id: "minecraft:chest".to_owned(),
+            data,
+        }
+    };
+
+    assert!(schem
+        .set_block_entity((1, 0, 1), chest("first"))
+        .unwrap()
+        .is_none());
+    // Replacing returns the previous block entity
+    let previous = schem
+        .set_block_entity((1, 0, 1), chest("second"))
+        .unwrap()
+        .unwrap();
+    assert_eq!(previous.get_string("CustomName"), Some("first"));
+    assert_eq!(
+        schem.block_entities[&(1, 0, 1)].get_string("CustomName"),
+        Some("second")
+    );
+
+    // Positions outside of the schematic are rejected on every axis
+    for pos in [(2, 0, 0), (0, 1, 0), (0, 0, 2), (u32::MAX, 0, 0)] {
+        assert!(matches!(
+            schem.set_block_entity(pos, chest("outside")),
+            Err(SchematicError::OutOfBounds(_))
+        ));
+    }
+    assert_eq!(schem.block_entities.len(), 1);
+
+    let removed = schem.remove_block_entity((1, 0, 1)).unwrap();
+    assert_eq!(removed.get_string("CustomName"), Some("second"));
+    assert!(schem.remove_block_entity((1, 0, 1)).is_none());
+    assert!(schem.remove_block_entity((5, 5, 5)).is_none());
+    assert!(schem.block_entities.is_empty());
+}
+
+#[test]
+fn debug_summary() {
+    let mut blocks = Blocks::new(64, 64, 64, "minecraft:air");
+    blocks.set_block_at(1, 2, 3, "minecraft:stone");
+    let mut schem = Schematic::new(blocks).with_data_version(3700);
+    schem.paste_offset = Some((1, -2, 3));
+    schem.metadata_set_str("Author", "someone");
+    // The blocks themselves aren't printed
+    assert_eq!(
+        format!("{schem:?}"),
+        "Schematic { size: (64, 64, 64), data_version: Some(3700), palette_len: 2, \
+         block_entities: 0, entities: 0, origin: None, paste_offset: Some((1, -2, 3)), \
+         has_biomes: false, has_metadata: true, regions: 0 }"
+    );
+}
+
+#[test]
+fn item_version_boundary() {
+    let mut tag = HashMap::new();
+    tag.insert("Damage".to_owned(), Value::Int(5));
+    let sword = |count| ItemStack {
+        id: "minecraft:iron_sword".to_owned(),
+        count,
+        slot: Some(4),
+        data: Some(tag.clone()),
+    };
+
+    // The last data version before 1.20.5 uses Count bytes and tag compounds
+    let old = ITEM_COMPONENTS_DATA_VERSION - 1;
+    let chest = BlockEntity::chest(vec![sword(2)], old).unwrap();
+    let Some(Value::List(list)) = chest.data.get("Items") else {
+        panic!("missing Items");
+    };
+    let Value::Compound(item) = &list[0] else {
+        panic!("mistyped item");
+    };
+    assert_eq!(item.get("Count"), Some(&Value::Byte(2)));
+    assert!(item.contains_key("tag") && !item.contains_key("components"));
+    let items = chest.items(Some(old)).unwrap();
+    assert_eq!((items[0].count, items[0].slot), (2, Some(4)));
+    assert_eq!(items[0].data.as_ref(), Some(&tag));
+    assert!(matches!(
+        BlockEntity::chest(vec![sword(200)], old),
+        Err(SchematicError::InvalidValue(_))
+    ));
+
+    // Starting at the boundary, counts are ints and the data is in components
+    let new = ITEM_COMPONENTS_DATA_VERSION;
+    let chest = BlockEntity::chest(vec![sword(200)], new).unwrap();
+    let Some(Value::List(list)) = chest.data.get("Items") else {
+        panic!("missing Items");
+    };
+    let Value::Compound(item) = &list[0] else {
+        panic!("mistyped item");
+    };
+    assert_eq!(item.get("count"), Some(&Value::Int(200)));
+    assert!(item.contains_key("components") && !item.contains_key("tag"));
+    let items = chest.items(Some(new)).unwrap();
+    assert_eq!(items[0].count, 200);
+    assert_eq!(items[0].data.as_ref(), Some(&tag));
+    // Reading with the version on the wrong side of the boundary doesn't find the count
+    assert!(matches!(
+        chest.items(Some(old)),
+        Err(SchematicError::MistypedField(field)) if field == "count"
+    ));
+
+    // Without a data version, both layouts are accepted
+    for version in [old, new] {
+        let chest = BlockEntity::chest(vec![sword(3)], version).unwrap();
+        let items = chest.items(None).unwrap();
+        assert_eq!(items[0].count, 3);
+        assert_eq!(items[0].data.as_ref(), Some(&tag));
+    }
+}
+
+#[test]
+fn three_byte_varint_round_trip() {
+    // Palette indices of 16384 and above take up three bytes
+    let mut schem = Schematic::deserialize(include_bytes!("sponge_v2.schem")).unwrap();
+    schem.blocks = Blocks::from_fn(200, 1, 100, |x, _, z| {
+        format!("minecraft:test[id={}]", x * 100 + z)
+    });
+    schem.block_entities.clear();
+    assert!(schem.blocks.palette_size_live() > 16384);
+
+    let bytes = schem.serialize(SchematicFormat::Sponge(3)).unwrap();
+    let read = Schematic::deserialize(&bytes).unwrap();
+    assert_eq!(read.size(), (200, 1, 100));
+    for ((x, y, z), block) in schem.blocks.cells() {
+        assert_eq!(read.blocks.get_block_at(x, y, z), block);
+    }
+}
+
+#[test]
+fn preserve_palette() {
+    let bytes = modified_v2(|nbt| {
+        let Some(Value::Compound(palette)) = nbt.get_mut("Palette") else {
+            panic!("missing Palette");
+        };
+        palette.insert("minecraft:gold_block".to_owned(), Value::Int(6));
+    });
+
+    let schem = Schematic::deserialize_preserve_palette(&bytes).unwrap();
+    let palette: Vec<&str> = schem.blocks.blocks_in_palette().collect();
+    assert_eq!(
+        palette,
+        [
+            "minecraft:polished_diorite",
+            "minecraft:target[power=0]",
+            "minecraft:stone_brick_slab[type=bottom,waterlogged=false]",
+            "minecraft:honey_block",
+            "minecraft:redstone_wire[east=side,north=side,power=0,south=side,west=side]",
+            "minecraft:air",
+            "minecraft:gold_block",
+        ]
+    );
+
+    // Air is the background block by default
+    let schem = Schematic::deserialize(&bytes).unwrap();
+    assert_eq!(
+        schem.blocks.blocks_in_palette().next(),
+        Some("minecraft:air")
+    );
+}
+
+#[test]
+fn convert_file() {
+    let dir = std::env::temp_dir();
+    let dst = dir.join(format!("mc_schems_convert_{}.schem", std::process::id()));
+    Schematic::convert_file("tests/sponge_v2.schem", &dst, SchematicFormat::Sponge(3)).unwrap();
+
+    let read = Schematic::deserialize(&std::fs::read(&dst).unwrap()).unwrap();
+    std::fs::remove_file(&dst).unwrap();
+    assert_eq!(read.source_format(), Some(SchematicFormat::Sponge(3)));
+    let original = Schematic::deserialize(include_bytes!("sponge_v2.schem")).unwrap();
+    assert_eq!(read.content_hash(), original.content_hash());
+
+    Schematic::convert_file(
+        "tests/sponge_v2.schem",
+        &dst,
+        SchematicFormat::Litematica(6),
+    )
+    .unwrap();
+    let read = Schematic::deserialize(&std::fs::read(&dst).unwrap()).unwrap();
+    std::fs::remove_file(&dst).unwrap();
+    assert_eq!(read.source_format(), Some(SchematicFormat::Litematica(6)));
+    assert_eq!(read.content_hash(), original.content_hash());
+
+    assert!(matches!(
+        Schematic::convert_file("tests/missing.schem", &dst, SchematicFormat::Sponge(3)),
+        Err(SchematicError::Io(_))
+    ));
+
+    // Legacy blocks are upgraded when converting
+    let legacy = |id: &str| {
+        modified_v2(|nbt| {
+            let Some(Value::Compound(palette)) = nbt.get_mut("Palette") else {
+                panic!("missing Palette");
+            };
+            let entry = palette
+                .remove(original.blocks.get_block_at(0, 0, 0))
+                .unwrap();
+            palette.insert(id.to_owned(), entry);
+        })
+    };
+    let src = dir.join(format!("mc_schems_legacy_{}.schem", std::process::id()));
+    std::fs::write(&src, legacy("35:14")).unwrap();
+    Schematic::convert_file(&src, &dst, SchematicFormat::Sponge(3)).unwrap();
+    let read = Schematic::deserialize(&std::fs::read(&dst).unwrap()).unwrap();
+    std::fs::remove_file(&dst).unwrap();
+    assert_eq!(read.blocks.get_block_at(0, 0, 0), "minecraft:red_wool");
+
+    // Blocks without a modern equivalent fail the conversion before anything is written
+    std::fs::write(&src, legacy("26:3")).unwrap();
+    let result = Schematic::convert_file(&src, &dst, SchematicFormat::Sponge(3));
+    std::fs::remove_file(&src).unwrap();
+    assert!(matches!(
+        result,
+        Err(SchematicError::LegacyBlock(block)) if block == "26:3"
+    ));
+    assert!(!dst.exists());
+}
+
+#[test]
+fn list_palette() {
+    let schem = Schematic::deserialize(include_bytes!("sponge_v2.schem")).unwrap();
+    let bytes = modified_v2(|nbt| {
+        let Some(Value::Compound(palette)) = nbt.get("Palette") else {
+            panic!("missing Palette");
+        };
+        let mut entries: Vec<(i32, &String)> = palette
+            .iter()
+            .map(|(name, id)| match id {
+                Value::Int(id) => (*id, name),
+                _ => panic!("mistyped palette id"),
+            })
+            .collect();
+        entries.sort();
+        let list = entries
+            .into_iter()
+            .map(|(_, block)| {
+                let mut entry = HashMap::new();
+                let (name, properties) = block.split_once('[').unwrap_or((block, ""));
+                entry.insert("Name".to_owned(), Value::String(name.to_owned()));
+                let properties: HashMap<String, Value> = properties
+                    .trim_end_matches(']')
+                    .split(',')
+                    .filter_map(|property| property.split_once('='))
+                    .map(|(key, value)| (key.to_owned(), Value::String(value.to_owned())))
+                    .collect();
+                if !properties.is_empty() {
+                    entry.insert("Properties".to_owned(), Value::Compound(properties));
+                }
+                Value::Compound(entry)
+            })
+            .collect();
+        nbt.insert("Palette".to_owned(), Value::List(list));
+    });
+
+    let read = Schematic::deserialize(&bytes).unwrap();
+    assert_eq!(read.content_hash(), schem.content_hash());
+}
+
+#[test]
+fn block_state_from_properties() {
+    let mut properties = HashMap::new();
+    properties.insert("facing".to_owned(), Value::String("north".to_owned()));
+    properties.insert("delay".to_owned(), Value::String("2".to_owned()));
+    assert_eq!(
+        block_state_from_nbt("minecraft:repeater", &properties).unwrap(),
+        "minecraft:repeater[delay=2,facing=north]"
+    );
+    assert_eq!(
+        block_state_from_nbt("minecraft:stone", &HashMap::new()).unwrap(),
+        "minecraft:stone"
+    );
+
+    properties.insert("powered".to_owned(), Value::Byte(1));
+    assert!(matches!(
+        block_state_from_nbt("minecraft:repeater", &properties),
+        Err(SchematicError::MistypedField(field)) if field == "powered"
+    ));
+}
+
+#[test]
+fn sponge_v2_zlib() {
+    // Some WorldEdit forks write zlib instead of gzip compressed files
+    let bytes = include_bytes!("sponge_v2_zlib.schem");
+    let schem = Schematic::deserialize(bytes).unwrap();
+    let gzip = Schematic::deserialize(include_bytes!("sponge_v2.schem")).unwrap();
+
+    assert_eq!(schem.size(), (2, 2, 2));
+    let mut palette: Vec<&str> = schem.blocks.blocks_in_palette().collect();
+    let mut expected: Vec<&str> = gzip.blocks.blocks_in_palette().collect();
+    palette.sort();
+    expected.sort();
+    assert_eq!(palette, expected);
+    assert_eq!(schem.content_hash(), gzip.content_hash());
+}
+
+#[test]
+fn map_blocks() {
+    let mut schem = Schematic::deserialize(include_bytes!("sponge_v2.schem")).unwrap();
+    let before: Vec<String> = schem.blocks.cells().map(|(_, b)| b.to_owned()).collect();
+
+    let mut calls = Vec::new();
+    schem.map_blocks(|block| {
+        calls.push(block.to_owned());
+        if block == "minecraft:air" {
+            "minecraft:glass".to_owned()
+        } else {
+            block.to_owned()
+        }
+    });
+
+    let distinct: std::collections::HashSet<&String> = before.iter().collect();
+    assert_eq!(calls.len(), distinct.len());
+    assert_eq!(schem.blocks.background_block(), "minecraft:glass");
+    for ((_, block), old) in schem.blocks.cells().zip(&before) {
+        if old == "minecraft:air" {
+            assert_eq!(block, "minecraft:glass");
+        } else {
+            assert_eq!(block, old);
+        }
+    }
+
+    // Counting non-air blocks carries over to the new container
+    assert_eq!(schem.blocks.non_air_count(), None);
+    schem.blocks.track_non_air();
+    schem.map_blocks(|block| {
+        if block == "minecraft:glass" {
+            "minecraft:air".to_owned()
+        } else {
+            block.to_owned()
+        }
+    });
+    let non_air = before.iter().filter(|b| *b != "minecraft:air").count();
+    assert_eq!(schem.blocks.non_air_count(), Some(non_air as u64));
+}
+
+#[test]
+fn to_json() {
+    let mut schem = Schematic::deserialize(include_bytes!("sponge_v2.schem")).unwrap();
+    schem.blocks = Blocks::new(2, 1, 2, "minecraft:air");
+    schem.blocks.set_block_at(1, 0, 0, "minecraft:stone");
+    schem.blocks.set_block_at(0, 0, 1, "odd\"block");
+
+    assert_eq!(
+        schem.to_json(),
+        r#"{"size":[2,1,2],"palette":["minecraft:air","minecraft:stone","odd\"block"],"blocks":[0,2,1,0]}"#
+    );
+}
+
+#[test]
+fn unsigned_dimensions() {
+    let mut blocks = Blocks::new(40000, 1, 1, "minecraft:air");
+    blocks.set_block_at(39999, 0, 0, "minecraft:stone");
+    let schem = Schematic::new(blocks).with_data_version(3700);
+    for format in [SchematicFormat::Sponge(2), SchematicFormat::Sponge(3)] {
+        let read = Schematic::deserialize(&schem.serialize(format).unwrap()).unwrap();
+        assert_eq!(read.size(), (40000, 1, 1));
+        assert_eq!(read.blocks.get_block_at(39999, 0, 0), "minecraft:stone");
+    }
+
+    // Dimensions beyond the range of unsigned shorts can't be written
+    let schem = Schematic::new(Blocks::new(1, 1, 65536, "minecraft:air")).with_data_version(3700);
+    assert!(matches!(
+        schem.serialize(SchematicFormat::Sponge(2)),
+        Err(SchematicError::InvalidValue(field)) if field == "Length"
+    ));
+}
+
+#[test]
+fn estimate_serialized_size() {
+    let mut blocks = Blocks::from_fn(20, 10, 20, |x, y, z| {
+        if (x + y + z) % 3 == 0 {
+            format!("minecraft:stone_{}", (x * 13 + z) % 300)
+        } else {
+            "minecraft:air".to_owned()
+        }
+    });
+    blocks.set_block_at(0, 0, 0, "minecraft:chest[facing=north]");
+    let mut large = Schematic::new(blocks).with_data_version(3700);
+    large.paste_offset = Some((1, 2, 3));
+    large.origin = Some((-10, 64, 10));
+    for i in 0..10 {
+        let mut data = HashMap::new();
+        data.insert("CustomName".to_owned(), Value::String(format!("chest {i}")));
+        large.block_entities.insert(
+            (i, 0, 0),
+            BlockEntity {
+                id: "minecraft:chest".to_owned(),
+                data: data.clone(),
+            },
+        );
+        large.entities.push(Entity {
+            id: "minecraft:armor_stand".to_owned(),
+            pos: (i as f64, 1.0, 1.0),
+            data,
+        });
+    }
+
+    let small = Schematic::deserialize(include_bytes!("sponge_v2.schem")).unwrap();
+    let check = |schem: &Schematic, format| {
+        let estimate = schem.estimate_serialized_size(format).unwrap() as f64;
+        let actual = schem.to_nbt(format).unwrap().len_bytes() as f64;
+        assert!(
+            (estimate - actual).abs() <= actual * 0.2,
+            "estimated {estimate} bytes for {format}, got {actual}"
+        );
+    };
+    for schem in [&small, &large] {
+        for format in [
+            SchematicFormat::Sponge(2),
+            SchematicFormat::Sponge(3),
+            SchematicFormat::Litematica(6),
+        ] {
+            check(schem, format);
+        }
+    }
+
+    // Alpha can only store legacy blocks, including ids that don't fit into a byte
+    let mut legacy = large.clone();
+    legacy.blocks = Blocks::from_fn(20, 10, 20, |x, y, z| {
+        format!("{}:{}", (x * 13 + z) % 300, y % 16)
+    });
+    check(
+        &legacy,
+        SchematicFormat::Schematica(SchematicaFormat::Alpha),
+    );
+    assert!(matches!(
+        Schematic::new(Blocks::new(1, 1, 1, "minecraft:air"))
+            .estimate_serialized_size(SchematicFormat::Sponge(1)),
+        Err(SchematicError::UnsupportedFormat(_))
+    ));
+}
+
+#[test]
+fn transpose_xz() {
+    let mut schem = Schematic::deserialize(include_bytes!("sponge_v2.schem")).unwrap();
+    schem.blocks = Blocks::new(3, 2, 1, "minecraft:air");
+    schem
+        .blocks
+        .set_block_at(2, 1, 0, "minecraft:repeater[facing=east]");
+    schem.block_entities.clear();
+    schem.block_entities.insert(
+        (2, 1, 0),
+        BlockEntity {
+            id: "minecraft:comparator".to_owned(),
+            data: HashMap::new(),
+        },
+    );
+    schem.regions = vec![Region {
+        name: "Unnamed".to_owned(),
+        offset: (1, 0, 0),
+        size: (2, 2, 1),
+    }];
+    schem.transpose_xz();
+
+    assert_eq!(schem.size(), (1, 2, 3));
+    // Moved but not rotated
+    assert_eq!(
+        schem.blocks.get_block_at(0, 1, 2),
+        "minecraft:repeater[facing=east]"
+    );
+    assert_eq!(schem.blocks.get_block_at(0, 1, 0), "minecraft:air");
+    assert!(schem.block_entities.contains_key(&(0, 1, 2)));
+    assert_eq!(schem.regions[0].offset, (0, 0, 1));
+    assert_eq!(schem.regions[0].size, (1, 2, 2));
+}
+
+#[test]
+fn sponge_v2_lowercase() {
+    let bytes = include_bytes!("sponge_v2_lowercase.schem");
+    // Strict by default
+    assert!(matches!(
+        Schematic::deserialize(bytes),
+        Err(SchematicError::MissingRequiredField(_))
+    ));
+
+    let schem = Schematic::deserialize_lenient(bytes).unwrap();
+    assert_eq!(schem.size(), (2, 2, 2));
+    assert_eq!(
+        schem.content_hash(),
+        Schematic::deserialize(include_bytes!("sponge_v2.schem"))
+            .unwrap()
+            .content_hash()
+    );
+}
+
+#[test]
+fn block_entities_sorted() {
+    let mut schem = Schematic::deserialize(include_bytes!("sponge_v2.schem")).unwrap();
+    schem.block_entities.clear();
+    for pos in [(1, 0, 0), (0, 1, 1), (0, 1, 0), (1, 1, 1)] {
+        schem.block_entities.insert(
+            pos,
+            BlockEntity {
+                id: "minecraft:chest".to_owned(),
+                data: HashMap::new(),
+            },
+        );
+    }
+
+    let positions: Vec<(u32, u32, u32)> = schem
+        .block_entities_sorted()
+        .into_iter()
+        .map(|(pos, _)| *pos)
+        .collect();
+    assert_eq!(positions, [(0, 1, 0), (0, 1, 1), (1, 0, 0), (1, 1, 1)]);
+}
+
+#[test]
+fn structure_void() {
+    let mut schem = Schematic::deserialize(include_bytes!("sponge_v2.schem")).unwrap();
+    schem.blocks = Blocks::new(2, 1, 1, "minecraft:air");
+    schem.blocks.set_block_at(1, 0, 0, "minecraft:stone");
+
+    schem.air_to_structure_void();
+    assert_eq!(
+        schem.blocks.get_block_at(0, 0, 0),
+        "minecraft:structure_void"
+    );
+    assert_eq!(schem.blocks.get_block_at(1, 0, 0), "minecraft:stone");
+    assert!(schem
+        .blocks
+        .blocks_in_palette()
+        .all(|b| b != "minecraft:air"));
+
+    schem.structure_void_to_air();
+    assert_eq!(schem.blocks.get_block_at(0, 0, 0), "minecraft:air");
+    assert_eq!(schem.blocks.get_block_at(1, 0, 0), "minecraft:stone");
+    assert!(schem
+        .blocks
+        .blocks_in_palette()
+        .all(|b| b != "minecraft:structure_void"));
+}
+
+#[test]
+fn block_entity_templates() {
+    let mut schem = Schematic::deserialize(include_bytes!("sponge_v3.schem")).unwrap();
+    let data_version = schem.data_version.unwrap();
+    let items = vec![
+        ItemStack {
+            id: "minecraft:diamond".to_owned(),
+            count: 3,
+            slot: Some(0),
+            data: None,
+        },
+        ItemStack {
+            id: "minecraft:stick".to_owned(),
+            count: 1,
+            slot: None,
+            data: None,
+        },
+    ];
+    let chest = BlockEntity::chest(items, data_version).unwrap();
+    schem.set_block_entity((0, 0, 0), chest).unwrap();
+    let sign = BlockEntity::sign(["Hello", "\"quoted\"", "", ""], data_version);
+    schem.set_block_entity((1, 0, 0), sign).unwrap();
+
+    let bytes = schem.serialize(SchematicFormat::Sponge(3)).unwrap();
+    let read = Schematic::deserialize(&bytes).unwrap();
+    let items = read.block_entities[&(0, 0, 0)]
+        .items(read.data_version)
+        .unwrap();
+    assert_eq!(items.len(), 2);
+    let stick = items.iter().find(|i| i.id == "minecraft:stick").unwrap();
+    assert_eq!((stick.count, stick.slot), (1, Some(1)));
+
+    let sign = &read.block_entities[&(1, 0, 0)];
+    let Some(Value::Compound(front)) = sign.data.get("front_text") else {
+        panic!("missing front_text");
+    };
+    let Some(Value::List(messages)) = front.get("messages") else {
+        panic!("missing messages");
+    };
+    assert_eq!(
+        messages[1],
+        Value::String(r#"{"text":"\"quoted\""}"#.to_owned())
+    );
+
+    // Old signs store each line in its own tag
+    let old = BlockEntity::sign(["a", "b", "c", "d"], 2975);
+    assert_eq!(
+        old.data.get("Text4"),
+        Some(&Value::String(r#"{"text":"d"}"#.to_owned()))
+    );
+    // Old item stacks store the count as a byte
+    let too_many = ItemStack {
+        id: "minecraft:stone".to_owned(),
+        count: 200,
+        slot: None,
+        data: None,
+    };
+    assert!(BlockEntity::chest(vec![too_many], 2975).is_err());
+}
+
+#[test]
+fn offset_mapping() {
+    // Version 2 stores the origin in Offset and the paste offset in the metadata
+    let v2 = Schematic::deserialize(include_bytes!("sponge_v2.schem")).unwrap();
+    assert_eq!(v2.origin, Some((1, 0, 2)));
+    assert_eq!(v2.paste_offset, Some((1, 0, 1)));
+    assert!(v2.metadata_get_i32("WEOffsetX").is_none());
+
+    let bytes = v2.serialize(SchematicFormat::Sponge(2)).unwrap();
+    let nbt = nbt::Blob::from_gzip_reader(&mut bytes.as_slice()).unwrap();
+    assert_eq!(nbt.get("Offset"), Some(&Value::IntArray(vec![1, 0, 2])));
+    let Some(Value::Compound(metadata)) = nbt.get("Metadata") else {
+        panic!("missing Metadata");
+    };
+    assert_eq!(metadata.get("WEOffsetZ"), Some(&Value::Int(1)));
+
+    // Version 3 stores the paste offset in Offset and the origin relative to it in the metadata
+    let v3 = Schematic::deserialize(include_bytes!("sponge_v3.schem")).unwrap();
+    assert_eq!(v3.origin, Some((1, 0, 2)));
+    assert_eq!(v3.paste_offset, Some((1, 0, 1)));
+
+    let bytes = v3.serialize(SchematicFormat::Sponge(3)).unwrap();
+    let nbt = nbt::Blob::from_gzip_reader(&mut bytes.as_slice()).unwrap();
+    let Some(Value::Compound(schem)) = nbt.get("Schematic") else {
+        panic!("missing Schematic");
+    };
+    assert_eq!(schem.get("Offset"), Some(&Value::IntArray(vec![1, 0, 1])));
+}
+
+#[test]
+fn convert_v3_to_v2() {
+    let v3 = Schematic::deserialize(include_bytes!("sponge_v3.schem")).unwrap();
+    let bytes = v3.serialize(SchematicFormat::Sponge(2)).unwrap();
+
+    // WorldEdit pastes the min point at the player position plus WEOffset, and Offset is the
+    // position it was copied from
+    let nbt = nbt::Blob::from_gzip_reader(&mut bytes.as_slice()).unwrap();
+    assert_eq!(nbt.get("Offset"), Some(&Value::IntArray(vec![1, 0, 2])));
+    let Some(Value::Compound(metadata)) = nbt.get("Metadata") else {
+        panic!("missing Metadata");
+    };
+    assert_eq!(metadata.get("WEOffsetX"), Some(&Value::Int(1)));
+    assert_eq!(metadata.get("WEOffsetY"), Some(&Value::Int(0)));
+    assert_eq!(metadata.get("WEOffsetZ"), Some(&Value::Int(1)));
+
+    // And back again
+    let v2 = Schematic::deserialize(&bytes).unwrap();
+    let bytes = v2.serialize(SchematicFormat::Sponge(3)).unwrap();
+    let nbt = nbt::Blob::from_gzip_reader(&mut bytes.as_slice()).unwrap();
+    let Some(Value::Compound(schem)) = nbt.get("Schematic") else {
+        panic!("missing Schematic");
+    };
+    assert_eq!(schem.get("Offset"), Some(&Value::IntArray(vec![1, 0, 1])));
+    let Some(Value::Compound(metadata)) = schem.get("Metadata") else {
+        panic!("missing Metadata");
+    };
+    let Some(Value::Compound(worldedit)) = metadata.get("WorldEdit") else {
+        panic!("missing WorldEdit");
+    };
+    assert_eq!(
+        worldedit.get("Origin"),
+        Some(&Value::IntArray(vec![0, 0, 1]))
+    );
+}
+
+#[test]
+fn float_entity_pos() {
+    let bytes = include_bytes!("sponge_v3_float_entity_pos.schem");
+    let schem = Schematic::deserialize(bytes).unwrap();
+
+    assert_eq!(schem.entities.len(), 2);
+    let armor_stand = &schem.entities[0];
+    assert_eq!(armor_stand.id, "minecraft:armor_stand");
+    assert_eq!(armor_stand.pos, (1.5, 0.25, 1.5));
+    assert_eq!(armor_stand.data.get("Invisible"), Some(&Value::Byte(1)));
+    let marker = &schem.entities[1];
+    assert_eq!(marker.id, "minecraft:marker");
+    assert_eq!(marker.pos, (0.0, 1.0, 2.0));
+}
+
+#[test]
+fn is_empty() {
+    let bytes = include_bytes!("sponge_v3.schem");
+    let mut schem = Schematic::deserialize(bytes).unwrap();
+    assert!(!schem.is_empty());
+    let (x, y, z) = schem.blocks.size();
+    schem.blocks = Blocks::new(x, y, z, "minecraft:air");
+    assert!(schem.is_empty());
+
+    let bytes = include_bytes!("sponge_v3_float_entity_pos.schem");
+    let mut schem = Schematic::deserialize(bytes).unwrap();
+    schem.blocks = Blocks::new(x, y, z, "minecraft:air");
+    assert!(!schem.is_empty());
+    schem.entities.clear();
+    assert!(schem.is_empty());
+}
+
+#[test]
+fn bom_prefix() {
+    let bytes = include_bytes!("sponge_v2_bom.schem");
+    let schem = Schematic::deserialize(bytes).unwrap();
+    assert_eq!(schem.blocks.size(), (2, 2, 2));
+    assert_eq!(schem.source_format(), Some(SchematicFormat::Sponge(2)));
+
+    // Anything other than a short prefix in front of the gzip header is still rejected
+    let mut junk = vec![b' '; 64];
+    junk.extend_from_slice(include_bytes!("sponge_v2.schem"));
+    assert!(Schematic::deserialize(&junk).is_err());
+}
+
+#[test]
+fn block_entity_getters() {
+    let mut spawn_data = HashMap::new();
+    spawn_data.insert(
+        "id".to_owned(),
+        Value::String("minecraft:zombie".to_owned()),
+    );
+    let mut data = HashMap::new();
+    data.insert("Command".to_owned(), Value::String("say hi".to_owned()));
+    data.insert("SuccessCount".to_owned(), Value::Int(3));
+    data.insert("auto".to_owned(), Value::Byte(1));
+    data.insert("LootTableSeed".to_owned(), Value::Long(-42));
+    data.insert("SpawnData".to_owned(), Value::Compound(spawn_data));
+    data.insert("Items".to_owned(), Value::List(Vec::new()));
+    let block_entity = BlockEntity {
+        id: "minecraft:command_block".to_owned(),
+        data,
+    };
+
+    assert_eq!(block_entity.get_string("Command"), Some("say hi"));
+    assert_eq!(block_entity.get_i32("SuccessCount"), Some(3));
+    assert_eq!(block_entity.get_i32("auto"), Some(1));
+    assert_eq!(block_entity.get_i32("LootTableSeed"), None);
+    assert_eq!(block_entity.get_i64("LootTableSeed"), Some(-42));
+    assert_eq!(block_entity.get_i64("SuccessCount"), Some(3));
+    assert_eq!(
+        block_entity
+            .get_compound("SpawnData")
+            .and_then(|spawn_data| spawn_data.get("id")),
+        Some(&Value::String("minecraft:zombie".to_owned()))
+    );
+    assert_eq!(block_entity.get_list("Items"), Some(&[][..]));
+    assert_eq!(block_entity.get_string("SuccessCount"), None);
+    assert_eq!(block_entity.get_compound("Missing"), None);
+}
+
+#[test]
+fn with_data_version() {
+    let mut blocks = Blocks::new(2, 1, 1, "minecraft:air");
+    blocks.set_block_at(1, 0, 0, "minecraft:stone");
+    let schem = Schematic::new(blocks);
+    assert!(matches!(
+        schem.serialize(SchematicFormat::Sponge(3)),
+        Err(SchematicError::MissingRequiredField(_))
+    ));
+
+    let schem = schem.with_data_version(3465);
+    let bytes = schem.serialize(SchematicFormat::Sponge(3)).unwrap();
+    let read = Schematic::deserialize(&bytes).unwrap();
+    assert_eq!(read.data_version, Some(3465));
+    assert_eq!(read.blocks.get_block_at(1, 0, 0), "minecraft:stone");
+}
+
+#[test]
+fn metadata_offset_fallbacks() {
+    for bytes in [
+        &include_bytes!("sponge_v2_offset_case.schem")[..],
+        &include_bytes!("sponge_v2_metadata_offset.schem")[..],
+    ] {
+        let schem = Schematic::deserialize(bytes).unwrap();
+        assert_eq!(schem.paste_offset, Some((1, 0, 1)));
+        assert_eq!(schem.origin, Some((1, 0, 2)));
+        assert!(schem.metadata.is_none());
+    }
+}
+
+#[test]
+fn block_entities_in() {
+    let mut schem = Schematic::new(Blocks::new(4, 4, 4, "minecraft:air"));
+    for pos in [(0, 0, 0), (1, 2, 1), (2, 2, 2), (3, 3, 3)] {
+        schem.block_entities.insert(
+            pos,
+            BlockEntity {
+                id: "minecraft:chest".to_owned(),
+                data: HashMap::new(),
+            },
+        );
+    }
+
+    let mut found: Vec<_> = schem
+        .block_entities_in((1, 1, 1), (2, 2, 2))
+        .map(|(pos, _)| *pos)
+        .collect();
+    found.sort();
+    assert_eq!(found, [(1, 2, 1), (2, 2, 2)]);
+    assert_eq!(schem.block_entities_in((3, 3, 3), (3, 3, 3)).count(), 1);
+    assert_eq!(schem.block_entities_in((2, 0, 0), (1, 3, 3)).count(), 0);
+}
+
+#[test]
+fn air_aliases() {
+    let bytes = include_bytes!("sponge_v2_air_alias.schem");
+    let schem = Schematic::deserialize(bytes).unwrap();
+    assert_eq!(schem.blocks.background_block(), "minecraft:air");
+    assert_eq!(schem.blocks.palette_size_live(), 6);
+    assert_eq!(schem.blocks.positions_of("minecraft:air").len(), 3);
+
+    let blocks = Schematic::deserialize_preserve_palette(bytes)
+        .unwrap()
+        .blocks;
+    assert_eq!(blocks.positions_of("air").len(), 2);
+    assert_eq!(blocks.positions_of("").len(), 1);
+
+    // The aliases can be replaced or cleared
+    let blocks = Schematic::deserialize_with_air_aliases(bytes, &["air"])
+        .unwrap()
+        .blocks;
+    assert_eq!(blocks.positions_of("minecraft:air").len(), 2);
+    assert_eq!(blocks.positions_of("").len(), 1);
+    let blocks = Schematic::deserialize_with_air_aliases(bytes, &[])
+        .unwrap()
+        .blocks;
+    assert_eq!(blocks.positions_of("minecraft:air").len(), 0);
+    assert_eq!(blocks.positions_of("air").len(), 2);
+}
+
+#[test]
+fn to_nbt() {
+    let bytes = include_bytes!("sponge_v3.schem");
+    let schem = Schematic::deserialize(bytes).unwrap();
+    let mut nbt = schem.to_nbt(SchematicFormat::Sponge(3)).unwrap();
+    let Some(Value::Compound(inner)) = nbt.get("Schematic") else {
+        panic!("missing Schematic compound");
+    };
+    assert_eq!(inner.get("Version"), Some(&Value::Int(3)));
+    assert_eq!(inner.get("Width"), Some(&Value::Short(2)));
+
+    // Custom tags survive compression and reading
+    nbt.insert("Custom", Value::Int(7)).unwrap();
+    let mut data = Vec::new();
+    nbt.to_gzip_writer(&mut data).unwrap();
+    let read = Schematic::deserialize(&data).unwrap();
+    assert_eq!(read.blocks.size(), (2, 2, 2));
+}
+
+#[test]
+fn sponge_v3_extra_root_tags() {
+    for bytes in [
+        &include_bytes!("sponge_v3_extra_root_tag.schem")[..],
+        &include_bytes!("sponge_v3_wrapped.schem")[..],
+    ] {
+        let schem = Schematic::deserialize(bytes).unwrap();
+        assert_eq!(schem.blocks.size(), (2, 2, 2));
+        assert_eq!(schem.paste_offset, Some((1, 0, 1)));
+        assert_eq!(schem.source_format(), Some(SchematicFormat::Sponge(3)));
+    }
+}
+
+#[test]
+fn verify_roundtrip() {
+    let bytes = include_bytes!("sponge_v3_float_entity_pos.schem");
+    let mut schem = Schematic::deserialize(bytes).unwrap();
+    schem.block_entities.insert(
+        (0, 1, 0),
+        BlockEntity::sign(["a", "b", "c", "d"], schem.data_version.unwrap()),
+    );
+    schem.verify_roundtrip(SchematicFormat::Sponge(2)).unwrap();
+    schem.verify_roundtrip(SchematicFormat::Sponge(3)).unwrap();
+    schem
+        .verify_roundtrip(SchematicFormat::Litematica(6))
+        .unwrap();
+
+    // Air aliases are renamed when reading, so they don't survive a round trip
+    schem.blocks.set_block_at(1, 1, 1, "air");
+    assert!(matches!(
+        schem.verify_roundtrip(SchematicFormat::Sponge(3)),
+        Err(SchematicError::RoundTripMismatch(_))
+    ));
 }
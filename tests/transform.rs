@@ -0,0 +1,314 @@
+use mc_schems::{
+    mirror_block_state, other_part_offset, rotate_block_state, BlockEntity, BlockEntityCollision,
+    Blocks, Entity, Mirror, PasteMode, Rotation, Schematic, SchematicError,
+};
+use nbt::Value;
+use std::collections::HashMap;
+
+#[test]
+fn door() {
+    let lower = "minecraft:oak_door[facing=north,half=lower,hinge=left,open=false,powered=false]";
+    let upper = "minecraft:oak_door[facing=north,half=upper,hinge=left,open=false,powered=false]";
+
+    assert_eq!(
+        rotate_block_state(lower, Rotation::Clockwise90),
+        "minecraft:oak_door[facing=east,half=lower,hinge=left,open=false,powered=false]"
+    );
+    assert_eq!(
+        mirror_block_state(upper, Mirror::X),
+        "minecraft:oak_door[facing=north,half=upper,hinge=right,open=false,powered=false]"
+    );
+    assert_eq!(
+        mirror_block_state(upper, Mirror::Z),
+        "minecraft:oak_door[facing=south,half=upper,hinge=right,open=false,powered=false]"
+    );
+
+    let rotated = rotate_block_state(upper, Rotation::CounterClockwise90);
+    assert_eq!(other_part_offset(&rotated), Some((0, -1, 0)));
+    assert_eq!(other_part_offset(lower), Some((0, 1, 0)));
+}
+
+#[test]
+fn bed() {
+    let head = "minecraft:red_bed[facing=east,occupied=false,part=head]";
+    let foot = "minecraft:red_bed[facing=east,occupied=false,part=foot]";
+    assert_eq!(other_part_offset(head), Some((-1, 0, 0)));
+    assert_eq!(other_part_offset(foot), Some((1, 0, 0)));
+
+    let head = rotate_block_state(head, Rotation::Clockwise90);
+    let foot = rotate_block_state(foot, Rotation::Clockwise90);
+    assert_eq!(
+        head,
+        "minecraft:red_bed[facing=south,occupied=false,part=head]"
+    );
+    assert_eq!(other_part_offset(&head), Some((0, 0, -1)));
+    assert_eq!(other_part_offset(&foot), Some((0, 0, 1)));
+
+    let head = mirror_block_state(&head, Mirror::Z);
+    assert_eq!(
+        head,
+        "minecraft:red_bed[facing=north,occupied=false,part=head]"
+    );
+}
+
+#[test]
+fn double_chest() {
+    let left = "minecraft:chest[facing=north,type=left,waterlogged=false]";
+    assert_eq!(other_part_offset(left), Some((1, 0, 0)));
+
+    let mirrored = mirror_block_state(left, Mirror::X);
+    assert_eq!(
+        mirrored,
+        "minecraft:chest[facing=north,type=right,waterlogged=false]"
+    );
+    assert_eq!(other_part_offset(&mirrored), Some((-1, 0, 0)));
+}
+
+#[test]
+fn rails_and_signs() {
+    assert_eq!(
+        rotate_block_state("minecraft:rail[shape=north_east]", Rotation::Clockwise90),
+        "minecraft:rail[shape=south_east]"
+    );
+    assert_eq!(
+        mirror_block_state("minecraft:rail[shape=ascending_east]", Mirror::X),
+        "minecraft:rail[shape=ascending_west]"
+    );
+    assert_eq!(
+        rotate_block_state("minecraft:oak_sign[rotation=14]", Rotation::Clockwise90),
+        "minecraft:oak_sign[rotation=2]"
+    );
+    assert_eq!(
+        mirror_block_state("minecraft:oak_sign[rotation=4]", Mirror::X),
+        "minecraft:oak_sign[rotation=12]"
+    );
+}
+
+#[test]
+fn rotated_schematic() {
+    let mut blocks = Blocks::new(3, 1, 2, "minecraft:air");
+    blocks.set_block_at(0, 0, 0, "minecraft:repeater[delay=1,facing=north]");
+    blocks.set_block_at(2, 0, 1, "minecraft:chest[facing=west,type=single]");
+    let mut block_entities = HashMap::new();
+    block_entities.insert(
+        (2, 0, 1),
+        BlockEntity {
+            id: "minecraft:chest".to_owned(),
+            data: HashMap::new(),
+        },
+    );
+    let mut data = HashMap::new();
+    data.insert("Facing".to_owned(), Value::Byte(2));
+    data.insert(
+        "Rotation".to_owned(),
+        Value::List(vec![Value::Float(180.0), Value::Float(0.0)]),
+    );
+    let entities = vec![Entity {
+        id: "minecraft:item_frame".to_owned(),
+        pos: (0.5, 0.5, 0.0),
+        data,
+    }];
+    let mut schem = Schematic::new(blocks).with_data_version(3700);
+    schem.paste_offset = Some((1, 0, 2));
+    schem.block_entities = block_entities;
+    schem.entities = entities;
+
+    let rotated = schem.rotated(1);
+    assert_eq!(schem.size(), (3, 1, 2));
+    assert_eq!(rotated.size(), (2, 1, 3));
+    assert_eq!(
+        rotated.blocks.get_block_at(1, 0, 0),
+        "minecraft:repeater[delay=1,facing=east]"
+    );
+    assert_eq!(
+        rotated.blocks.get_block_at(0, 0, 2),
+        "minecraft:chest[facing=north,type=single]"
+    );
+    assert!(rotated.block_entities.contains_key(&(0, 0, 2)));
+    assert_eq!(rotated.paste_offset, Some((-3, 0, 1)));
+
+    let frame = &rotated.entities[0];
+    assert_eq!(frame.pos, (2.0, 0.5, 0.5));
+    // North turns east
+    assert!(matches!(frame.data["Facing"], Value::Byte(5)));
+    let Value::List(angles) = &frame.data["Rotation"] else {
+        panic!("missing rotation");
+    };
+    assert!(matches!(angles[0], Value::Float(yaw) if yaw == 270.0));
+
+    // Four quarter turns are a no-op
+    let back = rotated.rotated(3);
+    assert_eq!(back.size(), schem.size());
+    assert_eq!(
+        back.blocks.get_block_at(0, 0, 0),
+        "minecraft:repeater[delay=1,facing=north]"
+    );
+    assert_eq!(back.paste_offset, schem.paste_offset);
+    assert_eq!(back.entities[0].pos, (0.5, 0.5, 0.0));
+}
+
+#[test]
+fn wall_sign() {
+    let mut blocks = Blocks::new(1, 1, 2, "minecraft:air");
+    blocks.set_block_at(
+        0,
+        0,
+        1,
+        "minecraft:oak_wall_sign[facing=north,waterlogged=false]",
+    );
+    let mut schem = Schematic::new(blocks).with_data_version(3700);
+    schem
+        .block_entities
+        .insert((0, 0, 1), BlockEntity::sign(["Hello", "", "", ""], 3700));
+
+    let rotated = schem.rotated(1);
+    assert_eq!(
+        rotated.blocks.get_block_at(0, 0, 0),
+        "minecraft:oak_wall_sign[facing=east,waterlogged=false]"
+    );
+    assert_eq!(rotated.block_entities[&(0, 0, 0)].id, "minecraft:sign");
+
+    schem.mirror(Mirror::Z);
+    assert_eq!(
+        schem.blocks.get_block_at(0, 0, 0),
+        "minecraft:oak_wall_sign[facing=south,waterlogged=false]"
+    );
+    assert!(schem.block_entities.contains_key(&(0, 0, 0)));
+}
+
+#[test]
+fn item_frame() {
+    let mut schem = Schematic::new(Blocks::new(2, 1, 2, "minecraft:air")).with_data_version(3700);
+    for id in ["minecraft:item_frame", "minecraft:painting"] {
+        let mut data = HashMap::new();
+        // North for both the 3D ids of item frames and the 2D ids of old paintings
+        data.insert("Facing".to_owned(), Value::Byte(2));
+        schem.entities.push(Entity {
+            id: id.to_owned(),
+            pos: (0.5, 0.5, 0.03125),
+            data,
+        });
+    }
+
+    let facing = |schem: &Schematic, i: usize| match schem.entities[i].data["Facing"] {
+        Value::Byte(facing) => facing,
+        _ => panic!("mistyped facing"),
+    };
+    let rotated = schem.rotated(1);
+    assert_eq!(facing(&rotated, 0), 5);
+    assert_eq!(facing(&rotated, 1), 3);
+    assert_eq!(rotated.entities[0].pos, (1.96875, 0.5, 0.5));
+
+    let mirrored = schem.mirrored(Mirror::Z);
+    assert_eq!(facing(&mirrored, 0), 3);
+    assert_eq!(facing(&mirrored, 1), 0);
+    assert_eq!(mirrored.entities[0].pos, (0.5, 0.5, 1.96875));
+    assert_eq!(facing(&schem.mirrored(Mirror::X), 0), 2);
+}
+
+#[test]
+fn modern_painting() {
+    let mut schem = Schematic::new(Blocks::new(2, 1, 2, "minecraft:air")).with_data_version(3700);
+    let mut data = HashMap::new();
+    // Paintings since 1.19 store their 2D direction id in `facing`, this is east
+    data.insert("facing".to_owned(), Value::Byte(3));
+    schem.entities.push(Entity {
+        id: "minecraft:painting".to_owned(),
+        pos: (1.96875, 0.5, 0.5),
+        data,
+    });
+
+    let facing = |schem: &Schematic| match schem.entities[0].data["facing"] {
+        Value::Byte(facing) => facing,
+        _ => panic!("mistyped facing"),
+    };
+    assert_eq!(facing(&schem.rotated(1)), 0);
+    assert_eq!(facing(&schem.rotated(3)), 2);
+    assert_eq!(facing(&schem.mirrored(Mirror::X)), 1);
+    assert_eq!(facing(&schem.mirrored(Mirror::Z)), 3);
+}
+
+fn chest_schematic(size: (u32, u32, u32), pos: (u32, u32, u32), name: &str) -> Schematic {
+    let mut blocks = Blocks::new(size.0, size.1, size.2, "minecraft:air");
+    blocks.set_block_at(pos.0, pos.1, pos.2, "minecraft:chest[facing=north]");
+    let mut schem = Schematic::new(blocks).with_data_version(3700);
+    let mut data = HashMap::new();
+    data.insert("CustomName".to_owned(), Value::String(name.to_owned()));
+    schem.block_entities.insert(
+        pos,
+        BlockEntity {
+            id: "minecraft:chest".to_owned(),
+            data,
+        },
+    );
+    schem
+}
+
+#[test]
+fn paste_block_entity_collision() {
+    let target = chest_schematic((3, 1, 3), (1, 0, 1), "existing");
+    let mut other = chest_schematic((2, 1, 2), (0, 0, 0), "pasted");
+    other.blocks.set_block_at(1, 0, 1, "minecraft:stone");
+    let name = |schem: &Schematic| schem.block_entities[&(1, 0, 1)].data["CustomName"].clone();
+
+    let mut schem = target.clone();
+    schem
+        .paste(
+            &other,
+            (1, 0, 1),
+            PasteMode::SkipBackground,
+            BlockEntityCollision::Overwrite,
+        )
+        .unwrap();
+    assert_eq!(name(&schem), Value::String("pasted".to_owned()));
+    assert_eq!(schem.blocks.get_block_at(2, 0, 2), "minecraft:stone");
+
+    let mut schem = target.clone();
+    schem
+        .paste(
+            &other,
+            (1, 0, 1),
+            PasteMode::SkipBackground,
+            BlockEntityCollision::KeepExisting,
+        )
+        .unwrap();
+    assert_eq!(name(&schem), Value::String("existing".to_owned()));
+    assert_eq!(schem.blocks.get_block_at(2, 0, 2), "minecraft:stone");
+
+    let mut schem = target.clone();
+    assert!(matches!(
+        schem.paste(
+            &other,
+            (1, 0, 1),
+            PasteMode::SkipBackground,
+            BlockEntityCollision::Error
+        ),
+        Err(SchematicError::BlockEntityCollision((1, 0, 1)))
+    ));
+    assert_eq!(name(&schem), Value::String("existing".to_owned()));
+    assert_eq!(schem.blocks.get_block_at(2, 0, 2), "minecraft:air");
+
+    // Overwriting the existing chest with stone removes its block entity
+    let mut schem = target.clone();
+    schem
+        .paste(
+            &other,
+            (0, 0, 0),
+            PasteMode::Replace,
+            BlockEntityCollision::Error,
+        )
+        .unwrap();
+    assert_eq!(schem.blocks.get_block_at(1, 0, 1), "minecraft:stone");
+    assert_eq!(schem.block_entities.len(), 1);
+    assert!(schem.block_entities.contains_key(&(0, 0, 0)));
+
+    assert!(matches!(
+        schem.paste(
+            &other,
+            (2, 0, 2),
+            PasteMode::Replace,
+            BlockEntityCollision::Error
+        ),
+        Err(SchematicError::OutOfBounds(_))
+    ));
+}
@@ -0,0 +1,54 @@
+use mc_schems::{read_varint, write_varint, SchematicError};
+
+fn signed(bytes: &[u8]) -> Vec<i8> {
+    bytes.iter().map(|b| *b as i8).collect()
+}
+
+fn round_trip(value: u32, len: usize) {
+    let mut out = Vec::new();
+    write_varint(&mut out, value);
+    assert_eq!(out.len(), len, "length of {value}");
+    let mut pos = 0;
+    assert_eq!(read_varint(&out, &mut pos).unwrap(), value);
+    assert_eq!(pos, len);
+}
+
+#[test]
+fn boundaries() {
+    round_trip(0, 1);
+    round_trip(127, 1);
+    round_trip(128, 2);
+    round_trip(255, 2);
+    round_trip(16383, 2);
+    round_trip(16384, 3);
+    round_trip(u32::MAX, 5);
+
+    let mut out = Vec::new();
+    write_varint(&mut out, 128);
+    assert_eq!(out, [0x80u8 as i8, 0x01]);
+}
+
+#[test]
+fn invalid() {
+    let invalid = |bytes: &[u8]| {
+        let mut pos = 0;
+        matches!(
+            read_varint(&signed(bytes), &mut pos),
+            Err(SchematicError::InvalidValue(_))
+        )
+    };
+    // Runs past the end
+    assert!(invalid(&[0x80]));
+    assert!(invalid(&[]));
+    // Too long
+    assert!(invalid(&[0x80, 0x80, 0x80, 0x80, 0x80, 0x01]));
+    // Doesn't fit in a u32
+    assert!(invalid(&[0xff, 0xff, 0xff, 0xff, 0x1f]));
+
+    let mut pos = 1;
+    assert_eq!(
+        read_varint(&signed(&[0xff, 0x80, 0x01, 0x05]), &mut pos).unwrap(),
+        128
+    );
+    assert_eq!(pos, 3);
+}
@@ -0,0 +1,23 @@
+#![cfg(feature = "zip")]
+
+use mc_schems::{Schematic, SchematicError};
+use std::io::Cursor;
+
+#[test]
+fn from_zip_entry() {
+    let archive = include_bytes!("sponge_v2.zip");
+    let schem =
+        Schematic::from_zip_entry(Cursor::new(archive), "schematics/sponge_v2.schem").unwrap();
+    let expected = Schematic::deserialize(include_bytes!("sponge_v2.schem")).unwrap();
+    assert_eq!(schem.content_hash(), expected.content_hash());
+
+    assert!(matches!(
+        Schematic::from_zip_entry(Cursor::new(archive), "missing.schem"),
+        Err(SchematicError::ZipError(_))
+    ));
+    // Entries which aren't schematics fail like any other data
+    assert!(matches!(
+        Schematic::from_zip_entry(Cursor::new(archive), "readme.txt"),
+        Err(SchematicError::UnrecognizedFormat | SchematicError::NbtError(_))
+    ));
+}